@@ -59,7 +59,10 @@ fn main() {
         println!("cargo:rereun-if-changed={}", s);
     }
 
-    for entry in walkdir::WalkDir::new(root.join("python").join("molt")) {
+    let sources = walkdir::WalkDir::new(root.join("python").join("molt"))
+        .into_iter()
+        .filter_entry(|e| e.file_name() != "__pycache__");
+    for entry in sources {
         let entry = entry.expect("cannot read Python source dir entry");
         let path = entry.path();
         if let Some(_) = find_module_entry(&path) {
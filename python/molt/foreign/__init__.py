@@ -0,0 +1,39 @@
+from __future__ import print_function
+
+import collections
+import contextlib
+import sys
+import warnings
+
+
+def report_warnings(caught, file=None):
+    """Print warnings caught during conversion, one line per kind.
+
+    Warnings about individual packages (those with a `package_name`) are
+    grouped by category, and listed under the category's `summary`.
+    """
+    if file is None:
+        file = sys.stderr
+    grouped = collections.OrderedDict()
+    for w in caught:
+        name = getattr(w.message, "package_name", None)
+        summary = getattr(w.category, "summary", None)
+        if name is None or summary is None:
+            print("warning: {}".format(w.message), file=file)
+            continue
+        grouped.setdefault(summary, []).append(name)
+    for summary, names in grouped.items():
+        print("warning: {}: {}".format(summary, ", ".join(names)), file=file)
+
+
+@contextlib.contextmanager
+def reported_warnings(quiet=False):
+    """Collect warnings in the block, and report them when it ends.
+
+    Nothing is printed if `quiet` is true.
+    """
+    with warnings.catch_warnings(record=True) as caught:
+        warnings.simplefilter("always")
+        yield
+    if not quiet:
+        report_warnings(caught)
@@ -0,0 +1,144 @@
+import collections
+import warnings
+
+import yaml
+
+from packaging.utils import canonicalize_name
+
+from molt.locks import LockFile
+
+
+class CondaLockError(Exception):
+    pass
+
+
+class PlatformNotFound(CondaLockError, ValueError):
+    pass
+
+
+class CondaLockWarning(UserWarning):
+    pass
+
+
+class CondaPackageDropped(CondaLockWarning):
+    summary = "conda packages dropped (not installable from PyPI)"
+
+    def __init__(self, name):
+        super(CondaPackageDropped, self).__init__(
+            "Conda package {!r} dropped (not installable from PyPI)".format(
+                name
+            )
+        )
+        self.package_name = name
+
+
+def load(f, encoding=None):
+    """Parse a conda-lock.yml file.
+
+    If `encoding` is specified, `f` is treated as binary; if `encoding` is
+    not specified or `None`, `f` should be opened in text mode.
+    """
+    text = f.read()
+    if encoding is not None:
+        text = text.decode(encoding)
+    return yaml.safe_load(text)
+
+
+def _select_packages(conda_lock, platform):
+    # A conda lock records packages for each platform separately. Only one
+    # platform can be converted, since a Molt lock has no platform axis.
+    platforms = conda_lock.get("metadata", {}).get("platforms") or []
+    if platform is None:
+        if not platforms:
+            return conda_lock["package"]
+        platform = platforms[0]
+    elif platform not in platforms:
+        raise PlatformNotFound(platform)
+    return [p for p in conda_lock["package"] if p.get("platform") == platform]
+
+
+def _generate_packages(packages):
+    for package_data in packages:
+        name = package_data["name"]
+        # YAML may load versions like "1.0" as numbers.
+        result = {"name": name, "version": str(package_data["version"])}
+        hashes = [
+            "{}:{}".format(algo, value)
+            for algo, value in sorted(package_data.get("hash", {}).items())
+            if algo == "sha256"
+        ]
+        yield canonicalize_name(name), result, hashes
+
+
+def _remove_if_same_section(top_level_packages, dependent, depended_name):
+    try:
+        depended = top_level_packages[depended_name]
+    except KeyError:
+        return
+    # The depended does not need to be a top-level if it's in the same section.
+    # It will be collected when the dependant is traversed.
+    if depended.get("category") == dependent.get("category"):
+        del top_level_packages[depended_name]
+
+
+def _generate_dependencies(packages):
+    keys = {canonicalize_name(p["name"]) for p in packages}
+    top_level_packages = {canonicalize_name(p["name"]): p for p in packages}
+
+    for package_data in packages:
+        package_name = canonicalize_name(package_data["name"])
+        for dep in package_data.get("dependencies") or {}:
+            dep = canonicalize_name(dep)
+            # Dependencies provided by conda packages are dropped with them.
+            if dep not in keys:
+                continue
+            _remove_if_same_section(top_level_packages, package_data, dep)
+            yield package_name, dep
+
+    # A package is a top-level dependency if it is not referenced by anyone.
+    # conda-lock marks every package outside main as optional, so the flag
+    # is not used to drop packages; the category already tells.
+    for package_data in top_level_packages.values():
+        category = package_data.get("category", "main")
+        key = "" if category == "main" else "[{}]".format(category)
+        yield key, canonicalize_name(package_data["name"])
+
+
+def to_lock_file(conda_lock, platform=None):
+    """Convert a conda-lock.yml to a Molt lock file.
+
+    `conda_lock` should be an instance returned by `load()`. Returns an
+    instance of `molt.locks.LockFile`.
+
+    Only packages installed by pip are converted; conda packages are dropped
+    with a warning. If `platform` is not given, the first platform listed in
+    the lock file is used.
+    """
+    packages = []
+    for package_data in _select_packages(conda_lock, platform):
+        if package_data.get("manager") == "pip":
+            packages.append(package_data)
+        else:
+            warnings.warn(CondaPackageDropped(package_data["name"]))
+
+    dependencies = {}
+    hashes = {}
+    for key, result, package_hashes in _generate_packages(packages):
+        dependencies[key] = {"python": result}
+        if package_hashes:
+            hashes[key] = package_hashes
+
+    links = collections.defaultdict(dict)
+    for dependent, depended in _generate_dependencies(packages):
+        links[dependent][depended] = None
+    for key, deps in links.items():
+        dependencies.setdefault(key, {})["dependencies"] = deps
+
+    data = {
+        # conda-lock resolves pip packages against PyPI.
+        "sources": {"pypi": {"url": "https://pypi.org/simple"}},
+        "dependencies": dependencies,
+        "hashes": hashes,
+    }
+
+    return LockFile(data)
@@ -34,6 +34,8 @@ class PipfileLockWarning(UserWarning):
 
 
 class EditablePackageDropped(PipfileLockWarning):
+    summary = "editable packages dropped (not supported yet)"
+
     def __init__(self, name):
         super(EditablePackageDropped, self).__init__(
             "Editable package {!r} dropped".format(name)
@@ -42,6 +44,8 @@ class EditablePackageDropped(PipfileLockWarning):
 
 
 class VCSPackageNotEditable(PipfileLockWarning):
+    summary = "VCS packages converted as non-editable"
+
     def __init__(self, name):
         super(VCSPackageNotEditable, self).__init__(
             "VCS package {!r} converted as non-editable".format(name)
@@ -75,7 +79,12 @@ def _parse_spec(name, package):
         if editable:
             warnings.warn(VCSPackageNotEditable(name))
         vcs, url, rev = vcs_info
-        return {"vcs": "{}+{}".format(vcs, url), "rev": rev}
+        if not url.startswith("{}+".format(vcs)):
+            url = "{}+{}".format(vcs, url)
+        subdirectory = getattr(package, "subdirectory", None)
+        if subdirectory:
+            url = "{}#subdirectory={}".format(url, subdirectory)
+        return {"vcs": url, "rev": rev}
 
     # Other than VCS, people generally specify editable to get its specific
     # behavior. We can't support that yet.
@@ -146,8 +155,9 @@ def _add_dependency(parent, child, marker):
 
 
 def _generate_sources(sources):
-    for source in sources:
-        result = {"url": source.url}
+    # Pipfile lists sources in the order they should be tried.
+    for priority, source in enumerate(sources):
+        result = {"url": source.url, "priority": priority}
         if not source.verify_ssl:
             result["no_verify_ssl"] = True
         yield source["name"], result
@@ -243,6 +253,8 @@ def is_accounted_for(pfl, lock):
             return False
         if source.get("no_verify_ssl", False) != v.get("no_verify_ssl", False):
             return False
+        if source.get("priority") != v["priority"]:
+            return False
 
     # Check all packages in Pipfile.lock have equivalents.
     for info in _generate_packages(pfl.default):
@@ -6,6 +6,7 @@ import tomlkit
 
 from packaging.specifiers import SpecifierSet
 from packaging.utils import canonicalize_name
+from packaging.version import parse as parse_version
 
 from molt.locks import LockFile
 
@@ -23,6 +24,8 @@ class SourceNameDuplicated(PoetryLockError):
 
 
 class SourceDropped(UserWarning):
+    summary = "sources dropped for non-index packages"
+
     def __init__(self, package_name):
         super(SourceDropped, self).__init__(
             "Source dropped for package {!r} (invalid in this context)".format(
@@ -66,10 +69,15 @@ def _parse_spec(package):
         # is confusingly named "url".
         return {"path": source["url"]}
     elif source_type in ["git", "hg", "bzr", "svn"]:
-        return {
-            "vcs": "{}+{}".format(source_type, source["url"]),
-            "rev": source["reference"],
-        }
+        url = source["url"]
+        if not url.startswith("{}+".format(source_type)):
+            url = "{}+{}".format(source_type, url)
+        subdirectory = source.get("subdirectory")
+        if subdirectory:
+            url = "{}#subdirectory={}".format(url, subdirectory)
+        # Newer Poetry records the commit a branch or tag resolved to.
+        rev = source.get("resolved_reference") or source["reference"]
+        return {"vcs": url, "rev": rev}
 
     try:
         version = package["version"]
@@ -154,15 +162,76 @@ def _generate_dependencies(poetry_lock):
             yield "[{}]".format(extra_name), dep, packages_markers.get(dep)
 
 
-def to_lock_file(poetry_lock):
+_SDIST_EXTENSIONS = [".tar.gz", ".tar.bz2", ".tar.xz", ".zip", ".tgz"]
+
+
+def _parse_filename(filename):
+    """Parse name and version from an artifact's file name.
+
+    Returns `None` if the file name is not recognized.
+    """
+    if filename.endswith(".whl"):
+        parts = filename[: -len(".whl")].split("-")
+        if len(parts) < 5:
+            return None
+        return parts[0], parts[1]
+    for ext in _SDIST_EXTENSIONS:
+        if filename.endswith(ext):
+            parts = filename[: -len(ext)].rsplit("-", 1)
+            if len(parts) != 2:
+                return None
+            return parts[0], parts[1]
+    return None
+
+
+def _matches_versions(filename, name, versions):
+    parsed = _parse_filename(filename)
+    if parsed is None:
+        return True  # Don't know what this is; keep it to be safe.
+    file_name, file_version = parsed
+    if canonicalize_name(file_name) != canonicalize_name(name):
+        return False
+    return any(parse_version(file_version) == v for v in versions)
+
+
+def _generate_hashes(poetry_lock, dedupe):
+    metadata = poetry_lock["metadata"]
+
+    # Older Poetry records a flat hash list per package, without file names,
+    # so it is impossible to tell which artifacts they belong to.
+    if "files" not in metadata:
+        for name, hashes in metadata["hashes"].items():
+            yield name, ["sha256:{}".format(h) for h in hashes]
+        return
+
+    versions = collections.defaultdict(set)
+    for package_data in poetry_lock["package"]:
+        key = canonicalize_name(package_data["name"])
+        versions[key].add(parse_version(package_data["version"]))
+
+    for name, files in metadata["files"].items():
+        key = canonicalize_name(name)
+        yield name, [
+            entry["hash"]
+            for entry in files
+            if not dedupe
+            or _matches_versions(entry["file"], name, versions[key])
+        ]
+
+
+def to_lock_file(poetry_lock, dedupe_hashes=False):
     """Convert a poetry.lock to a Molt lock file.
 
     `poetry_lock` should be an instance returned by `load()`. Returns an
     instance of `molt.locks.LockFile`.
+
+    If `dedupe_hashes` is true, hashes of artifacts not matching the pinned
+    version are dropped. This is only possible if the poetry.lock records
+    file names alongside hashes.
     """
     hashes = {
-        canonicalize_name(k): sorted("sha256:{}".format(h) for h in v)
-        for k, v in poetry_lock["metadata"]["hashes"].items()
+        canonicalize_name(k): sorted(v)
+        for k, v in _generate_hashes(poetry_lock, dedupe_hashes)
         if v  # Poetry produces an empty list for non-hash-required packages.
     }
 
@@ -173,9 +242,18 @@ def to_lock_file(poetry_lock):
     # Generate sources and packages information in depenency entries.
     for key, result, src in _generate_packages(poetry_lock):
         if src is not None:
-            if src.name in sources and sources[src.name]["url"] != src.url:
+            if src.name not in sources:
+                # Poetry does not record priority, so sources are tried in
+                # the order they are first seen. Poetry keeps PyPI around
+                # for packages without a source, so these are only extra
+                # indexes for them.
+                sources[src.name] = {
+                    "url": src.url,
+                    "priority": len(sources),
+                    "extra": True,
+                }
+            elif sources[src.name]["url"] != src.url:
                 raise SourceNameDuplicated(src.name)
-            sources[src.name] = {"url": src.url}
 
         # If there are no duplicates, good, insert by the package name.
         if key not in aliases:
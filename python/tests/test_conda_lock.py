@@ -0,0 +1,108 @@
+import pytest
+
+import io
+import warnings
+
+import molt.foreign.conda_lock
+
+from molt.foreign.conda_lock import CondaPackageDropped, PlatformNotFound
+
+
+_CONDA_LOCK = u"""
+version: 1
+metadata:
+  platforms:
+  - linux-64
+  - osx-64
+package:
+- name: numpy
+  version: 1.24.0
+  manager: conda
+  platform: linux-64
+  dependencies:
+    python: '>=3.8'
+  hash:
+    md5: '0000'
+  category: main
+  optional: false
+- name: requests
+  version: 2.28.1
+  manager: pip
+  platform: linux-64
+  dependencies:
+    certifi: '>=2017.4.17'
+    numpy: '*'
+  hash:
+    sha256: aaa
+  category: main
+  optional: false
+- name: certifi
+  version: 2022.12.7
+  manager: pip
+  platform: linux-64
+  dependencies: {}
+  hash:
+    sha256: bbb
+  category: main
+  optional: false
+- name: pytest
+  version: 7.2.0
+  manager: pip
+  platform: linux-64
+  dependencies: {}
+  hash:
+    sha256: ccc
+  category: dev
+  optional: true
+- name: requests
+  version: 2.27.0
+  manager: pip
+  platform: osx-64
+  dependencies: {}
+  hash:
+    sha256: ddd
+  category: main
+  optional: false
+"""
+
+
+def test_to_lock_file():
+    conda_lock = molt.foreign.conda_lock.load(io.StringIO(_CONDA_LOCK))
+
+    with warnings.catch_warnings(record=True) as w:
+        warnings.simplefilter("always", category=CondaPackageDropped)
+        lock = molt.foreign.conda_lock.to_lock_file(conda_lock)
+        assert [m.message.package_name for m in w] == ["numpy"]
+
+    assert lock._data == {
+        "sources": {"pypi": {"url": "https://pypi.org/simple"}},
+        "dependencies": {
+            "": {"dependencies": {"requests": None}},
+            "[dev]": {"dependencies": {"pytest": None}},
+            "certifi": {
+                "python": {"name": "certifi", "version": "2022.12.7"}
+            },
+            "pytest": {"python": {"name": "pytest", "version": "7.2.0"}},
+            "requests": {
+                "python": {"name": "requests", "version": "2.28.1"},
+                "dependencies": {"certifi": None},
+            },
+        },
+        "hashes": {
+            "certifi": ["sha256:bbb"],
+            "pytest": ["sha256:ccc"],
+            "requests": ["sha256:aaa"],
+        },
+    }
+
+
+def test_to_lock_file_platform():
+    conda_lock = molt.foreign.conda_lock.load(io.StringIO(_CONDA_LOCK))
+    lock = molt.foreign.conda_lock.to_lock_file(conda_lock, platform="osx-64")
+    assert lock._data["dependencies"]["requests"]["python"] == {
+        "name": "requests",
+        "version": "2.27.0",
+    }
+
+    with pytest.raises(PlatformNotFound):
+        molt.foreign.conda_lock.to_lock_file(conda_lock, platform="win-64")
@@ -0,0 +1,27 @@
+import warnings
+
+import six
+
+import molt.foreign
+
+from molt.foreign.pipfile_lock import (
+    EditablePackageDropped,
+    VCSPackageNotEditable,
+)
+
+
+def test_report_warnings():
+    with warnings.catch_warnings(record=True) as caught:
+        warnings.simplefilter("always")
+        warnings.warn(EditablePackageDropped("foo"))
+        warnings.warn(VCSPackageNotEditable("bar"))
+        warnings.warn(EditablePackageDropped("baz"))
+        warnings.warn(UserWarning("something else"))
+
+    f = six.StringIO()
+    molt.foreign.report_warnings(caught, f)
+    assert f.getvalue().splitlines() == [
+        "warning: something else",
+        "warning: editable packages dropped (not supported yet): foo, baz",
+        "warning: VCS packages converted as non-editable: bar",
+    ]
@@ -68,3 +68,37 @@ def test_is_accounted_for(example_name):
     with warnings.catch_warnings():
         warnings.simplefilter("ignore", category=PipfileLockWarning)
         assert molt.foreign.pipfile_lock.is_accounted_for(pipfile_lock, lock)
+
+
+_VCS_PIPFILE_LOCK = {
+    "_meta": {
+        "hash": {"sha256": "0000"},
+        "pipfile-spec": 6,
+        "requires": {},
+        "sources": [
+            {
+                "name": "pypi",
+                "url": "https://pypi.org/simple",
+                "verify_ssl": True,
+            }
+        ],
+    },
+    "default": {
+        "foo": {
+            "git": "https://example.com/foo.git",
+            "ref": "abc123",
+            "subdirectory": "src",
+        }
+    },
+    "develop": {},
+}
+
+
+def test_to_lock_file_vcs():
+    pipfile_lock = plette.Lockfile(_VCS_PIPFILE_LOCK)
+    lock = molt.foreign.pipfile_lock.to_lock_file(pipfile_lock)
+    assert lock._data["dependencies"]["foo"]["python"] == {
+        "name": "foo",
+        "vcs": "git+https://example.com/foo.git#subdirectory=src",
+        "rev": "abc123",
+    }
@@ -4,6 +4,8 @@ import io
 import json
 import os
 
+import tomlkit
+
 import molt.foreign.poetry_lock
 
 from _testcommons import SAMPLES_ROOT
@@ -35,3 +37,144 @@ def test_is_accounted_for(example_name):
         lock = molt.locks.LockFile.load(f)
 
     assert molt.foreign.poetry_lock.is_accounted_for(poetry_lock, lock)
+
+
+_FILES_POETRY_LOCK = """
+[[package]]
+category = "main"
+description = ""
+name = "six"
+optional = false
+python-versions = "*"
+version = "1.12.0"
+
+[metadata]
+content-hash = "0000"
+python-versions = "*"
+
+[metadata.files]
+six = [
+    {file = "six-1.12.0-py2.py3-none-any.whl", hash = "sha256:aaa"},
+    {file = "six-1.12.0.tar.gz", hash = "sha256:bbb"},
+    {file = "six-1.11.0-py2.py3-none-any.whl", hash = "sha256:ccc"},
+    {file = "other-1.12.0.tar.gz", hash = "sha256:ddd"},
+]
+"""
+
+
+@pytest.mark.parametrize(
+    "dedupe, expected",
+    [
+        (False, ["sha256:aaa", "sha256:bbb", "sha256:ccc", "sha256:ddd"]),
+        (True, ["sha256:aaa", "sha256:bbb"]),
+    ],
+)
+def test_to_lock_file_dedupe_hashes(dedupe, expected):
+    poetry_lock = tomlkit.parse(_FILES_POETRY_LOCK)
+    lock = molt.foreign.poetry_lock.to_lock_file(
+        poetry_lock, dedupe_hashes=dedupe
+    )
+    assert lock.hashes == {"six": expected}
+
+
+_SOURCES_POETRY_LOCK = """
+[[package]]
+category = "main"
+description = ""
+name = "foo"
+optional = false
+python-versions = "*"
+version = "1.0"
+
+[package.source]
+reference = "private"
+type = "legacy"
+url = "https://example.com/simple"
+
+[[package]]
+category = "main"
+description = ""
+name = "bar"
+optional = false
+python-versions = "*"
+version = "2.0"
+
+[package.source]
+reference = "mirror"
+type = "legacy"
+url = "https://mirror.example.com/simple"
+
+[[package]]
+category = "main"
+description = ""
+name = "baz"
+optional = false
+python-versions = "*"
+version = "3.0"
+
+[package.source]
+reference = "private"
+type = "legacy"
+url = "https://example.com/simple"
+
+[metadata]
+content-hash = "0000"
+python-versions = "*"
+
+[metadata.hashes]
+bar = []
+baz = []
+foo = []
+"""
+
+
+def test_to_lock_file_source_priority():
+    poetry_lock = tomlkit.parse(_SOURCES_POETRY_LOCK)
+    lock = molt.foreign.poetry_lock.to_lock_file(poetry_lock)
+    assert lock._data["sources"] == {
+        "private": {
+            "url": "https://example.com/simple",
+            "priority": 0,
+            "extra": True,
+        },
+        "mirror": {
+            "url": "https://mirror.example.com/simple",
+            "priority": 1,
+            "extra": True,
+        },
+    }
+
+
+_VCS_POETRY_LOCK = """
+[[package]]
+category = "main"
+description = ""
+name = "foo"
+optional = false
+python-versions = "*"
+version = "1.0"
+
+[package.source]
+reference = "master"
+resolved_reference = "abc123"
+subdirectory = "src"
+type = "git"
+url = "https://example.com/foo.git"
+
+[metadata]
+content-hash = "0000"
+python-versions = "*"
+
+[metadata.hashes]
+foo = []
+"""
+
+
+def test_to_lock_file_vcs():
+    poetry_lock = tomlkit.parse(_VCS_POETRY_LOCK)
+    lock = molt.foreign.poetry_lock.to_lock_file(poetry_lock)
+    assert lock._data["dependencies"]["foo"]["python"] == {
+        "name": "foo",
+        "vcs": "git+https://example.com/foo.git#subdirectory=src",
+        "rev": "abc123",
+    }
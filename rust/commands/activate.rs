@@ -0,0 +1,143 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use clap::ArgMatches;
+
+use crate::projects::{Layout, Project};
+use crate::pythons::Interpreter;
+use super::Result;
+
+/// Quote a string literal in a shell's syntax.
+fn quote(shell: &str, s: &str) -> String {
+    match shell {
+        "fish" => {
+            format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+        },
+        "powershell" => format!("'{}'", s.replace('\'', "''")),
+        _ => format!("'{}'", s.replace('\'', "'\\''")),
+    }
+}
+
+/// Write a script activating the environment in a shell.
+///
+/// Like running a command in the environment, this sets `VIRTUAL_ENV` and
+/// puts site-packages in front of the user's `PYTHONPATH`. The environment's
+/// bin directory is also prepended to `PATH`, so its scripts can be invoked
+/// directly.
+fn write_script<W>(
+    shell: &str,
+    env_root: &Path,
+    bindir: &Path,
+    site_packages: &Path,
+    mut writer: W,
+) -> io::Result<()>
+    where W: Write
+{
+    let env_root = quote(shell, &env_root.to_string_lossy());
+    let bindir = quote(shell, &bindir.to_string_lossy());
+    let site_packages = quote(shell, &site_packages.to_string_lossy());
+    match shell {
+        "fish" => {
+            writeln!(writer, "set -gx VIRTUAL_ENV {}", env_root)?;
+            writeln!(writer, "set -gx PATH {} $PATH", bindir)?;
+            writeln!(
+                writer,
+                "set -gx PYTHONPATH {} $PYTHONPATH",
+                site_packages,
+            )?;
+        },
+        "powershell" => {
+            let sep = "[IO.Path]::PathSeparator";
+            writeln!(writer, "$env:VIRTUAL_ENV = {}", env_root)?;
+            writeln!(writer, "$env:PATH = {} + {} + $env:PATH", bindir, sep)?;
+            writeln!(
+                writer,
+                "$env:PYTHONPATH = {} + $(if ($env:PYTHONPATH) {{ {} + \
+                 $env:PYTHONPATH }})",
+                site_packages,
+                sep,
+            )?;
+        },
+        _ => {
+            writeln!(writer, "export VIRTUAL_ENV={}", env_root)?;
+            writeln!(writer, "export PATH={}:\"$PATH\"", bindir)?;
+            writeln!(
+                writer,
+                "export PYTHONPATH={}\"${{PYTHONPATH:+:$PYTHONPATH}}\"",
+                site_packages,
+            )?;
+        },
+    }
+    Ok(())
+}
+
+pub struct Command<'a> {
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Command<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Self { matches }
+    }
+
+    fn shell(&self) -> &str {
+        self.matches.value_of("shell").expect("required")
+    }
+
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+    ) -> Result<()> {
+        let project = Project::find_in_cwd(interpreter, layout)?;
+        let stdout = io::stdout();
+        write_script(
+            self.shell(),
+            &project.env_root()?,
+            &project.bindir()?,
+            &project.site_packages()?,
+            stdout.lock(),
+        )?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use unindent::unindent;
+
+    fn script(shell: &str) -> String {
+        let mut buf = vec![];
+        write_script(
+            shell,
+            Path::new("/p/env"),
+            Path::new("/p/env/bin"),
+            Path::new("/p/it's"),
+            &mut buf,
+        ).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_write_script() {
+        assert_eq!(script("bash"), unindent(r#"
+            export VIRTUAL_ENV='/p/env'
+            export PATH='/p/env/bin':"$PATH"
+            export PYTHONPATH='/p/it'\''s'"${PYTHONPATH:+:$PYTHONPATH}"
+        "#));
+        assert_eq!(script("zsh"), script("bash"));
+        assert_eq!(script("fish"), unindent(r#"
+            set -gx VIRTUAL_ENV '/p/env'
+            set -gx PATH '/p/env/bin' $PATH
+            set -gx PYTHONPATH '/p/it\'s' $PYTHONPATH
+        "#));
+        assert_eq!(script("powershell"), unindent(r#"
+            $env:VIRTUAL_ENV = '/p/env'
+            $env:PATH = '/p/env/bin' + [IO.Path]::PathSeparator + $env:PATH
+            $env:PYTHONPATH = '/p/it''s' + $(if ($env:PYTHONPATH) { SEP })
+        "#).replace("SEP", "[IO.Path]::PathSeparator + $env:PYTHONPATH"));
+    }
+}
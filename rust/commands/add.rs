@@ -0,0 +1,37 @@
+use clap::ArgMatches;
+
+use crate::locking;
+use crate::projects::{Layout, Project};
+use crate::pythons::Interpreter;
+use crate::requirements::{RequirementsFile, REQUIREMENTS_IN};
+use super::Result;
+
+pub struct Command<'a> {
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Command<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Self { matches }
+    }
+
+    fn specs(&self) -> Vec<&str> {
+        self.matches.values_of("specs").expect("required").collect()
+    }
+
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+    ) -> Result<()> {
+        let project = Project::find_in_cwd(interpreter, layout)?;
+        let path = project.root().join(REQUIREMENTS_IN);
+        let mut requirements = RequirementsFile::load(&path)?;
+        for spec in self.specs() {
+            requirements.add(spec)?;
+        }
+        requirements.save(&path)?;
+        locking::lock(&project)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use clap::ArgMatches;
+
+use crate::projects::{Layout, Project};
+use crate::pythons::Interpreter;
+use super::{Error, Result};
+
+/// Whether `env` is an environment directly inside `pypackages`.
+///
+/// This guards against removing anything else if the project root or the
+/// compatibility tag is misconfigured.
+fn is_env_dir(env: &Path, pypackages: &Path) -> bool {
+    env.file_name().is_some() && env.parent() == Some(pypackages)
+}
+
+/// Read a yes/no answer. Anything other than "y" or "yes" is a no.
+fn confirmed<R: BufRead>(mut reader: R) -> io::Result<bool> {
+    let mut answer = String::new();
+    reader.read_line(&mut answer)?;
+    let answer = answer.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
+pub struct Command<'a> {
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Command<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Self { matches }
+    }
+
+    fn yes(&self) -> bool {
+        self.matches.is_present("yes")
+    }
+
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+    ) -> Result<()> {
+        let project = Project::find_in_cwd(interpreter, layout)?;
+        let env = project.presumed_env_root()?;
+        let pypackages = layout.pypackages_in(project.root());
+        if !is_env_dir(&env, &pypackages) {
+            return Err(Error::EnvironmentOutsideProject(env));
+        }
+        if !env.exists() {
+            return Ok(());
+        }
+        if !self.yes() {
+            eprint!("Remove {}? [y/N] ", env.display());
+            io::stderr().flush()?;
+            let stdin = io::stdin();
+            if !confirmed(stdin.lock())? {
+                eprintln!("Environment not removed");
+                return Ok(());
+            }
+        }
+        fs::remove_dir_all(&env)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_env_dir() {
+        let pypackages = Path::new("/project/__pypackages__");
+        assert!(is_env_dir(&pypackages.join("cp37"), pypackages));
+        assert!(!is_env_dir(&pypackages.join(".."), pypackages));
+        assert!(!is_env_dir(&pypackages.join("cp37/lib"), pypackages));
+        assert!(!is_env_dir(Path::new("/elsewhere/cp37"), pypackages));
+    }
+
+    #[test]
+    fn test_confirmed() {
+        assert!(confirmed(&b"y\n"[..]).unwrap());
+        assert!(confirmed(&b" Yes \n"[..]).unwrap());
+        assert!(!confirmed(&b"\n"[..]).unwrap());
+        assert!(!confirmed(&b"nope\n"[..]).unwrap());
+        assert!(!confirmed(&b""[..]).unwrap());
+    }
+}
@@ -1,9 +1,10 @@
 use std::{fmt, io};
+use std::path::PathBuf;
 
-use clap::{App, AppSettings, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgGroup, SubCommand};
 use which::which;
 
-use crate::{projects, pythons, sync};
+use crate::{locking, outputs, projects, pythons, requirements, sync};
 
 pub fn app<'a, 'b>() -> App<'a, 'b> {
     let py_available = which("py").is_ok();
@@ -13,11 +14,53 @@ pub fn app<'a, 'b>() -> App<'a, 'b> {
         .setting(AppSettings::VersionlessSubcommands)
         .arg(Arg::with_name("py")
             .long("py")
-            .help("Python interpreter to use")
-            .required(true)
+            .help("Python interpreter to use (or set MOLT_PYTHON)")
+            .env("MOLT_PYTHON")
             .takes_value(true)
             .allow_hyphen_values(py_available)
         )
+        .arg(Arg::with_name("no_interpreter_cache")
+            .long("no-interpreter-cache")
+            .help("Do not cache interpreter probes (or set MOLT_NO_CACHE)")
+        )
+        .arg(Arg::with_name("env_dir")
+            .long("env-dir")
+            .value_name("DIR")
+            .help("Environment directory to use instead of __pypackages__")
+            .takes_value(true)
+        )
+        .arg(Arg::with_name("pypackages")
+            .long("pypackages")
+            .value_name("DIR")
+            .help("Directory used as __pypackages__ (or set MOLT_PYPACKAGES)")
+            .env("MOLT_PYPACKAGES")
+            .takes_value(true)
+        )
+        .arg(Arg::with_name("tag_granularity")
+            .long("tag-granularity")
+            .help("How much of the compatibility tag names the environment \
+                   (or set MOLT_TAG_GRANULARITY)")
+            .env("MOLT_TAG_GRANULARITY")
+            .takes_value(true)
+            .possible_values(pythons::TAG_GRANULARITY_CHOICES)
+        )
+        .arg(Arg::with_name("color")
+            .long("color")
+            .help("When to color output (or set FORCE_COLOR or NO_COLOR)")
+            .takes_value(true)
+            .possible_values(outputs::COLOR_CHOICES)
+        )
+        .arg(Arg::with_name("quiet")
+            .long("quiet")
+            .short("q")
+            .help("Suppress output from pip")
+            .conflicts_with("verbose")
+        )
+        .arg(Arg::with_name("verbose")
+            .long("verbose")
+            .short("v")
+            .help("Make pip verbose, and show commands run")
+        )
         .subcommand(SubCommand::with_name("show")
             .about("Print project information")
             .setting(AppSettings::ArgRequiredElseHelp)
@@ -25,6 +68,44 @@ pub fn app<'a, 'b>() -> App<'a, 'b> {
                 .long("env")
                 .help("Path to the environment")
             )
+            .arg(Arg::with_name("site_packages")
+                .long("site-packages")
+                .help("Path to the environment's site-packages directory")
+            )
+            .arg(Arg::with_name("python")
+                .long("python")
+                .help("Path to the Python interpreter")
+            )
+            .arg(Arg::with_name("bin")
+                .long("bin")
+                .help("Path to the environment's executables directory")
+            )
+            .arg(Arg::with_name("tags")
+                .long("tags")
+                .help("Compatibility tags of the interpreter, in order")
+            )
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("All of the above as JSON, or --lock-stats as JSON")
+            )
+            .arg(Arg::with_name("lock_stats")
+                .long("lock-stats")
+                .help("Summary of the lock file (combine with --json)")
+                .conflicts_with_all(&[
+                    "env", "site_packages", "python", "bin", "tags",
+                    "outdated",
+                ])
+            )
+            .arg(Arg::with_name("outdated")
+                .long("outdated")
+                .help("Locked packages with newer versions on the index")
+            )
+            .group(ArgGroup::with_name("what")
+                .args(&[
+                    "env", "site_packages", "python", "bin", "tags", "json",
+                ])
+                .arg("outdated")
+            )
         )
         .subcommand(SubCommand::with_name("init")
             .about("Initialize an environment for project")
@@ -32,37 +113,179 @@ pub fn app<'a, 'b>() -> App<'a, 'b> {
                 .help("Path to project root directory")
                 .required(true)
             )
+            .arg(Arg::with_name("prompt")
+                .long("prompt")
+                .help("Prompt prefix of the environment")
+                .takes_value(true)
+                .value_name("NAME")
+            )
+            .arg(Arg::with_name("force")
+                .long("force")
+                .help("Re-initialize even if the environment already exists")
+            )
+            .arg(Arg::with_name("copies")
+                .long("copies")
+                .help("Copy interpreter binaries instead of symlinking")
+            )
+            .arg(Arg::with_name("system_site_packages")
+                .long("system-site-packages")
+                .help("Make the base interpreter's site-packages visible")
+            )
+            .arg(Arg::with_name("with_empty_lock")
+                .long("with-empty-lock")
+                .help("Create an empty lock file if there is none")
+            )
         )
         .subcommand(SubCommand::with_name("sync")
             .about("Synchronize environment with locked project dependencies")
             .arg(Arg::with_name("no_default")
                 .long("--no-default")
                 .help("Do no install the default section")
-                .requires("extras")
+                .requires("extra_sections")
             )
             .arg(Arg::with_name("extras")
                 .long("--with")
-                .help("Extra sections to install")
+                .help("Extra sections to install (case-sensitive)")
+                .value_delimiter(",")
+            )
+            .arg(Arg::with_name("all_extras")
+                .long("all-extras")
+                .help("Install all extra sections in the lock file")
+            )
+            .group(ArgGroup::with_name("extra_sections")
+                .args(&["extras", "all_extras"])
+            )
+            .arg(Arg::with_name("only")
+                .long("only")
+                .help("Install only these packages and their dependencies")
                 .value_delimiter(",")
+                .conflicts_with_all(&["no_default", "extras", "all_extras"])
+            )
+            .arg(Arg::with_name("group_output")
+                .long("group-output")
+                .help("Install packages from each source in one pip run")
+            )
+            .arg(Arg::with_name("parallel_downloads")
+                .long("parallel-downloads")
+                .help("Download packages in parallel before installing them")
+                .conflicts_with("group_output")
+            )
+            .arg(Arg::with_name("ignore_installed")
+                .long("ignore-installed")
+                .help("Reinstall packages even if they are already installed")
+            )
+            .arg(Arg::with_name("find_links")
+                .long("find-links")
+                .help("Also look for packages in this directory")
+                .takes_value(true)
+                .value_name("DIR")
+                .multiple(true)
+                .number_of_values(1)
+            )
+            .arg(Arg::with_name("offline")
+                .long("offline")
+                .help("Install only from --find-links, without any index")
+                .requires("find_links")
+            )
+            .arg(Arg::with_name("cache_dir")
+                .long("cache-dir")
+                .help("Directory for pip's cache (or set MOLT_CACHE_DIR)")
+                .takes_value(true)
+                .value_name("DIR")
+            )
+            .arg(Arg::with_name("no_cache")
+                .long("no-cache")
+                .help("Disable pip's cache")
+                .conflicts_with("cache_dir")
+            )
+            .arg(Arg::with_name("summary")
+                .long("summary")
+                .help("Print counts of installed and removed packages")
+            )
+            .arg(Arg::with_name("dry_run")
+                .long("dry-run")
+                .help("Print packages to install without installing them")
+                .conflicts_with_all(&[
+                    "freeze_output",
+                    "rollback_on_check_failure",
+                ])
+            )
+            .arg(Arg::with_name("rollback_on_check_failure")
+                .long("rollback-on-check-failure")
+                .help("Remove added packages if any is missing, mismatches, \
+                       or fails to import")
+            )
+            .arg(Arg::with_name("freeze_output")
+                .long("freeze-output")
+                .help("Write a lock file pinned to installed versions")
+                .takes_value(true)
+                .value_name("PATH")
             )
         )
         .subcommand(SubCommand::with_name("run")
             .about("Run a command in the environment")
             .setting(AppSettings::AllowLeadingHyphen)
             .setting(AppSettings::DisableHelpFlags)
+            .setting(AppSettings::TrailingVarArg)
+            .arg(Arg::with_name("tty")
+                .long("tty")
+                .help("Attach the command to a pseudo-terminal (Unix only)")
+            )
+            .arg(Arg::with_name("extra_pythonpath")
+                .long("extra-pythonpath")
+                .help("Also import from this directory (before the \
+                       environment)")
+                .takes_value(true)
+                .value_name("DIR")
+                .multiple(true)
+                .number_of_values(1)
+            )
             .arg(Arg::with_name("command")
-                .help("Command to run")
+                .help("Command to run, followed by its arguments \
+                       (or -m <module> to run a module)")
                 .required(true)
-            )
-            .arg(Arg::with_name("args")
-                .help("Arguments to command")
                 .multiple(true)
             )
         )
+        .subcommand(SubCommand::with_name("clean")
+            .about("Remove the environment of the interpreter")
+            .arg(Arg::with_name("yes")
+                .long("yes")
+                .short("y")
+                .help("Do not ask for confirmation")
+            )
+        )
+        .subcommand(SubCommand::with_name("which")
+            .about("Print the call target of a command without running it")
+            .arg(Arg::with_name("command")
+                .help("Command to look up")
+                .required(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("activate")
+            .about("Print a script to activate the environment in a shell")
+            .arg(Arg::with_name("shell")
+                .long("shell")
+                .help("Shell to generate the script for")
+                .takes_value(true)
+                .required(true)
+                .possible_values(&["bash", "zsh", "fish", "powershell"])
+            )
+        )
         .subcommand(SubCommand::with_name("py")
             .about("Run the Python interpreter in the environment")
             .setting(AppSettings::AllowLeadingHyphen)
             .setting(AppSettings::DisableHelpFlags)
+            .setting(AppSettings::TrailingVarArg)
+            .arg(Arg::with_name("extra_pythonpath")
+                .long("extra-pythonpath")
+                .help("Also import from this directory (before the \
+                       environment)")
+                .takes_value(true)
+                .value_name("DIR")
+                .multiple(true)
+                .number_of_values(1)
+            )
             .arg(Arg::with_name("args")
                 .help("Arguments to interpreter")
                 .multiple(true)
@@ -70,6 +293,98 @@ pub fn app<'a, 'b>() -> App<'a, 'b> {
         )
         .subcommand(SubCommand::with_name("convert")
             .about("Convert a foreign lock file format to molt.lock.json")
+            .arg(Arg::with_name("dedupe_hashes")
+                .long("dedupe-hashes")
+                .help("Drop hashes not matching the pinned versions")
+            )
+            .arg(Arg::with_name("from")
+                .long("from")
+                .help("Foreign lock file to convert, in Pipfile.lock, \
+                       poetry.lock, conda-lock.yml, or requirements.txt \
+                       format (default: found in project root)")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("to")
+                .long("to")
+                .help("Where to write the lock file (default: \
+                       molt.lock.json in project root)")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("force")
+                .long("force")
+                .help("Overwrite the lock file if it already exists")
+            )
+        )
+        .subcommand(SubCommand::with_name("export")
+            .about("Write locked project dependencies as requirements.txt")
+            .arg(Arg::with_name("no_default")
+                .long("no-default")
+                .help("Do no export the default section")
+                .requires("extras")
+            )
+            .arg(Arg::with_name("extras")
+                .long("with")
+                .help("Extra sections to export")
+                .value_delimiter(",")
+            )
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("Write to file instead of stdout")
+                .takes_value(true)
+                .value_name("PATH")
+            )
+        )
+        .subcommand(SubCommand::with_name("list")
+            .about("List packages installed in the environment")
+            .arg(Arg::with_name("format")
+                .long("format")
+                .help("Output format")
+                .takes_value(true)
+                .possible_values(&["table", "csv"])
+                .default_value("table")
+            )
+        )
+        .subcommand(SubCommand::with_name("add")
+            .about("Add requirements to requirements.in, and update the lock")
+            .arg(Arg::with_name("specs")
+                .help("Requirements to add, e.g. \"requests>=2.0\"")
+                .required(true)
+                .multiple(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("remove")
+            .about("Remove requirements from requirements.in, and update \
+                    the lock")
+            .arg(Arg::with_name("names")
+                .help("Names of packages to remove")
+                .required(true)
+                .multiple(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("lock")
+            .about("Resolve project requirements into molt.lock.json")
+        )
+        .subcommand(SubCommand::with_name("graph")
+            .about("Print the locked dependency graph")
+            .arg(Arg::with_name("format")
+                .long("format")
+                .help("Output format")
+                .takes_value(true)
+                .possible_values(&["dot"])
+                .default_value("dot")
+            )
+        )
+        .subcommand(SubCommand::with_name("tree")
+            .about("Print locked dependencies as a tree")
+            .arg(Arg::with_name("extras")
+                .long("with")
+                .help("Extra sections to print")
+                .value_delimiter(",")
+            )
+        )
+        .subcommand(SubCommand::with_name("validate")
+            .about("Check the lock file's dependency graph (no Python needed)")
         )
         .subcommand(SubCommand::with_name("pip-install")
             .about("Secret subcommand to install things into the environment")
@@ -86,35 +401,87 @@ pub fn app<'a, 'b>() -> App<'a, 'b> {
 #[derive(Debug)]
 pub enum Error {
     ConvertError(i32),
+    DependencyCycle(Vec<String>),
+    EnvironmentExists(PathBuf),
+    EnvironmentOutsideProject(PathBuf),
     InterpreterError(pythons::Error),
+    InterpreterNotSpecified,
+    LockError(locking::Error),
+    LockFileExists(PathBuf),
+    ModuleNotSpecified,
+    MultipleRecordedInterpreters(Vec<String>),
     ProjectError(projects::Error),
+    RequirementsError(requirements::Error),
+    SectionNotFound(String),
     SubCommandMissing,
     SubprocessExit(i32),
     SyncError(sync::Error),
     SystemError(io::Error),
+    UnrecognizedListOption(String),
     UnrecognizedSubcommand(String),
 }
 
+// Exit codes from sysexits.h, used for the low 8 bits of molt's own errors.
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_NOINPUT: i32 = 66;
+const EX_UNAVAILABLE: i32 = 69;
+const EX_SOFTWARE: i32 = 70;
+const EX_OSERR: i32 = 71;
+const EX_CANTCREAT: i32 = 73;
+const EX_CONFIG: i32 = 78;
+
 impl Error {
+    /// Exit status of molt when the error occurs.
+    ///
+    /// A subprocess's exit code is passed through as-is, except an
+    /// interrupted sync, which exits with the conventional 130.
+    ///
+    /// On Unix only the lowest 8 bits of the status are kept, so molt's own
+    /// errors can't be told apart from every possible child exit code. They
+    /// use the sysexits range (64-78) there, which Python processes rarely
+    /// return, and "command not found" is 127, as in shells. The higher bits
+    /// keep each error distinct on platforms that keep the full status.
     pub fn status(&self) -> i32 {
         match *self {
             // Bridged error from subprocess.
             Error::SubprocessExit(v) => v,
 
+            // Conventional status for termination by SIGINT.
+            Error::SyncError(sync::Error::Interrupted) => 130,
+
             // General command errors.
-            Error::ConvertError(_) => 1,
-            Error::SyncError(_) => 2,
+            Error::ConvertError(_) => 0x20_00_01_00 | EX_DATAERR,
+            Error::SyncError(_) => 0x20_00_02_00 | EX_UNAVAILABLE,
+            Error::EnvironmentExists(_) => 0x20_00_03_00 | EX_CANTCREAT,
+            Error::UnrecognizedListOption(_) => 0x20_00_04_00 | EX_USAGE,
+            Error::LockError(_) => 0x20_00_05_00 | EX_UNAVAILABLE,
+            Error::SectionNotFound(_) => 0x20_00_06_00 | EX_DATAERR,
+            Error::RequirementsError(_) => 0x20_00_07_00 | EX_DATAERR,
+            Error::DependencyCycle(_) => 0x20_00_08_00 | EX_DATAERR,
+            Error::InterpreterNotSpecified => 0x20_00_09_00 | EX_USAGE,
+            Error::MultipleRecordedInterpreters(_) => {
+                0x20_00_0a_00 | EX_USAGE
+            },
+            Error::LockFileExists(_) => 0x20_00_0b_00 | EX_CANTCREAT,
+            Error::ModuleNotSpecified => 0x20_00_0c_00 | EX_USAGE,
+            Error::EnvironmentOutsideProject(_) => 0x20_00_0d_00 | EX_USAGE,
+
+            // Nothing to run.
+            Error::ProjectError(projects::Error::CommandNotFoundError(_)) => {
+                0x10_00_02_7f
+            },
 
             // Can't run without a project ._.
-            Error::ProjectError(_) => 0x10_00_00_01,
+            Error::ProjectError(_) => 0x10_00_01_00 | EX_NOINPUT,
 
             // Shouldn't happen unless there's a bug in Clap.
-            Error::SubCommandMissing => 0x60_00_00_01,
-            Error::UnrecognizedSubcommand(_) => 0x60_00_00_02,
+            Error::SubCommandMissing => 0x60_00_01_00 | EX_SOFTWARE,
+            Error::UnrecognizedSubcommand(_) => 0x60_00_02_00 | EX_SOFTWARE,
 
             // Something is very wrong in the user's runtime environment.
-            Error::InterpreterError(_) => 0x70_00_00_01,
-            Error::SystemError(_) => 0x70_00_00_02,
+            Error::InterpreterError(_) => 0x70_00_01_00 | EX_CONFIG,
+            Error::SystemError(_) => 0x70_00_02_00 | EX_OSERR,
         }
     }
 }
@@ -125,14 +492,49 @@ impl fmt::Display for Error {
             Error::ConvertError(c) => {
                 write!(f, "conversion failed with error {}", c)
             },
+            Error::DependencyCycle(ref keys) => {
+                write!(f, "dependency cycle found: {}", keys.join(" -> "))
+            },
+            Error::EnvironmentExists(ref p) => {
+                write!(f, "environment exists at {:?} (use --force to \
+                           re-initialize)", p)
+            },
+            Error::EnvironmentOutsideProject(ref p) => {
+                write!(f, "refusing to remove {:?} (not in the project's \
+                           __pypackages__)", p)
+            },
             Error::InterpreterError(ref e) => e.fmt(f),
+            Error::InterpreterNotSpecified => {
+                write!(f, "no interpreter given (use --py or MOLT_PYTHON)")
+            },
+            Error::LockError(ref e) => e.fmt(f),
+            Error::LockFileExists(ref p) => {
+                write!(f, "lock file exists at {:?} (use --force to \
+                           overwrite)", p)
+            },
+            Error::ModuleNotSpecified => {
+                write!(f, "missing module name after -m")
+            },
+            Error::MultipleRecordedInterpreters(ref tags) => {
+                write!(
+                    f, "multiple environments found ({}), use --py to choose",
+                    tags.join(", "),
+                )
+            },
             Error::ProjectError(ref e) => e.fmt(f),
+            Error::RequirementsError(ref e) => e.fmt(f),
+            Error::SectionNotFound(ref s) => {
+                write!(f, "section {} not found in lock file", s)
+            },
             Error::SubCommandMissing => write!(f, "missing subcommand"),
             Error::SubprocessExit(c) => {
                 write!(f, "process exited with status code {}", c)
             },
             Error::SyncError(ref e) => e.fmt(f),
             Error::SystemError(ref e) => e.fmt(f),
+            Error::UnrecognizedListOption(ref n) => {
+                write!(f, "unrecognized option {:?} for --list", n)
+            },
             Error::UnrecognizedSubcommand(ref n) => {
                 write!(f, "unhandled subcommand {:?}", n)
             },
@@ -146,6 +548,12 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<locking::Error> for Error {
+    fn from(e: locking::Error) -> Self {
+        Error::LockError(e)
+    }
+}
+
 impl From<projects::Error> for Error {
     fn from(e: projects::Error) -> Self {
         Error::ProjectError(e)
@@ -158,6 +566,12 @@ impl From<pythons::Error> for Error {
     }
 }
 
+impl From<requirements::Error> for Error {
+    fn from(e: requirements::Error) -> Self {
+        Error::RequirementsError(e)
+    }
+}
+
 impl From<sync::Error> for Error {
     fn from(e: sync::Error) -> Self {
         Error::SyncError(e)
@@ -165,3 +579,253 @@ impl From<sync::Error> for Error {
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status() {
+        assert_eq!(Error::SubprocessExit(0).status(), 0);
+        assert_eq!(Error::SubprocessExit(3).status(), 3);
+        assert_eq!(Error::SubprocessExit(255).status(), 255);
+        assert_eq!(Error::SyncError(sync::Error::Interrupted).status(), 130);
+
+        let not_found = Error::ProjectError(
+            projects::Error::CommandNotFoundError(String::from("foo")),
+        );
+        assert_eq!(not_found.status() & 0xff, 127);
+
+        let errors = vec![
+            not_found,
+            Error::ConvertError(1),
+            Error::DependencyCycle(vec![]),
+            Error::EnvironmentExists(PathBuf::new()),
+            Error::EnvironmentOutsideProject(PathBuf::new()),
+            Error::InterpreterError(
+                pythons::Error::IncompatibleInterpreterError(String::new()),
+            ),
+            Error::InterpreterNotSpecified,
+            Error::LockError(locking::Error::ResolveCommandError(None)),
+            Error::LockFileExists(PathBuf::new()),
+            Error::ModuleNotSpecified,
+            Error::MultipleRecordedInterpreters(vec![]),
+            Error::ProjectError(
+                projects::Error::ProjectNotFoundError(PathBuf::new()),
+            ),
+            Error::RequirementsError(
+                requirements::Error::RequirementNotFoundError(String::new()),
+            ),
+            Error::SectionNotFound(String::new()),
+            Error::SubCommandMissing,
+            Error::SyncError(sync::Error::DefaultSectionNotFound),
+            Error::SystemError(io::Error::from(io::ErrorKind::Other)),
+            Error::UnrecognizedListOption(String::new()),
+            Error::UnrecognizedSubcommand(String::new()),
+        ];
+        let mut seen = std::collections::HashSet::new();
+        for (i, e) in errors.into_iter().enumerate() {
+            let status = e.status();
+            assert!(status > 255, "{:?} has status {}", e, status);
+            assert!(seen.insert(status), "{:?} status {} reused", e, status);
+
+            // What's left on Unix, for all but not_found (checked above).
+            if i > 0 {
+                let code = status & 0xff;
+                assert!((64..=78).contains(&code), "{:?} exits {}", e, code);
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_tty_before_command() {
+        let matches = app().get_matches_from_safe(vec![
+            "molt", "--py", "python", "run", "--tty", "foo", "--tty", "-x",
+        ]).unwrap();
+        let matches = matches.subcommand_matches("run").unwrap();
+        assert!(matches.is_present("tty"));
+        assert_eq!(
+            matches.values_of("command").unwrap().collect::<Vec<_>>(),
+            vec!["foo", "--tty", "-x"],
+        );
+    }
+
+    #[test]
+    fn test_py_args_passthrough() {
+        let cases: &[&[&str]] = &[
+            &["-c", "print(1)"],
+            &["-m", "venv", "-h"],
+            &["--version"],
+            &["-h"],
+            &["script.py", "--flag", "--py", "x", "-v"],
+            &["-", "--help"],
+            &["-c", "import sys; print(sys.argv)", "--", "-x"],
+            &["script.py", "--", "--flag"],
+        ];
+        for args in cases {
+            let mut argv = vec!["molt", "--py", "python3", "py"];
+            argv.extend(args.iter());
+            let matches = app().get_matches_from_safe(argv).unwrap();
+            assert_eq!(matches.value_of("py"), Some("python3"));
+            let matches = matches.subcommand_matches("py").unwrap();
+            assert_eq!(
+                matches.values_of("args").unwrap().collect::<Vec<_>>(),
+                args.to_vec(),
+            );
+        }
+
+        // A leading "--" separates molt's arguments from the interpreter's.
+        let matches = app().get_matches_from_safe(vec![
+            "molt", "--py", "python3", "py", "--", "-c", "print(1)",
+        ]).unwrap();
+        let matches = matches.subcommand_matches("py").unwrap();
+        assert_eq!(
+            matches.values_of("args").unwrap().collect::<Vec<_>>(),
+            vec!["-c", "print(1)"],
+        );
+    }
+
+    #[test]
+    fn test_run_args_passthrough() {
+        let matches = app().get_matches_from_safe(vec![
+            "molt", "--py", "python", "run", "mytool", "a", "b", "-c",
+        ]).unwrap();
+        let matches = matches.subcommand_matches("run").unwrap();
+        assert_eq!(
+            matches.values_of("command").unwrap().collect::<Vec<_>>(),
+            vec!["mytool", "a", "b", "-c"],
+        );
+    }
+
+    #[test]
+    fn test_extra_pythonpath() {
+        for (name, rest) in &[("run", "command"), ("py", "args")] {
+            let matches = app().get_matches_from_safe(vec![
+                "molt", "--py", "python", name,
+                "--extra-pythonpath", "src", "--extra-pythonpath", "lib",
+                "foo", "--extra-pythonpath", "x",
+            ]).unwrap();
+            let matches = matches.subcommand_matches(name).unwrap();
+            assert_eq!(
+                matches.values_of("extra_pythonpath").unwrap()
+                    .collect::<Vec<_>>(),
+                vec!["src", "lib"],
+            );
+            assert_eq!(
+                matches.values_of(rest).unwrap().collect::<Vec<_>>(),
+                vec!["foo", "--extra-pythonpath", "x"],
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_module() {
+        let matches = app().get_matches_from_safe(vec![
+            "molt", "--py", "python", "run", "-m", "http.server", "--bind",
+            "::1",
+        ]).unwrap();
+        let matches = matches.subcommand_matches("run").unwrap();
+        assert_eq!(
+            matches.values_of("command").unwrap().collect::<Vec<_>>(),
+            vec!["-m", "http.server", "--bind", "::1"],
+        );
+    }
+
+    #[test]
+    fn test_sync_all_extras() {
+        let parse = |args: &[&str]| {
+            let mut argv = vec!["molt", "--py", "python", "sync"];
+            argv.extend_from_slice(args);
+            app().get_matches_from_safe(argv)
+        };
+        assert!(parse(&["--no-default", "--all-extras"]).is_ok());
+        assert!(parse(&["--no-default", "--with", "dev"]).is_ok());
+        assert!(parse(&["--no-default"]).is_err());
+        assert!(parse(&["--with", "dev", "--all-extras"]).is_err());
+        assert!(parse(&["--only", "six", "--all-extras"]).is_err());
+    }
+
+    #[test]
+    fn test_validate_without_py() {
+        let matches = app().get_matches_from_safe(vec!["molt", "validate"])
+            .unwrap();
+        assert!(matches.value_of("py").is_none());
+        assert_eq!(matches.subcommand_name(), Some("validate"));
+    }
+
+    #[test]
+    fn test_show_options_exclusive() {
+        let result = app().get_matches_from_safe(vec![
+            "molt", "--py", "python", "show", "--env", "--bin",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_env_dir_with_show_env() {
+        let matches = app().get_matches_from_safe(vec![
+            "molt", "--py", "python", "--env-dir", "venv", "show", "--env",
+        ]).unwrap();
+        assert_eq!(matches.value_of("env_dir"), Some("venv"));
+        let show = matches.subcommand_matches("show").unwrap();
+        assert!(show.is_present("env"));
+    }
+
+    #[test]
+    fn test_sync_offline_requires_find_links() {
+        let result = app().get_matches_from_safe(vec![
+            "molt", "--py", "python", "sync", "--offline",
+        ]);
+        assert!(result.is_err());
+
+        let matches = app().get_matches_from_safe(vec![
+            "molt", "--py", "python", "sync", "--offline",
+            "--find-links", "a", "--find-links", "b",
+        ]).unwrap();
+        let sync = matches.subcommand_matches("sync").unwrap();
+        let dirs: Vec<_> = sync.values_of("find_links").unwrap().collect();
+        assert_eq!(dirs, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_sync_no_cache_cache_dir_exclusive() {
+        let result = app().get_matches_from_safe(vec![
+            "molt", "--py", "python", "sync", "--no-cache",
+            "--cache-dir", "cache",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_quiet_verbose_exclusive() {
+        let result = app().get_matches_from_safe(vec![
+            "molt", "--py", "python", "-q", "-v", "sync",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_show_outdated_exclusive() {
+        for other in &["--json", "--lock-stats"] {
+            let result = app().get_matches_from_safe(vec![
+                "molt", "--py", "python", "show", "--outdated", other,
+            ]);
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn test_show_lock_stats_json() {
+        let matches = app().get_matches_from_safe(vec![
+            "molt", "--py", "python", "show", "--lock-stats", "--json",
+        ]).unwrap();
+        let matches = matches.subcommand_matches("show").unwrap();
+        assert!(matches.is_present("lock_stats"));
+        assert!(matches.is_present("json"));
+
+        let result = app().get_matches_from_safe(vec![
+            "molt", "--py", "python", "show", "--lock-stats", "--env",
+        ]);
+        assert!(result.is_err());
+    }
+}
@@ -1,21 +1,51 @@
+use std::path::Path;
+
 use clap::ArgMatches;
 
-use crate::projects::Project;
+use crate::projects::{Layout, Project};
 use crate::pythons::Interpreter;
 use super::{Error, Result};
 
 pub struct Command<'a> {
-    _matches: &'a ArgMatches<'a>,
+    matches: &'a ArgMatches<'a>,
 }
 
 impl<'a> Command<'a> {
-    pub fn new(_matches: &'a ArgMatches) -> Self {
-        Self { _matches }
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Self { matches }
+    }
+
+    fn dedupe_hashes(&self) -> bool {
+        self.matches.is_present("dedupe_hashes")
+    }
+
+    fn force(&self) -> bool {
+        self.matches.is_present("force")
     }
 
-    pub fn run(&self, interpreter: Interpreter) -> Result<()> {
-        let project = Project::find_in_cwd(interpreter)?;
-        let code = project.convert_foreign_lock()?;
+    fn from(&self) -> Option<&Path> {
+        self.matches.value_of_os("from").map(Path::new)
+    }
+
+    fn to(&self) -> Option<&Path> {
+        self.matches.value_of_os("to").map(Path::new)
+    }
+
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+    ) -> Result<()> {
+        let project = Project::find_in_cwd(interpreter, layout)?;
+        let to = self.to().map_or_else(|| project.lock_path(), Path::to_owned);
+        if to.exists() && !self.force() {
+            return Err(Error::LockFileExists(to));
+        }
+        let code = project.convert_foreign_lock(
+            self.from(),
+            Some(&to),
+            self.dedupe_hashes(),
+        )?;
         if code == 0 {
             Ok(())
         } else {
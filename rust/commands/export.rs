@@ -0,0 +1,54 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use clap::{ArgMatches, Values};
+
+use crate::projects::{Layout, Project};
+use crate::pythons::Interpreter;
+use crate::sync::Synchronizer;
+use super::Result;
+
+pub struct Command<'a> {
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Command<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Self { matches }
+    }
+
+    fn default(&self) -> bool {
+        !self.matches.is_present("no_default")
+    }
+
+    fn extras(&self) -> Values {
+        self.matches.values_of("extras").unwrap_or_default()
+    }
+
+    fn output(&self) -> Option<&Path> {
+        self.matches.value_of_os("output").map(Path::new)
+    }
+
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+    ) -> Result<()> {
+        let project = Project::find_in_cwd(interpreter, layout)?;
+        let sync = Synchronizer::new(project.read_lock_file()?)?;
+        let interpreter = project.base_interpreter();
+        match self.output() {
+            Some(path) => {
+                let writer = BufWriter::new(File::create(path)?);
+                sync.export(interpreter, self.default(), self.extras(), writer)
+            },
+            None => {
+                let stdout = io::stdout();
+                let writer = stdout.lock();
+                sync.export(interpreter, self.default(), self.extras(), writer)
+            },
+        }?;
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use std::env;
+use std::io::{self, Write};
+
+use clap::ArgMatches;
+
+use crate::lockfiles::{Lock, Marker};
+use crate::projects::{self, Layout, LOCK_FILE_NAME};
+use super::Result;
+
+/// Quote a string as a DOT identifier.
+fn dot_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Write the dependency graph in Graphviz's DOT format.
+///
+/// Each entry in the lock file is a node, labeled with its package and
+/// version, or section name. Each dependency is an edge, labeled with its
+/// markers if there are any. Nodes and edges are sorted by key.
+fn write_dot<W>(lock: &Lock, mut writer: W) -> io::Result<()>
+    where W: Write
+{
+    let mut deps: Vec<_> = lock.dependencies().iter().collect();
+    deps.sort_by(|a, b| a.0.cmp(b.0));
+
+    writeln!(writer, "digraph dependencies {{")?;
+    for (key, dep) in deps.iter() {
+        let label = match dep.python() {
+            Some(python) => format!(
+                "{} {}", python.name(), python.specifier_display(),
+            ),
+            None if key.is_empty() => String::from("(default)"),
+            None => key.to_string(),
+        };
+        writeln!(
+            writer, "    {} [label={}];",
+            dot_quote(key), dot_quote(&label),
+        )?;
+    }
+    for (key, dep) in deps.iter() {
+        let mut children: Vec<_> = dep.dependencies().collect();
+        children.sort_by(|a, b| a.0.key().cmp(b.0.key()));
+        for (child, marker) in children {
+            write!(
+                writer, "    {} -> {}",
+                dot_quote(key), dot_quote(child.key()),
+            )?;
+            if let Some(marker) = marker.map(Marker::to_string) {
+                write!(writer, " [label={}]", dot_quote(&marker))?;
+            }
+            writeln!(writer, ";")?;
+        }
+    }
+    writeln!(writer, "}}")?;
+    Ok(())
+}
+
+pub struct Command<'a> {
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Command<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Self { matches }
+    }
+
+    fn format(&self) -> &str {
+        self.matches.value_of("format").expect("has default")
+    }
+
+    pub fn run(&self, layout: &Layout) -> Result<()> {
+        let (root, _) = projects::find_root(&env::current_dir()?, layout)?;
+        let lock = projects::read_lock_file(&root.join(LOCK_FILE_NAME))?;
+        match self.format() {
+            "dot" => {
+                let stdout = io::stdout();
+                write_dot(&lock, stdout.lock())?;
+            },
+            f => unreachable!("unknown graph format {:?}", f),
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::from_str;
+    use unindent::unindent;
+
+    #[test]
+    fn test_write_dot() {
+        let lock: Lock = from_str(r#"{
+            "dependencies": {
+                "": {"dependencies": {"a": null, "c": ["os_name == 'nt'"]}},
+                "[dev]": {"dependencies": {"c": null}},
+                "a": {
+                    "python": {"name": "A", "version": "1.0"},
+                    "dependencies": {
+                        "b": ["os_name == \"nt\"", "os_name == 'x'"]
+                    }
+                },
+                "b": {"python": {"name": "b", "version": "2.0"}},
+                "c": {"python": {"name": "c", "url": "https://c.org/c.zip"}}
+            }
+        }"#).unwrap();
+
+        let mut buf = vec![];
+        write_dot(&lock, &mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), unindent(r#"
+            digraph dependencies {
+                "" [label="(default)"];
+                "[dev]" [label="[dev]"];
+                "a" [label="A == 1.0"];
+                "b" [label="b == 2.0"];
+                "c" [label="c https://c.org/c.zip"];
+                "" -> "a";
+                "" -> "c" [label="os_name == 'nt'"];
+                "[dev]" -> "c";
+                "a" -> "b" [label="(os_name == \"nt\") or (os_name == 'x')"];
+            }
+        "#));
+    }
+}
@@ -1,9 +1,28 @@
-use std::path::PathBuf;
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
 
 use clap::ArgMatches;
 
+use crate::lockfiles::Lock;
+use crate::projects::{self, InterpreterRecord, Layout};
 use crate::pythons::Interpreter;
-use super::Result;
+use super::{Error, Result};
+
+/// Write an empty lock file to `path`, unless a file already exists there.
+///
+/// Returns whether the file is written.
+fn write_empty_lock(path: &Path) -> io::Result<bool> {
+    let f = match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(f) => f,
+        Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            return Ok(false);
+        },
+        Err(e) => { return Err(e); },
+    };
+    Lock::empty().write(BufWriter::new(f))?;
+    Ok(true)
+}
 
 pub struct Command<'a> {
     matches: &'a ArgMatches<'a>,
@@ -14,23 +33,104 @@ impl<'a> Command<'a> {
         Self { matches }
     }
 
-    fn project_root(&self) -> PathBuf {
-        PathBuf::from(self.matches.value_of("project").expect("required"))
+    /// The project root to initialize.
+    ///
+    /// If the given path is inside an existing project (one that has
+    /// `__pypackages__` or a project marker file), that project's root is
+    /// used. Otherwise the given path itself is the root.
+    fn project_root(&self, layout: &Layout) -> PathBuf {
+        let p = PathBuf::from(
+            self.matches.value_of("project").expect("required"),
+        );
+        match projects::find_root(&p, layout) {
+            Ok((root, _)) => root,
+            Err(_) => p,
+        }
     }
 
-    fn project_name(&self) -> Option<String> {
-        let root = self.project_root();
+    fn project_name(&self, layout: &Layout) -> Option<String> {
+        let root = self.project_root(layout);
         let root = root.canonicalize().unwrap_or(root);
         root.file_name().map(|n| n.to_string_lossy().into_owned())
     }
 
-    pub fn run(&self, interpreter: Interpreter) -> Result<()> {
-        let envdir = self.project_root()
-            .join("__pypackages__")
-            .join(interpreter.compatibility_tag()?);
-        let prompt = self.project_name()
-            .unwrap_or_else(|| String::from("venv"));
-        interpreter.create_venv(&envdir, &prompt)?;
+    fn prompt(&self, layout: &Layout) -> String {
+        if let Some(p) = self.matches.value_of("prompt") {
+            return p.to_string();
+        }
+        self.project_name(layout).unwrap_or_else(|| String::from("venv"))
+    }
+
+    fn force(&self) -> bool {
+        self.matches.is_present("force")
+    }
+
+    fn copies(&self) -> bool {
+        self.matches.is_present("copies")
+    }
+
+    fn system_site_packages(&self) -> bool {
+        self.matches.is_present("system_site_packages")
+    }
+
+    fn with_empty_lock(&self) -> bool {
+        self.matches.is_present("with_empty_lock")
+    }
+
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+    ) -> Result<()> {
+        let root = self.project_root(layout);
+        let pypackages = layout.pypackages_in(&root);
+        let envdir = interpreter.presumed_env_root(&pypackages)?;
+        if envdir.exists() && !self.force() {
+            return Err(Error::EnvironmentExists(envdir));
+        }
+        interpreter.create_venv(
+            &envdir,
+            &self.prompt(layout),
+            self.copies(),
+            self.system_site_packages(),
+        )?;
+        InterpreterRecord {
+            path: interpreter.location().to_path_buf(),
+            version: interpreter.probe()?.version().to_string(),
+            root: Some(dunce::canonicalize(&root).unwrap_or_else(|_| {
+                root.clone()
+            })),
+        }.write(&envdir)?;
+        if self.with_empty_lock() {
+            write_empty_lock(&root.join(projects::LOCK_FILE_NAME))?;
+        }
         Ok(())
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{read_to_string, write};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_empty_lock() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join(projects::LOCK_FILE_NAME);
+
+        assert!(write_empty_lock(&path).unwrap());
+        let lock: Lock = serde_json::from_str(
+            &read_to_string(&path).unwrap(),
+        ).unwrap();
+        assert!(lock.dependencies().default().is_some());
+        assert_eq!(lock.dependencies().iter().count(), 1);
+        assert!(lock.sources().get("pypi").is_some());
+
+        write(&path, "{}").unwrap();
+        assert!(!write_empty_lock(&path).unwrap());
+        assert_eq!(read_to_string(&path).unwrap(), "{}");
+    }
+}
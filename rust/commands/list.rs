@@ -0,0 +1,118 @@
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+use clap::ArgMatches;
+use prettytable::format::consts::FORMAT_CLEAN;
+
+use crate::distributions::Distribution;
+use crate::projects::{Layout, Project};
+use crate::pythons::Interpreter;
+use super::Result;
+
+/// Quote a CSV field if needed, as described in RFC 4180.
+fn csv_field(v: &str) -> Cow<'_, str> {
+    if v.contains(&[',', '"', '\r', '\n'][..]) {
+        Cow::Owned(format!("\"{}\"", v.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(v)
+    }
+}
+
+fn write_csv<W>(dists: &[Distribution], mut writer: W) -> io::Result<()>
+    where W: Write
+{
+    writeln!(writer, "name,version,location")?;
+    for d in dists {
+        let location = d.location().to_string_lossy();
+        writeln!(
+            writer, "{},{},{}",
+            csv_field(d.name()), csv_field(d.version()), csv_field(&location),
+        )?;
+    }
+    Ok(())
+}
+
+fn print_table(distributions: &[Distribution]) {
+    let mut table = table![["Name", "Version"]];
+    for d in distributions {
+        table.add_row(row![d.name(), d.version()]);
+    }
+    table.set_format(*FORMAT_CLEAN);
+    table.printstd();
+}
+
+pub struct Command<'a> {
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Command<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Self { matches }
+    }
+
+    fn format(&self) -> &str {
+        self.matches.value_of("format").expect("has default")
+    }
+
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+    ) -> Result<()> {
+        let project = Project::find_in_cwd(interpreter, layout)?;
+        let mut distributions = project.installed_distributions()?;
+        distributions.sort_by_key(Distribution::canonical_name);
+        match self.format() {
+            "csv" => {
+                let stdout = io::stdout();
+                write_csv(&distributions, stdout.lock())?;
+            },
+            _ => { print_table(&distributions); },
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir, write};
+    use tempfile::TempDir;
+
+    use crate::distributions;
+
+    #[test]
+    fn test_write_csv() {
+        let site_packages = TempDir::new().unwrap();
+        for (name, version, dirname) in &[
+            ("foo", "1.0", "foo-1.0.dist-info"),
+            ("Bar, \"Baz\"", "2.0", "Bar_Baz-2.0.dist-info"),
+        ] {
+            let dist_info = site_packages.path().join(dirname);
+            create_dir(&dist_info).unwrap();
+            write(
+                dist_info.join("METADATA"),
+                format!("Name: {}\nVersion: {}\n", name, version),
+            ).unwrap();
+        }
+        let mut distributions = distributions::find(site_packages.path())
+            .unwrap();
+        distributions.sort_by_key(Distribution::canonical_name);
+
+        let mut buf = vec![];
+        write_csv(&distributions, &mut buf).unwrap();
+        let location = |dirname: &str| {
+            let p = site_packages.path().join(dirname);
+            csv_field(&p.to_string_lossy()).into_owned()
+        };
+        assert_eq!(String::from_utf8(buf).unwrap(), format!(
+            "name,version,location\n\
+             \"Bar, \"\"Baz\"\"\",2.0,{}\n\
+             foo,1.0,{}\n",
+            location("Bar_Baz-2.0.dist-info"),
+            location("foo-1.0.dist-info"),
+        ));
+    }
+}
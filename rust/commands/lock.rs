@@ -0,0 +1,26 @@
+use clap::ArgMatches;
+
+use crate::locking;
+use crate::projects::{Layout, Project};
+use crate::pythons::Interpreter;
+use super::Result;
+
+pub struct Command<'a> {
+    _matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Command<'a> {
+    pub fn new(_matches: &'a ArgMatches) -> Self {
+        Self { _matches }
+    }
+
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+    ) -> Result<()> {
+        let project = Project::find_in_cwd(interpreter, layout)?;
+        locking::lock(&project)?;
+        Ok(())
+    }
+}
@@ -1,50 +1,235 @@
+mod activate;
+mod add;
+mod clean;
 mod cmd;
 mod convert;
+mod export;
+mod graph;
 mod init;
+mod list;
+mod lock;
 mod pip_install;
+mod remove;
 mod py;
 mod run;
 mod show;
 mod sync;
+mod tree;
+mod validate;
+mod which;
 
 pub use self::cmd::{Error, Result};
 
+use std::env;
+use std::path::Path;
+
 use clap::ArgMatches;
+use crate::{outputs, projects};
+use crate::projects::{Layout, Project};
 use crate::pythons::{self, Interpreter};
 
 macro_rules! subcommand {
-    ($matches:expr, $module:ident) => {
+    ($matches:expr, $layout:expr, $module:ident) => {
         {
-            let interpreter = discover_interpreter(&$matches)?;
+            let interpreter = discover_interpreter(&$matches, &$layout)?;
             let n = stringify!($module).replace('_', "-");
             let matches = $matches.subcommand_matches(&n).unwrap();
-            $module::Command::new(matches).run(interpreter)
+            $module::Command::new(matches).run(interpreter, &$layout)
         }
     };
+    ($matches:expr, $layout:expr, $module:ident, $verbosity:expr) => {
+        {
+            let interpreter = discover_interpreter(&$matches, &$layout)?;
+            if $verbosity == outputs::Verbosity::Verbose {
+                print_interpreter(&interpreter)?;
+            }
+            let n = stringify!($module).replace('_', "-");
+            let matches = $matches.subcommand_matches(&n).unwrap();
+            $module::Command::new(matches)
+                .run(interpreter, &$layout, $verbosity)
+        }
+    };
+}
+
+/// Find the interpreter recorded in the project's environment.
+///
+/// This is only done for commands working on an existing environment, and
+/// only if there is exactly one recorded interpreter.
+fn recorded_interpreter(
+    matches: &ArgMatches,
+    layout: &Layout,
+) -> Result<String> {
+    match matches.subcommand_name() {
+        Some("clean") | Some("py") | Some("run") | Some("show")
+                | Some("sync") | Some("which") => {},
+        _ => { return Err(Error::InterpreterNotSpecified); },
+    }
+    let (root, _) = projects::find_root(&env::current_dir()?, layout)?;
+    recorded_interpreter_in(&root, layout)
 }
 
-fn discover_interpreter<'a>(matches: &'a ArgMatches) -> Result<Interpreter> {
-    let py = matches.value_of("py").expect("required");
+/// The only interpreter recorded in environments of the project at `root`.
+///
+/// This is not a `Project` method since a project can't be found without
+/// an interpreter, which is what this looks for.
+fn recorded_interpreter_in(root: &Path, layout: &Layout) -> Result<String> {
+    let mut records = projects::recorded_interpreters(root, layout)?;
+    match records.len() {
+        0 => Err(Error::InterpreterNotSpecified),
+        1 => {
+            let (_, record) = records.remove(0);
+            Ok(record.path.to_string_lossy().into_owned())
+        },
+        _ => Err(Error::MultipleRecordedInterpreters(
+            records.into_iter().map(|(tag, _)| tag).collect(),
+        )),
+    }
+}
+
+fn discover_interpreter<'a>(
+    matches: &'a ArgMatches,
+    layout: &Layout,
+) -> Result<Interpreter> {
+    let py = match matches.value_of("py") {
+        Some(py) => py.to_string(),
+        None => recorded_interpreter(matches, layout)?,
+    };
+    let py = py.as_str();
     let (prog, args) = if py.starts_with('-') {
         ("py", vec![py])
     } else {
         (py, vec![])
     };
-    pythons::Interpreter::discover(py, prog, args).map_err(Error::from)
+    let mut interpreter = pythons::Interpreter::discover(py, prog, args)?;
+    if matches.is_present("no_interpreter_cache")
+            || env::var_os("MOLT_NO_CACHE").is_some() {
+        interpreter.disable_caching();
+    }
+    if let Some(name) = matches.value_of("tag_granularity") {
+        let granularity = pythons::TagGranularity::from_name(name)
+            .expect("value should be validated by clap");
+        interpreter.set_tag_granularity(granularity);
+    }
+    Ok(interpreter)
+}
+
+/// Print the interpreter chosen, to help debug "wrong environment" issues.
+fn print_interpreter(interpreter: &Interpreter) -> Result<()> {
+    eprintln!("interpreter: {}", interpreter.location().display());
+    eprintln!("compatibility tag: {}", interpreter.compatibility_tag()?);
+    Ok(())
+}
+
+/// Print the environment a command works on, if running verbosely.
+fn print_env_root(
+    project: &Project,
+    verbosity: outputs::Verbosity,
+) -> Result<()> {
+    if verbosity == outputs::Verbosity::Verbose {
+        let env = project.presumed_env_root()?;
+        eprintln!("environment: {}", env.display());
+    }
+    Ok(())
 }
 
 pub fn dispatch() -> Result<()> {
     let matches = cmd::app().get_matches();
+    outputs::init_color(matches.value_of("color"));
+    // The environment is given relative to the current directory, not the
+    // project root.
+    let env_dir = match matches.value_of_os("env_dir") {
+        Some(p) => Some(env::current_dir()?.join(p)),
+        None => None,
+    };
+    let layout = Layout::new(
+        matches.value_of_os("pypackages").map(Path::new),
+        env_dir.as_deref(),
+    );
+    let verbosity = outputs::Verbosity::from_flags(
+        matches.is_present("quiet"),
+        matches.is_present("verbose"),
+    );
     match matches.subcommand_name() {
-        Some("convert") => subcommand!(matches, convert),
-        Some("init") => subcommand!(matches, init),
-        Some("py") => subcommand!(matches, py),
-        Some("run") => subcommand!(matches, run),
-        Some("show") => subcommand!(matches, show),
-        Some("sync") => subcommand!(matches, sync),
-
-        Some("pip-install") => subcommand!(matches, pip_install),
+        Some("activate") => subcommand!(matches, layout, activate),
+        Some("add") => subcommand!(matches, layout, add),
+        Some("clean") => subcommand!(matches, layout, clean),
+        Some("convert") => subcommand!(matches, layout, convert),
+        Some("export") => subcommand!(matches, layout, export),
+        Some("init") => subcommand!(matches, layout, init),
+        Some("list") => subcommand!(matches, layout, list),
+        Some("lock") => subcommand!(matches, layout, lock),
+        Some("py") => subcommand!(matches, layout, py),
+        Some("remove") => subcommand!(matches, layout, remove),
+        Some("run") => subcommand!(matches, layout, run, verbosity),
+        Some("show") => subcommand!(matches, layout, show),
+        Some("sync") => subcommand!(matches, layout, sync, verbosity),
+        Some("graph") => {
+            // This does not need an interpreter.
+            let matches = matches.subcommand_matches("graph").unwrap();
+            graph::Command::new(matches).run(&layout)
+        },
+        Some("tree") => {
+            // This does not need an interpreter.
+            let matches = matches.subcommand_matches("tree").unwrap();
+            tree::Command::new(matches).run(&layout)
+        },
+        Some("which") => subcommand!(matches, layout, which),
+        Some("validate") => {
+            // This does not need an interpreter.
+            let matches = matches.subcommand_matches("validate").unwrap();
+            validate::Command::new(matches).run(&layout)
+        },
+
+        Some("pip-install") => {
+            subcommand!(matches, layout, pip_install, verbosity)
+        },
         Some(n) => Err(Error::UnrecognizedSubcommand(n.to_string())),
         None => Err(Error::SubCommandMissing),
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::create_dir_all;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    use crate::projects::InterpreterRecord;
+
+    #[test]
+    fn test_recorded_interpreter_in() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        let layout = Layout::default();
+        let record = |tag: &str, path: &str| {
+            let env_root = layout.pypackages_in(root).join(tag);
+            create_dir_all(&env_root).unwrap();
+            let record = InterpreterRecord {
+                path: PathBuf::from(path),
+                version: String::from("3.7"),
+                root: None,
+            };
+            record.write(&env_root).unwrap();
+        };
+
+        match recorded_interpreter_in(root, &layout) {
+            Err(Error::InterpreterNotSpecified) => {},
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        record("cp37-cp37m", "/usr/bin/python3.7");
+        let py = recorded_interpreter_in(root, &layout).unwrap();
+        assert_eq!(py, "/usr/bin/python3.7");
+
+        record("cp38-cp38", "/usr/bin/python3.8");
+        match recorded_interpreter_in(root, &layout) {
+            Err(Error::MultipleRecordedInterpreters(tags)) => {
+                assert_eq!(tags, vec!["cp37-cp37m", "cp38-cp38"]);
+            },
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
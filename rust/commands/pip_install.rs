@@ -1,8 +1,10 @@
+use std::env;
 use std::process;
 
 use clap::ArgMatches;
 
-use crate::projects::Project;
+use crate::outputs::Verbosity;
+use crate::projects::{Layout, Project};
 use crate::pythons::{self, Interpreter};
 use super::{Error, Result};
 
@@ -19,25 +21,33 @@ impl<'a> Command<'a> {
         self.matches.values_of("args").unwrap_or_default().collect()
     }
 
-    pub fn run(&self, interpreter: Interpreter) -> Result<()> {
-        let project = Project::find_in_cwd(interpreter)?;
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+        verbosity: Verbosity,
+    ) -> Result<()> {
+        let project = Project::find_in_cwd(interpreter, layout)?;
         let env = project.presumed_env_root().unwrap();
         let interpreter = project.base_interpreter().location();
 
         let cmd = interpreter.to_str().ok_or_else(|| {
             pythons::Error::PathRepresentationError(interpreter.to_owned())
         })?;
-        let args = vec![
+        let mut cmd = process::Command::new(cmd);
+        cmd.args([
             "-m", "pip", "install",
             "--prefix", env.to_str().unwrap(),
             "--no-warn-script-location",
-        ].into_iter().chain(self.args()).collect::<Vec<_>>();
-
-        let code = process::Command::new(cmd)
-            .args(args)
-            .status()?
-            .code()
-            .unwrap_or(-1);
+        ]);
+        // Passed before the user's arguments, so an explicit --no-cache-dir
+        // (or --cache-dir) still wins.
+        if let Some(dir) = env::var_os("MOLT_CACHE_DIR") {
+            cmd.arg("--cache-dir").arg(dir);
+        }
+        cmd.args(self.args());
+        verbosity.configure_pip(&mut cmd);
+        let code = cmd.status()?.code().unwrap_or(-1);
         if code == 0 {
             Ok(())
         } else {
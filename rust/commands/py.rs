@@ -1,6 +1,8 @@
+use std::path::PathBuf;
+
 use clap::ArgMatches;
 
-use crate::projects::Project;
+use crate::projects::{Layout, Project};
 use crate::pythons::Interpreter;
 use super::{Error, Result};
 
@@ -13,13 +15,24 @@ impl<'a> Command<'a> {
         Self { matches }
     }
 
+    fn extra_pythonpath(&self) -> Vec<PathBuf> {
+        self.matches.values_of_os("extra_pythonpath")
+            .map(|v| v.map(PathBuf::from).collect())
+            .unwrap_or_default()
+    }
+
     fn args(&self) -> Vec<&str> {
         self.matches.values_of("args").unwrap_or_default().collect()
     }
 
-    pub fn run(&self, interpreter: Interpreter) -> Result<()> {
-        let project = Project::find_in_cwd(interpreter)?;
-        let code = project.py(self.args())?.code().unwrap_or(-1);
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+    ) -> Result<()> {
+        let project = Project::find_in_cwd(interpreter, layout)?;
+        let status = project.py(self.args(), &self.extra_pythonpath())?;
+        let code = status.code().unwrap_or(-1);
         if code == 0 {
             Ok(())
         } else {
@@ -0,0 +1,117 @@
+use clap::ArgMatches;
+
+use crate::lockfiles::{canonicalize_name, Lock};
+use crate::locking;
+use crate::projects::{Layout, Project};
+use crate::pythons::Interpreter;
+use crate::requirements::{self, RequirementsFile, REQUIREMENTS_IN};
+use super::Result;
+
+pub struct Command<'a> {
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Command<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Self { matches }
+    }
+
+    fn names(&self) -> Vec<&str> {
+        self.matches.values_of("names").expect("required").collect()
+    }
+
+    /// Whether another package in the lock requires the named package.
+    ///
+    /// Edges from sections (i.e. top-level requirements) don't count.
+    fn is_required(lock: &Lock, name: &str) -> bool {
+        let name = canonicalize_name(name);
+        lock.dependencies().iter()
+            .filter(|(_, dep)| dep.python().is_some())
+            .any(|(_, dep)| dep.dependencies().any(|(d, _)| {
+                d.python().map(|p| p.canonical_name()) == Some(name.clone())
+            }))
+    }
+
+    /// Error to report if the named package is not in the requirement file.
+    fn not_found_error(
+        lock: Option<&Lock>,
+        name: &str,
+    ) -> requirements::Error {
+        let name = name.to_string();
+        if lock.is_some_and(|lock| Self::is_required(lock, &name)) {
+            requirements::Error::NotDirectRequirementError(name)
+        } else {
+            requirements::Error::RequirementNotFoundError(name)
+        }
+    }
+
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+    ) -> Result<()> {
+        let project = Project::find_in_cwd(interpreter, layout)?;
+        let path = project.root().join(REQUIREMENTS_IN);
+        let mut requirements = RequirementsFile::load(&path)?;
+
+        // Check all names before writing, so nothing is changed on error.
+        for name in self.names() {
+            if requirements.remove(name) {
+                continue;
+            }
+            let lock = project.read_lock_file().ok();
+            return Err(Self::not_found_error(lock.as_ref(), name).into());
+        }
+
+        requirements.save(&path)?;
+        locking::lock(&project)?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static LOCK_JSON: &str = r#"{
+        "dependencies": {
+            "": {"dependencies": {"baz": null, "foo": null}},
+            "bar": {"python": {"name": "Bar", "version": "2.0"}},
+            "baz": {"python": {"name": "baz", "version": "3.0"}},
+            "foo": {
+                "python": {"name": "foo", "version": "1.0"},
+                "dependencies": {"bar": null}
+            }
+        }
+    }"#;
+
+    #[test]
+    fn test_not_found_error() {
+        let lock: Lock = serde_json::from_str(LOCK_JSON).unwrap();
+        let lock = Some(&lock);
+
+        // Only reachable through another package.
+        match Command::not_found_error(lock, "bar") {
+            requirements::Error::NotDirectRequirementError(ref s) => {
+                assert_eq!(s, "bar");
+            },
+            e => panic!("unexpected error {:?}", e),
+        }
+
+        // Only required by the default section, and not locked at all.
+        for name in &["baz", "qux"] {
+            match Command::not_found_error(lock, name) {
+                requirements::Error::RequirementNotFoundError(ref s) => {
+                    assert_eq!(s, name);
+                },
+                e => panic!("unexpected error {:?}", e),
+            }
+        }
+
+        match Command::not_found_error(None, "bar") {
+            requirements::Error::RequirementNotFoundError(_) => {},
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+}
@@ -1,9 +1,26 @@
+use std::io;
+use std::path::PathBuf;
+
 use clap::ArgMatches;
+use prettytable::{Cell, Row};
 use prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR;
 
-use crate::projects::Project;
+use serde_json;
+
+use crate::outputs;
+use crate::projects::{Layout, Project};
 use crate::pythons::Interpreter;
-use super::{Error, Result};
+use super::{print_env_root, Error, Result};
+
+#[derive(Serialize)]
+struct EntryPointInfo<'a> {
+    name: &'a str,
+    module: &'a str,
+    function: &'a str,
+    gui: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    runnable: Option<bool>,
+}
 
 pub struct Command<'a> {
     matches: &'a ArgMatches<'a>,
@@ -14,33 +31,116 @@ impl<'a> Command<'a> {
         Self { matches }
     }
 
+    // The command and its arguments are parsed as one argument, so everything
+    // after the command is passed through even if it looks like our flags.
     fn command(&self) -> &str {
-        self.matches.value_of("command").expect("required")
+        self.matches.values_of("command").expect("required").next().unwrap()
+    }
+
+    fn tty(&self) -> bool {
+        self.matches.is_present("tty")
+    }
+
+    fn extra_pythonpath(&self) -> Vec<PathBuf> {
+        self.matches.values_of_os("extra_pythonpath")
+            .map(|v| v.map(PathBuf::from).collect())
+            .unwrap_or_default()
     }
 
     fn args(&self) -> Vec<&str> {
-        self.matches.values_of("args").unwrap_or_default().collect()
+        self.matches.values_of("command").expect("required").skip(1).collect()
     }
 
-    pub fn run(&self, interpreter: Interpreter) -> Result<()> {
-        let project = Project::find_in_cwd(interpreter)?;
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+        verbosity: outputs::Verbosity,
+    ) -> Result<()> {
+        let project = Project::find_in_cwd(interpreter, layout)?;
+        print_env_root(&project, verbosity)?;
         let command = self.command();
         if command == "--list" {
-            // HACK: Handle "run --list".
-            let mut eps: Vec<Vec<String>> = project.entry_points().unwrap()
-                .map(|(n, e)| {
-                    let call = format!("{}:{}", e.module(), e.function());
-                    vec![n, call]
-                })
-                .collect();
-            eps.sort_unstable();
-            let mut table = prettytable::Table::from(eps);
-            table.set_titles(row!["Entry point", "Call target"]);
+            // HACK: Handle "run --list [--json] [--check]".
+            let args = self.args();
+            let json = args.contains(&"--json");
+            let check = args.contains(&"--check");
+            if let Some(arg) = args.iter().find(|a| {
+                **a != "--json" && **a != "--check"
+            }) {
+                return Err(Error::UnrecognizedListOption(arg.to_string()));
+            }
+
+            let mut eps: Vec<_> = if check {
+                project.checked_entry_points()?.into_iter()
+                    .map(|(n, e, r)| (n, e, Some(r)))
+                    .collect()
+            } else {
+                project.entry_points()?.map(|(n, e)| (n, e, None)).collect()
+            };
+            eps.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+            if json {
+                let infos: Vec<_> = eps.iter().map(|(n, e, r)| {
+                    EntryPointInfo {
+                        name: n,
+                        module: e.module(),
+                        function: e.function(),
+                        gui: e.is_gui(),
+                        runnable: *r,
+                    }
+                }).collect();
+                let out = serde_json::to_string_pretty(&infos)
+                    .expect("entry points should be serializable");
+                println!("{}", out);
+                return Ok(());
+            }
+
+            let rows: Vec<Vec<String>> = eps.iter().map(|(n, e, r)| {
+                let call = format!("{}:{}", e.module(), e.function());
+                let kind = if e.is_gui() { "gui" } else { "console" };
+                let mut row = vec![n.to_string(), call, kind.into()];
+                match r {
+                    Some(true) => { row.push("yes".into()); },
+                    Some(false) => { row.push("no".into()); },
+                    None => {},
+                }
+                row
+            }).collect();
+            let mut table = prettytable::Table::from(rows);
+            let mut titles = vec!["Entry point", "Call target", "Type"];
+            if check {
+                titles.push("Runnable");
+            }
+            let color = outputs::color();
+            table.set_titles(Row::new(titles.into_iter().map(|t| {
+                if color { Cell::new(t).style_spec("b") } else { Cell::new(t) }
+            }).collect()));
             table.set_format(*FORMAT_NO_BORDER_LINE_SEPARATOR);
-            table.printstd();
+            if color {
+                table.print_tty(true);
+            } else {
+                table.print(&mut io::stdout())?;
+            }
             Ok(())
         } else {
-            let code = project.run(command, self.args())?.code().unwrap_or(-1);
+            let extra_paths = self.extra_pythonpath();
+            let status = if let Some(rest) = command.strip_prefix("-m") {
+                // Run a module like "python -m". Both "-m mod" and "-mmod"
+                // are accepted, as Python does.
+                let mut args = self.args();
+                let module = match rest {
+                    "" if args.is_empty() => {
+                        return Err(Error::ModuleNotSpecified);
+                    },
+                    "" => args.remove(0),
+                    m => m,
+                };
+                project.run_module(module, args, self.tty(), &extra_paths)?
+            } else {
+                project.run(command, self.args(), self.tty(), &extra_paths)?
+            };
+            let code = status.code().unwrap_or(-1);
             if code == 0 {
                 Ok(())
             } else {
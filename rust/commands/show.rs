@@ -1,11 +1,196 @@
+use std::path::PathBuf;
+use std::process;
+
 use clap::ArgMatches;
+use prettytable::format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR;
+use serde_json;
 
-use crate::projects::Project;
+use crate::lockfiles::{Lock, PythonPackage};
+use crate::projects::{Layout, Project};
 use crate::pythons::Interpreter;
 use super::Result;
 
+/// Project information for tools to consume.
+///
+/// Paths are presumed, so they can be shown even if the environment is not
+/// created yet. `exists` tells whether the environment is actually there.
+#[derive(Serialize)]
+struct Info {
+    env_root: PathBuf,
+    site_packages: PathBuf,
+    python: PathBuf,
+    bin: PathBuf,
+    compatibility_tag: String,
+    exists: bool,
+}
+
+impl Info {
+    fn new(project: &Project) -> Result<Self> {
+        let env_root = project.presumed_env_root()?;
+        let interpreter = project.base_interpreter();
+        Ok(Self {
+            exists: env_root.is_dir(),
+            site_packages: project.presumed_site_packages()?,
+            python: interpreter.location().to_path_buf(),
+            bin: project.presumed_bindir()?,
+            compatibility_tag: interpreter.compatibility_tag()?,
+            env_root,
+        })
+    }
+}
+
+/// Summary of a lock file.
+#[derive(Debug, Eq, PartialEq, Serialize)]
+struct LockStats {
+    packages: usize,
+    hashed_packages: usize,
+    sources: usize,
+    extra_sections: usize,
+    max_depth: usize,
+}
+
+impl LockStats {
+    fn new(lock: &Lock) -> Self {
+        let mut stats = Self {
+            packages: 0,
+            hashed_packages: 0,
+            sources: lock.sources().len(),
+            extra_sections: 0,
+            max_depth: 0,
+        };
+        let dependencies = lock.dependencies();
+        for (_, dep) in dependencies.iter() {
+            if let Some(python) = dep.python() {
+                stats.packages += 1;
+                if python.hashes().is_some() {
+                    stats.hashed_packages += 1;
+                }
+            }
+        }
+        stats.max_depth = dependencies.max_depth("");
+        for extra in dependencies.extras() {
+            stats.extra_sections += 1;
+            let d = dependencies.max_depth(&format!("[{}]", extra));
+            stats.max_depth = stats.max_depth.max(d);
+        }
+        stats
+    }
+
+    fn print_table(&self) {
+        let mut table = table![
+            ["Packages", self.packages],
+            ["Packages with hashes", self.hashed_packages],
+            ["Sources", self.sources],
+            ["Extra sections", self.extra_sections],
+            ["Max dependency depth", self.max_depth]
+        ];
+        table.set_format(*FORMAT_NO_BORDER_LINE_SEPARATOR);
+        table.printstd();
+    }
+}
+
+/// Parse the latest version from `pip index versions` output.
+///
+/// The first line looks like `name (version)`.
+fn parse_index_versions(output: &str) -> Option<&str> {
+    let line = output.lines().next()?.trim_end();
+    let start = line.rfind('(')?;
+    line[start + 1..].strip_suffix(')')
+}
+
+/// Ask pip for the latest version of a package available on its index.
+///
+/// pip decides which releases are compatible with the interpreter. Errors
+/// are returned as messages, so they can be shown along other results.
+fn latest_version(
+    interpreter: &Interpreter,
+    package: &PythonPackage,
+    lock: &Lock,
+) -> std::result::Result<String, String> {
+    let output = process::Command::new(interpreter.location())
+        .args(["-m", "pip", "index", "versions", package.name()])
+        .args(package.pip_options(lock.sources()))
+        .env("PIP_DISABLE_PIP_VERSION_CHECK", "1")
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(match stderr.lines().last() {
+            Some(line) => line.trim().to_string(),
+            None => format!("pip exited with {}", output.status),
+        });
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_index_versions(&stdout)
+        .map(String::from)
+        .ok_or_else(|| String::from("unrecognized pip output"))
+}
+
+/// Rows of `name / locked / latest` for named requirements that are behind.
+///
+/// `query` finds the latest version of a package. `newer` is then given all
+/// pairs of latest and locked versions at once, and tells whether each latest
+/// version is newer. If the latest version can't be found, the error is shown
+/// in place of it.
+fn outdated_rows<F, G>(
+    lock: &Lock,
+    query: F,
+    newer: G,
+) -> Result<Vec<[String; 3]>>
+    where F: Fn(&PythonPackage) -> std::result::Result<String, String>,
+          G: FnOnce(&[(&str, &str)]) -> Result<Vec<bool>>
+{
+    let mut entries = vec![];
+    for dep in lock.dependencies().sorted_packages() {
+        let python = dep.python().expect("sorted packages have python");
+        let locked = match python.version() {
+            Some(v) => v.to_string(),
+            None => { continue; },
+        };
+        entries.push((python.name().to_string(), locked, query(python)));
+    }
+
+    let pairs: Vec<_> = entries.iter()
+        .filter_map(|(_, locked, latest)| {
+            latest.as_ref().ok().map(|v| (v.as_str(), locked.as_str()))
+        })
+        .collect();
+    let mut newer = newer(&pairs)?.into_iter();
+
+    let mut rows = vec![];
+    for (name, locked, latest) in entries {
+        let latest = match latest {
+            Ok(v) => {
+                if !newer.next().unwrap_or(false) {
+                    continue;
+                }
+                v
+            },
+            Err(e) => format!("error: {}", e),
+        };
+        rows.push([name, locked, latest]);
+    }
+    Ok(rows)
+}
+
+fn print_outdated(rows: &[[String; 3]]) {
+    let mut table = table![["Name", "Locked", "Latest"]];
+    for [name, locked, latest] in rows {
+        table.add_row(row![name, locked, latest]);
+    }
+    table.set_format(*FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.printstd();
+}
+
 pub enum What {
+    Bin,
     Env,
+    Json,
+    LockStats(bool),
+    Outdated,
+    Python,
+    SitePackages,
+    Tags,
 }
 
 pub struct Command<'a> {
@@ -18,21 +203,173 @@ impl<'a> Command<'a> {
     }
 
     fn what(&self) -> What {
-        if self.matches.is_present("env") {
+        // --json modifies --lock-stats, so this needs to be checked first.
+        if self.matches.is_present("lock_stats") {
+            What::LockStats(self.matches.is_present("json"))
+        } else if self.matches.is_present("env") {
             What::Env
+        } else if self.matches.is_present("site_packages") {
+            What::SitePackages
+        } else if self.matches.is_present("python") {
+            What::Python
+        } else if self.matches.is_present("bin") {
+            What::Bin
+        } else if self.matches.is_present("tags") {
+            What::Tags
+        } else if self.matches.is_present("outdated") {
+            What::Outdated
+        } else if self.matches.is_present("json") {
+            What::Json
         } else {
             panic!("one of the options should present");
         }
     }
 
-    pub fn run(&self, interpreter: Interpreter) -> Result<()> {
-        let project = Project::find_in_cwd(interpreter)?;
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+    ) -> Result<()> {
+        let project = Project::find_in_cwd(interpreter, layout)?;
         match self.what() {
+            What::Bin => {
+                println!("{}", project.bindir()?.display());
+            },
             What::Env => {
                 let env = project.presumed_env_root().unwrap();
                 println!("{}", env.display());
             },
+            What::Json => {
+                let info = Info::new(&project)?;
+                let out = serde_json::to_string_pretty(&info)
+                    .expect("project info should be serializable");
+                println!("{}", out);
+            },
+            What::LockStats(json) => {
+                let stats = LockStats::new(&project.read_lock_file()?);
+                if json {
+                    let out = serde_json::to_string_pretty(&stats)
+                        .expect("lock stats should be serializable");
+                    println!("{}", out);
+                } else {
+                    stats.print_table();
+                }
+            },
+            What::Outdated => {
+                let lock = project.read_lock_file()?;
+                let interpreter = project.base_interpreter();
+                let rows = outdated_rows(
+                    &lock,
+                    |package| latest_version(interpreter, package, &lock),
+                    |pairs| Ok(interpreter.newer_versions(pairs)?),
+                )?;
+                print_outdated(&rows);
+            },
+            What::Python => {
+                let python = project.base_interpreter().location();
+                println!("{}", python.display());
+            },
+            What::SitePackages => {
+                println!("{}", project.site_packages()?.display());
+            },
+            What::Tags => {
+                for tag in project.base_interpreter().compatibility_tags()? {
+                    println!("{}", tag);
+                }
+            },
         }
         Ok(())
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::from_str;
+
+    #[test]
+    fn test_parse_index_versions() {
+        let output = "requests (2.31.0)\nAvailable versions: 2.31.0, 2.30.0\n";
+        assert_eq!(parse_index_versions(output), Some("2.31.0"));
+        assert_eq!(parse_index_versions(""), None);
+        assert_eq!(parse_index_versions("garbage"), None);
+    }
+
+    #[test]
+    fn test_outdated_rows() {
+        let lock: Lock = from_str(r#"{
+            "dependencies": {
+                "a": {"python": {"name": "A", "version": "1.0"}},
+                "b": {"python": {"name": "b", "version": "2.0"}},
+                "c": {"python": {"name": "c", "version": "3.0"}},
+                "d": {"python": {"name": "d", "path": "d"}},
+                "e": {"python": {"name": "e", "version": "1.0"}},
+                "f": {"python": {"name": "f", "version": "2.0b1"}},
+                "g": {"python": {"name": "g", "version": "4.1"}}
+            }
+        }"#).unwrap();
+
+        let rows = outdated_rows(&lock, |package| match package.name() {
+            "A" => Ok(String::from("1.1")),
+            "b" => Ok(String::from("2.0")),
+            "c" => Err(String::from("network unreachable")),
+            "e" => Ok(String::from("1.0.0")),
+            "f" => Ok(String::from("1.9")),
+            "g" => Ok(String::from("4.0")),
+            n => panic!("unexpected query for {:?}", n),
+        }, |pairs| {
+            assert_eq!(pairs, &[
+                ("1.1", "1.0"),
+                ("2.0", "2.0"),
+                ("1.0.0", "1.0"),
+                ("1.9", "2.0b1"),
+                ("4.0", "4.1"),
+            ]);
+            Ok(vec![true, false, false, false, false])
+        }).unwrap();
+        assert_eq!(rows, vec![
+            [String::from("A"), String::from("1.0"), String::from("1.1")],
+            [
+                String::from("c"),
+                String::from("3.0"),
+                String::from("error: network unreachable"),
+            ],
+        ]);
+    }
+
+    #[test]
+    fn test_lock_stats() {
+        let lock: Lock = from_str(r#"{
+            "sources": {"pypi": {"url": "https://pypi.org/simple"}},
+            "dependencies": {
+                "": {"dependencies": {"a": null, "d": null}},
+                "[dev]": {"dependencies": {"d": null}},
+                "[docs]": {"dependencies": {}},
+                "a": {
+                    "python": {"name": "a", "version": "1.0"},
+                    "dependencies": {"b": null}
+                },
+                "b": {
+                    "python": {"name": "b", "version": "1.0"},
+                    "dependencies": {"c": null}
+                },
+                "c": {
+                    "python": {"name": "c", "version": "1.0"},
+                    "dependencies": {"a": null}
+                },
+                "d": {"python": {"name": "d", "version": "1.0"}}
+            },
+            "hashes": {"a": ["sha256:1234"], "d": ["sha256:5678"]}
+        }"#).unwrap();
+
+        assert_eq!(LockStats::new(&lock), LockStats {
+            packages: 4,
+            hashed_packages: 2,
+            sources: 1,
+            extra_sections: 2,
+            max_depth: 3,
+        });
+    }
+}
@@ -1,9 +1,14 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
 use clap::{ArgMatches, Values};
 
-use crate::projects::Project;
+use crate::outputs::Verbosity;
+use crate::projects::{Layout, Project};
 use crate::pythons::Interpreter;
 use crate::sync::Synchronizer;
-use super::Result;
+use super::{print_env_root, Result};
 
 pub struct Command<'a> {
     matches: &'a ArgMatches<'a>,
@@ -22,10 +27,119 @@ impl<'a> Command<'a> {
         self.matches.values_of("extras").unwrap_or_default()
     }
 
-    pub fn run(&self, interpreter: Interpreter) -> Result<()> {
-        let project = Project::find_in_cwd(interpreter)?;
-        let sync = Synchronizer::new(project.read_lock_file()?)?;
-        sync.sync(&project, self.default(), self.extras())?;
+    fn all_extras(&self) -> bool {
+        self.matches.is_present("all_extras")
+    }
+
+    fn only(&self) -> Vec<String> {
+        self.matches.values_of("only")
+            .map(|v| v.map(String::from).collect())
+            .unwrap_or_default()
+    }
+
+    fn group_output(&self) -> bool {
+        self.matches.is_present("group_output")
+    }
+
+    fn parallel_downloads(&self) -> bool {
+        self.matches.is_present("parallel_downloads")
+    }
+
+    fn dry_run(&self) -> bool {
+        self.matches.is_present("dry_run")
+    }
+
+    fn summary(&self) -> bool {
+        self.matches.is_present("summary")
+    }
+
+    fn ignore_installed(&self) -> bool {
+        self.matches.is_present("ignore_installed")
+    }
+
+    fn rollback_on_check_failure(&self) -> bool {
+        self.matches.is_present("rollback_on_check_failure")
+    }
+
+    fn find_links(&self) -> Vec<PathBuf> {
+        self.matches.values_of_os("find_links")
+            .map(|v| v.map(PathBuf::from).collect())
+            .unwrap_or_default()
+    }
+
+    fn offline(&self) -> bool {
+        self.matches.is_present("offline")
+    }
+
+    /// MOLT_CACHE_DIR is read here instead of by clap, so --no-cache only
+    /// conflicts with an explicit --cache-dir, and still works in CI setting
+    /// the environment variable.
+    fn cache_dir(&self) -> Option<PathBuf> {
+        self.matches.value_of_os("cache_dir")
+            .map(OsString::from)
+            .or_else(|| env::var_os("MOLT_CACHE_DIR"))
+            .map(PathBuf::from)
+    }
+
+    fn no_cache(&self) -> bool {
+        self.matches.is_present("no_cache")
+    }
+
+    fn freeze_output(&self) -> Option<&Path> {
+        self.matches.value_of_os("freeze_output").map(Path::new)
+    }
+
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+        verbosity: Verbosity,
+    ) -> Result<()> {
+        let project = Project::find_in_cwd(interpreter, layout)?;
+        print_env_root(&project, verbosity)?;
+        let lock = project.read_lock_file()?;
+        let extras: Vec<_> = if self.all_extras() {
+            lock.dependencies().extras().map(String::from).collect()
+        } else {
+            self.extras().map(String::from).collect()
+        };
+        let mut sync = Synchronizer::new(lock)?;
+        if self.ignore_installed() {
+            sync.ignore_installed();
+        }
+        sync.only(self.only());
+        if self.dry_run() {
+            sync.dry_run();
+        }
+        sync.verbosity(verbosity);
+        sync.find_links(self.find_links());
+        if self.offline() {
+            sync.offline();
+        }
+        sync.cache_dir(self.cache_dir());
+        if self.no_cache() {
+            sync.no_cache();
+        }
+        if self.group_output() {
+            sync.group_by_source();
+        }
+        if self.parallel_downloads() {
+            sync.parallel_downloads();
+        }
+        if self.rollback_on_check_failure() {
+            sync.rollback_on_check_failure();
+        }
+        let summary = sync.sync(
+            &project,
+            self.default(),
+            extras.iter().map(String::as_str),
+        )?;
+        if let Some(path) = self.freeze_output() {
+            sync.freeze_into(&project, path)?;
+        }
+        if self.summary() {
+            println!("{}", summary);
+        }
         Ok(())
     }
 }
@@ -0,0 +1,138 @@
+use std::collections::HashSet;
+use std::env;
+use std::io::{self, Write};
+
+use clap::{ArgMatches, Values};
+
+use crate::lockfiles::{Dependency, Lock, Marker};
+use crate::projects::{self, Layout, LOCK_FILE_NAME};
+use super::{Error, Result};
+
+/// Print dependencies of `dep` as an indented tree.
+///
+/// A package already printed is marked with `(*)`, and its dependencies are
+/// not printed again. This also stops the walk on cycles.
+fn write_children<W>(
+    dep: &Dependency,
+    level: usize,
+    visited: &mut HashSet<String>,
+    writer: &mut W,
+) -> io::Result<()>
+    where W: Write
+{
+    let mut children: Vec<_> = dep.dependencies().collect();
+    children.sort_by(|a, b| a.0.key().cmp(b.0.key()));
+    for (child, marker) in children {
+        write!(writer, "{:indent$}- ", "", indent = level * 2)?;
+        match child.python() {
+            Some(python) => write!(
+                writer, "{} {}",
+                python.name(), python.specifier_display(),
+            )?,
+            None => write!(writer, "{}", child.key())?,
+        }
+        if let Some(marker) = marker.map(Marker::to_string) {
+            write!(writer, " ; {}", marker)?;
+        }
+        if visited.insert(child.key().to_string()) {
+            writeln!(writer)?;
+            write_children(&child, level + 1, visited, writer)?;
+        } else {
+            writeln!(writer, " (*)")?;
+        }
+    }
+    Ok(())
+}
+
+fn write_tree<'a, I, W>(lock: &Lock, extras: I, mut writer: W) -> Result<()>
+    where I: Iterator<Item=&'a str>, W: Write
+{
+    let dependencies = lock.dependencies();
+    let mut sections = vec![];
+    sections.push((
+        String::from("(default)"),
+        dependencies.default().ok_or_else(|| {
+            Error::SectionNotFound(String::from("default"))
+        })?,
+    ));
+    for extra in extras {
+        let section = dependencies.extra(extra).ok_or_else(|| {
+            Error::SectionNotFound(extra.to_string())
+        })?;
+        sections.push((format!("[{}]", extra), section));
+    }
+
+    let mut visited = HashSet::new();
+    for (name, section) in sections {
+        writeln!(writer, "{}", name)?;
+        write_children(&section, 0, &mut visited, &mut writer)?;
+    }
+    Ok(())
+}
+
+pub struct Command<'a> {
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Command<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Self { matches }
+    }
+
+    fn extras(&self) -> Values {
+        self.matches.values_of("extras").unwrap_or_default()
+    }
+
+    pub fn run(&self, layout: &Layout) -> Result<()> {
+        let (root, _) = projects::find_root(&env::current_dir()?, layout)?;
+        let lock = projects::read_lock_file(&root.join(LOCK_FILE_NAME))?;
+        let stdout = io::stdout();
+        write_tree(&lock, self.extras(), stdout.lock())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::from_str;
+    use unindent::unindent;
+
+    #[test]
+    fn test_write_tree() {
+        let lock: Lock = from_str(r#"{
+            "dependencies": {
+                "": {"dependencies": {"a": null, "d": ["os_name == 'nt'"]}},
+                "[dev]": {"dependencies": {"d": null, "e": null}},
+                "a": {
+                    "python": {"name": "A", "version": "1.0"},
+                    "dependencies": {
+                        "b": ["os_name == 'nt'", "python_version < '3'"]
+                    }
+                },
+                "b": {
+                    "python": {"name": "b", "version": "2.0"},
+                    "dependencies": {"a": null, "c": null}
+                },
+                "c": {"python": {"name": "c", "path": "c"}},
+                "d": {"python": {"name": "d", "version": "4.0"}},
+                "e": {"python": {"name": "e", "url": "https://e.org/e.zip"}}
+            }
+        }"#).unwrap();
+
+        let mut buf = vec![];
+        write_tree(&lock, vec!["dev"].into_iter(), &mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), unindent(r#"
+            (default)
+            - A == 1.0
+              - b == 2.0 ; (os_name == 'nt') or (python_version < '3')
+                - A == 1.0 (*)
+                - c c
+            - d == 4.0 ; os_name == 'nt'
+            [dev]
+            - d == 4.0 (*)
+            - e https://e.org/e.zip
+        "#));
+    }
+}
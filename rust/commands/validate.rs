@@ -0,0 +1,114 @@
+use std::env;
+use std::path::Path;
+
+use clap::ArgMatches;
+
+use crate::projects::{self, Layout, LOCK_FILE_NAME};
+use super::{Error, Result};
+
+/// Check a lock file's dependency graph.
+///
+/// Dangling edges and unknown sources are caught when the lock file is
+/// parsed. The graph is then checked for cycles. Packages not reachable from
+/// any section are not errors, but returned so they can be warned about.
+fn validate(path: &Path) -> Result<Vec<String>> {
+    let lock = projects::read_lock_file(path)?;
+    let dependencies = lock.dependencies();
+    match dependencies.find_cycle() {
+        Some(cycle) => Err(Error::DependencyCycle(cycle)),
+        None => Ok(dependencies.unreferenced_packages()),
+    }
+}
+
+pub struct Command<'a> {
+    _matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Command<'a> {
+    pub fn new(_matches: &'a ArgMatches) -> Self {
+        Self { _matches }
+    }
+
+    pub fn run(&self, layout: &Layout) -> Result<()> {
+        let (root, _) = projects::find_root(&env::current_dir()?, layout)?;
+        for key in validate(&root.join(LOCK_FILE_NAME))? {
+            eprintln!("warning: {:?} is not required by any section", key);
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::write;
+    use tempfile::TempDir;
+
+    fn validate_json(json: &str) -> Result<Vec<String>> {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join(LOCK_FILE_NAME);
+        write(&path, json).unwrap();
+        validate(&path)
+    }
+
+    #[test]
+    fn test_validate() {
+        let result = validate_json(r#"{
+            "sources": {"pypi": {"url": "https://pypi.org/simple"}},
+            "dependencies": {
+                "": {"dependencies": {"a": null}},
+                "a": {
+                    "python": {"name": "a", "version": "1", "source": "pypi"},
+                    "dependencies": {"b": null}
+                },
+                "b": {"python": {"name": "b", "version": "1"}}
+            }
+        }"#);
+        assert_eq!(result.unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_validate_unreferenced() {
+        let result = validate_json(r#"{
+            "dependencies": {
+                "": {"dependencies": {"a": null}},
+                "a": {"python": {"name": "a", "version": "1"}},
+                "b": {"python": {"name": "b", "version": "1"}}
+            }
+        }"#);
+        assert_eq!(result.unwrap(), vec!["b"]);
+    }
+
+    #[test]
+    fn test_validate_dangling_edge() {
+        let result = validate_json(r#"{
+            "dependencies": {
+                "": {"dependencies": {"a": null}},
+                "a": {"dependencies": {"missing": null}}
+            }
+        }"#);
+        match result {
+            Err(Error::ProjectError(_)) => {},
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_validate_cycle() {
+        let result = validate_json(r#"{
+            "dependencies": {
+                "": {"dependencies": {"a": null}},
+                "a": {"dependencies": {"b": null}},
+                "b": {"dependencies": {"a": null}}
+            }
+        }"#);
+        match result {
+            Err(Error::DependencyCycle(cycle)) => {
+                assert_eq!(cycle, vec!["a", "b", "a"]);
+            },
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::io::{self, Write};
+
+use clap::ArgMatches;
+
+use crate::entrypoints::EntryPoint;
+use crate::projects::{self, Layout, Project};
+use crate::pythons::Interpreter;
+use super::Result;
+
+/// Write the target of the entry point named `command`.
+///
+/// The target is written as `module:function`, followed by ` (gui)` if the
+/// entry point is declared in `gui_scripts`.
+fn write_entry<I, W>(
+    entry_points: I,
+    command: &str,
+    mut writer: W,
+) -> Result<()>
+    where I: IntoIterator<Item=(String, EntryPoint)>, W: Write
+{
+    let entry = entry_points.into_iter()
+        .find(|(name, _)| name == command)
+        .map(|(_, entry)| entry)
+        .ok_or_else(|| {
+            projects::Error::CommandNotFoundError(command.to_owned())
+        })?;
+    write!(writer, "{}:{}", entry.module(), entry.function())?;
+    if entry.is_gui() {
+        write!(writer, " (gui)")?;
+    }
+    writeln!(writer)?;
+    Ok(())
+}
+
+pub struct Command<'a> {
+    matches: &'a ArgMatches<'a>,
+}
+
+impl<'a> Command<'a> {
+    pub fn new(matches: &'a ArgMatches) -> Self {
+        Self { matches }
+    }
+
+    fn command(&self) -> &str {
+        self.matches.value_of("command").expect("required")
+    }
+
+    pub fn run(
+        &self,
+        interpreter: Interpreter,
+        layout: &Layout,
+    ) -> Result<()> {
+        let project = Project::find_in_cwd(interpreter, layout)?;
+        let stdout = io::stdout();
+        write_entry(project.entry_points()?, self.command(), stdout.lock())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir, write};
+    use tempfile::TempDir;
+    use unindent::unindent;
+
+    use crate::entrypoints::EntryPoints;
+
+    #[test]
+    fn test_write_entry() {
+        let site_packages = TempDir::new().unwrap();
+        let dist_info = site_packages.path().join("prog-1.0.dist-info");
+        create_dir(&dist_info).unwrap();
+        write(dist_info.join("entry_points.txt"), unindent("
+            [console_scripts]
+            prog = prog.cli:main
+
+            [gui_scripts]
+            prog-gui = prog.gui:main
+        ")).unwrap();
+
+        let which = |command: &str| {
+            let entry_points = EntryPoints::new(site_packages.path()).unwrap();
+            let mut buf = vec![];
+            write_entry(entry_points, command, &mut buf)
+                .map(|_| String::from_utf8(buf).unwrap())
+        };
+
+        assert_eq!(which("prog").unwrap(), "prog.cli:main\n");
+        assert_eq!(which("prog-gui").unwrap(), "prog.gui:main (gui)\n");
+
+        let error = which("missing").unwrap_err();
+        assert_eq!(error.to_string(), "command \"missing\" not found");
+        assert_ne!(error.status(), 0);
+    }
+}
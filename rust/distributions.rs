@@ -0,0 +1,134 @@
+use std::fs::{File, read_dir, read_to_string};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::lockfiles::canonicalize_name;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Distribution {
+    name: String,
+    version: String,
+    location: PathBuf,
+}
+
+impl Distribution {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn canonical_name(&self) -> String {
+        canonicalize_name(&self.name)
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Top-level modules, as listed in top_level.txt.
+    ///
+    /// This is empty if the distribution does not list them.
+    pub fn top_level(&self) -> Vec<String> {
+        let path = self.location.join("top_level.txt");
+        let content = match read_to_string(path) {
+            Ok(content) => content,
+            Err(_) => { return vec![]; },
+        };
+        content.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    /// Path to the .dist-info or .egg-info entry.
+    pub fn location(&self) -> &Path {
+        &self.location
+    }
+}
+
+fn read_metadata(p: &Path) -> Option<(String, String)> {
+    let mut name = None;
+    let mut version = None;
+    for line in BufReader::new(File::open(p).ok()?).lines() {
+        let line = line.ok()?;
+        if line.is_empty() {
+            break;  // End of headers.
+        }
+        if let Some(v) = line.strip_prefix("Name: ") {
+            name = Some(v.trim().to_string());
+        } else if let Some(v) = line.strip_prefix("Version: ") {
+            version = Some(v.trim().to_string());
+        }
+        if name.is_some() && version.is_some() {
+            break;
+        }
+    }
+    Some((name?, version?))
+}
+
+fn read_distribution(path: &Path) -> Option<Distribution> {
+    let metadata = match path.extension()?.to_str()? {
+        "dist-info" => path.join("METADATA"),
+        "egg-info" => if path.is_dir() {
+            path.join("PKG-INFO")
+        } else {
+            path.to_path_buf()  // egg-info can be a single file.
+        },
+        _ => { return None; },
+    };
+
+    // Fall back to parse the directory name if metadata is not available.
+    let (name, version) = read_metadata(&metadata).or_else(|| {
+        let stem = path.file_stem()?.to_str()?;
+        let mut parts = stem.splitn(3, '-');
+        Some((parts.next()?.to_string(), parts.next()?.to_string()))
+    })?;
+
+    Some(Distribution { name, version, location: path.to_path_buf() })
+}
+
+/// Find distributions installed in a site-packages directory.
+pub fn find(site_packages: &Path) -> io::Result<Vec<Distribution>> {
+    let mut distributions = vec![];
+    for entry in read_dir(site_packages)? {
+        if let Some(d) = read_distribution(&entry?.path()) {
+            distributions.push(d);
+        }
+    }
+    Ok(distributions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir, write};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find() {
+        let site_packages = TempDir::new().unwrap();
+        let root = site_packages.path();
+
+        let dist_info = root.join("Foo_Bar-1.0.dist-info");
+        create_dir(&dist_info).unwrap();
+        write(
+            dist_info.join("METADATA"),
+            "Metadata-Version: 2.1\nName: Foo-Bar\nVersion: 1.0\n\nName: x",
+        ).unwrap();
+
+        // No metadata file, inferred from directory name.
+        create_dir(root.join("baz-2.0-py3.7.egg-info")).unwrap();
+
+        // Not a distribution.
+        create_dir(root.join("foo_bar")).unwrap();
+
+        let mut distributions = find(root).unwrap();
+        distributions.sort_by_key(Distribution::canonical_name);
+        assert_eq!(
+            distributions.iter()
+                .map(|d| (d.name(), d.version()))
+                .collect::<Vec<_>>(),
+            vec![("baz", "2.0"), ("Foo-Bar", "1.0")],
+        );
+    }
+}
@@ -1,4 +1,6 @@
-use std::collections::{HashMap, hash_map};
+use std::collections::{HashMap, HashSet, hash_map};
+use std::fs::ReadDir;
+use std::io;
 use std::path::Path;
 
 use ini::Ini;
@@ -7,18 +9,31 @@ use regex::Regex;
 pub struct EntryPoint {
     modu: String,
     func: String,
-
-    #[allow(dead_code)] gui: bool,
+    gui: bool,
 }
 
 impl EntryPoint {
+    /// Parse an entry point value. `None` is returned if it is malformed.
     fn parse(value: &str, gui: bool) -> Option<Self> {
+        // Strip trailing comments.
+        let value = match value.find('#') {
+            Some(i) => &value[..i],
+            None => value,
+        };
+
         let (m, f) = value.split_at(value.find(':')?);
-        Some(Self {
-            modu: m.trim().to_string(),
-            func: f[1..].trim().to_string(),
-            gui,
-        })
+
+        // Strip the extras part, e.g. "module:func [extra1,extra2]".
+        let f = match f.find('[') {
+            Some(i) => &f[1..i],
+            None => &f[1..],
+        };
+
+        let (m, f) = (m.trim(), f.trim());
+        if !DOTTED_NAME_RE.is_match(m) || !DOTTED_NAME_RE.is_match(f) {
+            return None;
+        }
+        Some(Self { modu: m.to_string(), func: f.to_string(), gui })
     }
 
     pub fn module(&self) -> &str {
@@ -28,9 +43,16 @@ impl EntryPoint {
     pub fn function(&self) -> &str {
         &self.func
     }
+
+    /// Whether this is declared in `gui_scripts`.
+    pub fn is_gui(&self) -> bool {
+        self.gui
+    }
 }
 
 lazy_static! {
+    static ref DOTTED_NAME_RE: Regex =
+        Regex::new(r"^[^\W\d]\w*(\.[^\W\d]\w*)*$").unwrap();
     static ref PIP_RE: Regex =
         Regex::new(r"^pip\d+(\.\d+)?$").unwrap();
     static ref EASY_INSTALL_RE: Regex =
@@ -73,36 +95,148 @@ fn read_entry_points(distro: &Path) -> Option<HashMap<String, EntryPoint>> {
     Some(entry_points)
 }
 
-fn read_all_entry_points(dir: &Path) -> Option<HashMap<String, EntryPoint>> {
-    let mut entry_points = HashMap::new();
-    for read_result in dir.read_dir().ok()? {
-        let entry = match read_result {
-            Ok(e) => e,
-            Err(_) => { continue; },
-        };
-        match read_entry_points(&entry.path()) {
-            Some(h) => { entry_points.extend(h); },
-            None => { continue; },
-        }
-    }
-    Some(entry_points)
-}
-
-// TODO: Implement this as a lazy iterator instead.
+/// Entry points in a site-packages directory.
+///
+/// Distributions are read lazily as the iterator advances, so a caller
+/// looking for a specific entry can stop early without reading everything.
+/// If more than one distribution declares the same name, only the first one
+/// found is yielded.
 pub struct EntryPoints {
-    iterator: hash_map::IntoIter<String, EntryPoint>,
+    distros: ReadDir,
+    current: hash_map::IntoIter<String, EntryPoint>,
+    seen: HashSet<String>,
 }
 
 impl EntryPoints {
-    pub fn new(site_packages: &Path) -> Self {
-        let members = read_all_entry_points(site_packages).unwrap_or_default();
-        Self { iterator: members.into_iter() }
+    /// Errors are returned if `site_packages` cannot be read. Distributions
+    /// that fail to be read while iterating are skipped.
+    pub fn new(site_packages: &Path) -> io::Result<Self> {
+        Ok(Self {
+            distros: site_packages.read_dir()?,
+            current: HashMap::new().into_iter(),
+            seen: HashSet::new(),
+        })
     }
 }
 
 impl Iterator for EntryPoints {
     type Item = (String, EntryPoint);
     fn next(&mut self) -> Option<Self::Item> {
-        self.iterator.next()
+        loop {
+            for (name, entry_point) in &mut self.current {
+                if self.seen.insert(name.clone()) {
+                    return Some((name, entry_point));
+                }
+            }
+            let entry = match self.distros.next()? {
+                Ok(e) => e,
+                Err(_) => { continue; },
+            };
+            if let Some(h) = read_entry_points(&entry.path()) {
+                self.current = h.into_iter();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, write};
+    use tempfile::TempDir;
+    use unindent::unindent;
+
+    #[test]
+    fn test_entry_point_parse() {
+        let ep = EntryPoint::parse("m:f", false).unwrap();
+        assert_eq!((ep.module(), ep.function()), ("m", "f"));
+
+        let ep = EntryPoint::parse("m:f [x]", false).unwrap();
+        assert_eq!((ep.module(), ep.function()), ("m", "f"));
+
+        let ep = EntryPoint::parse("m:f [x,y]", false).unwrap();
+        assert_eq!((ep.module(), ep.function()), ("m", "f"));
+
+        let ep = EntryPoint::parse("m.sub:Cls.method", false).unwrap();
+        assert_eq!((ep.module(), ep.function()), ("m.sub", "Cls.method"));
+    }
+
+    #[test]
+    fn test_entry_point_parse_comment_whitespace() {
+        let ep = EntryPoint::parse("m:f  # comment", false).unwrap();
+        assert_eq!((ep.module(), ep.function()), ("m", "f"));
+
+        let ep = EntryPoint::parse("  m.sub \t:  f  ", false).unwrap();
+        assert_eq!((ep.module(), ep.function()), ("m.sub", "f"));
+
+        let ep = EntryPoint::parse("m:f [x]  # comment", false).unwrap();
+        assert_eq!((ep.module(), ep.function()), ("m", "f"));
+    }
+
+    #[test]
+    fn test_entry_point_parse_invalid() {
+        assert!(EntryPoint::parse("m", false).is_none());
+        assert!(EntryPoint::parse("m:", false).is_none());
+        assert!(EntryPoint::parse("1m:f", false).is_none());
+        assert!(EntryPoint::parse("m:f g", false).is_none());
+        assert!(EntryPoint::parse("m..sub:f", false).is_none());
+        assert!(EntryPoint::parse("# m:f", false).is_none());
+    }
+
+    #[test]
+    fn test_entry_points_gui() {
+        let tmp = TempDir::new().unwrap();
+        let dist_info = tmp.path().join("foo-1.0.dist-info");
+        create_dir_all(&dist_info).unwrap();
+        write(dist_info.join("entry_points.txt"), unindent("
+            [console_scripts]
+            foo = foo.cli:main
+
+            [gui_scripts]
+            foo-gui = foo.gui:App.run
+        ")).unwrap();
+
+        let mut eps: Vec<_> = EntryPoints::new(tmp.path()).unwrap()
+            .map(|(n, e)| (n, e.module().to_string(), e.is_gui()))
+            .collect();
+        eps.sort();
+        assert_eq!(eps, vec![
+            (String::from("foo"), String::from("foo.cli"), false),
+            (String::from("foo-gui"), String::from("foo.gui"), true),
+        ]);
+    }
+
+    #[test]
+    fn test_entry_points_across_distributions() {
+        let tmp = TempDir::new().unwrap();
+        for (distro, content) in &[
+            ("pip-19.1.dist-info", "pip = pip:main\npip3.7 = pip:main\n"),
+            ("foo-1.0.dist-info", "foo = foo:main\nshared = foo:main\n"),
+            ("bar-1.0.egg-info", "shared = bar:main\n"),
+            ("baz-1.0.dist-info", "baz = baz:main\n"),
+        ] {
+            let dir = tmp.path().join(distro);
+            create_dir_all(&dir).unwrap();
+            write(
+                dir.join("entry_points.txt"),
+                format!("[console_scripts]\n{}", content),
+            ).unwrap();
+        }
+        // Not a distribution; should be skipped.
+        create_dir_all(tmp.path().join("qux")).unwrap();
+
+        let mut names: Vec<_> = EntryPoints::new(tmp.path()).unwrap()
+            .map(|(n, _)| n)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["baz", "foo", "pip", "shared"]);
+    }
+
+    #[test]
+    fn test_entry_points_unreadable() {
+        let tmp = TempDir::new().unwrap();
+        let missing = tmp.path().join("site-packages");
+        assert!(EntryPoints::new(&missing).is_err());
     }
 }
@@ -0,0 +1,186 @@
+pub mod requirements;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+pub enum Foreign {
+    PipfileLock(PathBuf),
+    PoetryLock(PathBuf),
+    CondaLock(PathBuf),
+    RequirementsTxt(PathBuf),
+}
+
+impl Foreign {
+    pub fn find_in(path: &Path) -> Option<Self> {
+        let mut p: PathBuf;
+
+        p = path.join("Pipfile.lock");
+        if p.is_file() {
+            return Some(Foreign::PipfileLock(p));
+        }
+
+        p = path.join("poetry.lock");
+        if p.is_file() {
+            return Some(Foreign::PoetryLock(p));
+        }
+
+        p = path.join("conda-lock.yml");
+        if p.is_file() {
+            return Some(Foreign::CondaLock(p));
+        }
+
+        // Fall back to sniffing other lock files, in name order so the
+        // result does not depend on the file system.
+        let mut candidates: Vec<_> = fs::read_dir(path).ok()?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && p.extension() == Some("lock".as_ref()))
+            .collect();
+        candidates.sort();
+        let detected = candidates.iter().find_map(|p| Self::detect(p));
+        if detected.is_some() {
+            return detected;
+        }
+
+        // requirements.txt is only used if there is no richer lock file.
+        p = path.join("requirements.txt");
+        if p.is_file() {
+            return Some(Foreign::RequirementsTxt(p));
+        }
+
+        None
+    }
+
+    /// Detect the format of the file at `path` by its content.
+    ///
+    /// A JSON object with `_meta` and `default` or `develop` keys is a
+    /// Pipfile.lock. A file with a `[[package]]` table array is a
+    /// poetry.lock. A YAML `package` list with `manager` entries is a
+    /// conda-lock.yml. This only sniffs the shape; the content is validated
+    /// on conversion.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        if let Ok(Value::Object(ref map)) = serde_json::from_str(&content) {
+            if map.contains_key("_meta")
+                    && (map.contains_key("default")
+                        || map.contains_key("develop")) {
+                return Some(Foreign::PipfileLock(path.to_path_buf()));
+            }
+            return None;
+        }
+        if content.lines().any(|l| l.trim() == "[[package]]") {
+            return Some(Foreign::PoetryLock(path.to_path_buf()));
+        }
+        if content.lines().any(|l| l.trim_end() == "package:")
+                && content.lines().any(|l| {
+                    l.trim_start_matches(&[' ', '-'][..])
+                        .starts_with("manager:")
+                }) {
+            return Some(Foreign::CondaLock(path.to_path_buf()));
+        }
+        None
+    }
+
+    /// Use the file at `path`, inferring its format from the file name, or
+    /// its content if the name is not recognized. Any `.txt` file is taken
+    /// as a requirements.txt.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name == "pipfile.lock" {
+            Some(Foreign::PipfileLock(path.to_path_buf()))
+        } else if name == "poetry.lock" {
+            Some(Foreign::PoetryLock(path.to_path_buf()))
+        } else if name == "conda-lock.yml" {
+            Some(Foreign::CondaLock(path.to_path_buf()))
+        } else if name.ends_with(".txt") {
+            Some(Foreign::RequirementsTxt(path.to_path_buf()))
+        } else {
+            Self::detect(path)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_from_path() {
+        match Foreign::from_path(Path::new("sub/Pipfile.lock")) {
+            Some(Foreign::PipfileLock(p)) => {
+                assert_eq!(p, Path::new("sub/Pipfile.lock"));
+            },
+            _ => panic!("Pipfile.lock not recognized"),
+        }
+        match Foreign::from_path(Path::new("poetry.lock")) {
+            Some(Foreign::PoetryLock(_)) => {},
+            _ => panic!("poetry.lock not recognized"),
+        }
+        match Foreign::from_path(Path::new("conda-lock.yml")) {
+            Some(Foreign::CondaLock(_)) => {},
+            _ => panic!("conda-lock.yml not recognized"),
+        }
+        match Foreign::from_path(Path::new("requirements-dev.txt")) {
+            Some(Foreign::RequirementsTxt(_)) => {},
+            _ => panic!("requirements-dev.txt not recognized"),
+        }
+        assert!(Foreign::from_path(Path::new("molt.lock.json")).is_none());
+    }
+
+    #[test]
+    fn test_detect() {
+        let tmp = TempDir::new().unwrap();
+        let write = |name: &str, content: &str| {
+            let p = tmp.path().join(name);
+            fs::write(&p, content).unwrap();
+            p
+        };
+
+        let p = write("a.json", r#"{"_meta": {}, "default": {}}"#);
+        match Foreign::detect(&p) {
+            Some(Foreign::PipfileLock(_)) => {},
+            _ => panic!("Pipfile.lock content not detected"),
+        }
+
+        let p = write("b.toml", "[[package]]\nname = \"foo\"\n");
+        match Foreign::detect(&p) {
+            Some(Foreign::PoetryLock(_)) => {},
+            _ => panic!("poetry.lock content not detected"),
+        }
+
+        let p = write("c.yml", "package:\n- name: foo\n  manager: pip\n");
+        match Foreign::detect(&p) {
+            Some(Foreign::CondaLock(_)) => {},
+            _ => panic!("conda-lock.yml content not detected"),
+        }
+
+        let p = write("d.json", r#"{"dependencies": {}}"#);
+        assert!(Foreign::detect(&p).is_none());
+    }
+
+    #[test]
+    fn test_find_in_fallback() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("other.lock"), "[[package]]\n").unwrap();
+        match Foreign::find_in(tmp.path()) {
+            Some(Foreign::PoetryLock(p)) => {
+                assert_eq!(p, tmp.path().join("other.lock"));
+            },
+            _ => panic!("renamed poetry.lock not found"),
+        }
+
+        fs::write(tmp.path().join("requirements.txt"), "six==1.12.0\n")
+            .unwrap();
+        match Foreign::find_in(tmp.path()) {
+            Some(Foreign::PoetryLock(_)) => {},
+            _ => panic!("requirements.txt should not take precedence"),
+        }
+        fs::remove_file(tmp.path().join("other.lock")).unwrap();
+        match Foreign::find_in(tmp.path()) {
+            Some(Foreign::RequirementsTxt(_)) => {},
+            _ => panic!("requirements.txt not found"),
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use regex::Regex;
+use serde_json::{self, Value};
+use url::Url;
+
+use crate::lockfiles::{canonicalize_name, Lock};
+use crate::requirements::{self, requirement_name, RequirementsTxt};
+
+#[derive(Debug)]
+pub enum Error {
+    LockError(serde_json::Error),
+    ReadError(requirements::Error),
+    UnsupportedLineError(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::LockError(ref e) => e.fmt(f),
+            Error::ReadError(ref e) => e.fmt(f),
+            Error::UnsupportedLineError(ref s) => {
+                write!(f, "unsupported requirements.txt line {:?} (only \
+                           pinned name==version lines are supported)", s)
+            },
+        }
+    }
+}
+
+impl From<requirements::Error> for Error {
+    fn from(e: requirements::Error) -> Self {
+        Error::ReadError(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::LockError(e)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+lazy_static! {
+    static ref PINNED_RE: Regex = Regex::new(
+        r"^([A-Za-z0-9][A-Za-z0-9._-]*)\s*===?\s*([^\s;]+)\s*(?:;\s*(.+))?$",
+    ).unwrap();
+}
+
+/// Value of an option line, e.g. `--index-url=URL` or `-i URL`.
+fn option_value<'a>(line: &'a str, names: &[&str]) -> Option<&'a str> {
+    for name in names {
+        if let Some(rest) = line.strip_prefix(name) {
+            if let Some(value) = rest.strip_prefix('=') {
+                return Some(value.trim());
+            }
+            if rest.starts_with(char::is_whitespace) {
+                return Some(rest.trim());
+            }
+        }
+    }
+    None
+}
+
+/// Split `--hash` options off a requirement line.
+fn split_hashes(line: &str) -> (String, Vec<String>) {
+    let mut words = vec![];
+    let mut hashes = vec![];
+    let mut tokens = line.split_whitespace();
+    while let Some(token) = tokens.next() {
+        if let Some(hash) = token.strip_prefix("--hash=") {
+            hashes.push(hash.to_string());
+        } else if token == "--hash" {
+            hashes.extend(tokens.next().map(String::from));
+        } else {
+            words.push(token);
+        }
+    }
+    (words.join(" "), hashes)
+}
+
+/// Pin a requirement by a constraint line, e.g. `six==1.12.0`.
+///
+/// The constraint's version replaces whatever the requirement specifies.
+/// The requirement's markers are kept, or the constraint's if it has none.
+/// Constraints that do not pin a version are ignored.
+fn constrain(spec: &str, constraint: &str) -> Option<String> {
+    let pin = PINNED_RE.captures(constraint)?;
+    let (requirement, markers) = match spec.find(';') {
+        Some(i) => (&spec[..i], Some(spec[i + 1..].trim())),
+        None => (spec, pin.get(3).map(|m| m.as_str())),
+    };
+    let name = requirement.trim_start()
+        .split(|c: char| !c.is_ascii_alphanumeric() && !"._-".contains(c))
+        .next()?;
+    Some(match markers {
+        Some(markers) => format!("{}=={}; {}", name, &pin[2], markers),
+        None => format!("{}=={}", name, &pin[2]),
+    })
+}
+
+struct Index {
+    url: String,
+    no_verify_ssl: bool,
+}
+
+impl Index {
+    fn new(url: &str) -> Self {
+        Self { url: url.to_string(), no_verify_ssl: false }
+    }
+
+    fn host(&self) -> Option<String> {
+        Url::parse(&self.url).ok()?.host_str().map(String::from)
+    }
+}
+
+/// Convert a pinned requirements.txt into a lock.
+///
+/// Every package goes directly into the default section since the file
+/// has no dependency graph. Indexes become sources, `--index-url` first
+/// and `--extra-index-url` in the order they appear, with PyPI as the
+/// default if no `--index-url` is given. Hosts listed by `--trusted-host`
+/// mark matching sources with `no_verify_ssl`. A requirement with a pinned
+/// `-c` constraint takes its version from the constraint.
+pub fn to_lock(path: &Path) -> Result<Lock> {
+    let txt = RequirementsTxt::load(path)?;
+
+    let mut index = None;
+    let mut extra_indexes = vec![];
+    let mut trusted_hosts = vec![];
+    let mut packages = BTreeMap::new();
+    let mut hashes = BTreeMap::new();
+    let mut section = BTreeMap::new();
+
+    for line in txt.lines() {
+        if let Some(v) = option_value(line, &["--index-url", "-i"]) {
+            index = Some(Index::new(v));
+        } else if let Some(v) = option_value(line, &["--extra-index-url"]) {
+            extra_indexes.push(Index::new(v));
+        } else if let Some(v) = option_value(line, &["--trusted-host"]) {
+            trusted_hosts.push(v.to_string());
+        } else {
+            let (mut spec, mut line_hashes) = split_hashes(line);
+            let constraint = requirement_name(&spec)
+                .and_then(|name| txt.constraint(&name));
+            if let Some(constraint) = constraint {
+                let (constraint, hashes) = split_hashes(constraint);
+                if let Some(pinned) = constrain(&spec, &constraint) {
+                    spec = pinned;
+                    line_hashes.extend(hashes);
+                }
+            }
+            let captures = PINNED_RE.captures(&spec).ok_or_else(|| {
+                Error::UnsupportedLineError(line.to_string())
+            })?;
+            let name = &captures[1];
+            let key = canonicalize_name(name);
+            let markers = captures.get(3).map(|m| json!([m.as_str()]));
+
+            packages.insert(key.clone(), json!({"python": {
+                "name": name,
+                "version": &captures[2],
+            }}));
+            if !line_hashes.is_empty() {
+                hashes.insert(key.clone(), json!(line_hashes));
+            }
+            section.insert(key, markers.unwrap_or(Value::Null));
+        }
+    }
+
+    let mut indexes = vec![
+        index.unwrap_or_else(|| Index::new("https://pypi.org/simple")),
+    ];
+    indexes.extend(extra_indexes);
+    for index in indexes.iter_mut() {
+        if let Some(host) = index.host() {
+            index.no_verify_ssl = trusted_hosts.contains(&host);
+        }
+    }
+
+    let mut sources = BTreeMap::new();
+    for (i, index) in indexes.iter().enumerate() {
+        let name = match i {
+            0 => String::from("default"),
+            n => format!("extra-{}", n),
+        };
+        let mut source = json!({"url": index.url, "priority": i});
+        if index.no_verify_ssl {
+            source["no_verify_ssl"] = json!(true);
+        }
+        sources.insert(name, source);
+    }
+
+    packages.insert(String::new(), json!({"dependencies": section}));
+    let data = json!({
+        "sources": sources,
+        "dependencies": packages,
+        "hashes": hashes,
+    });
+    Ok(serde_json::from_value(data)?)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_to_lock() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("requirements.txt");
+        write(&path, "\
+            # Pinned.\n\
+            -i https://example.com/simple\n\
+            --extra-index-url=https://pypi.org/simple\n\
+            --trusted-host example.com\n\
+            Django==2.2 \\\n    --hash=sha256:abc --hash sha256:def\n\
+            six==1.12.0; python_version < \"3\"  # Legacy.\n\
+        ").unwrap();
+
+        let lock = to_lock(&path).unwrap();
+        let mut out = vec![];
+        lock.write(&mut out).unwrap();
+        let out: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(out, json!({
+            "sources": {
+                "default": {
+                    "url": "https://example.com/simple",
+                    "no_verify_ssl": true,
+                    "priority": 0,
+                },
+                "extra-1": {"url": "https://pypi.org/simple", "priority": 1},
+            },
+            "dependencies": {
+                "": {"dependencies": {
+                    "django": null,
+                    "six": ["python_version < \"3\""],
+                }},
+                "django": {"python": {"name": "Django", "version": "2.2"}},
+                "six": {"python": {"name": "six", "version": "1.12.0"}},
+            },
+            "hashes": {"django": ["sha256:abc", "sha256:def"]},
+        }));
+    }
+
+    #[test]
+    fn test_to_lock_constraints() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("requirements.txt");
+        write(&path, "\
+            -c constraints.txt\n\
+            requests>=2\n\
+            six; python_version < \"3\"\n\
+            Django==2.2\n\
+        ").unwrap();
+        write(tmp.path().join("constraints.txt"), "\
+            requests==2.22.0 --hash=sha256:abc\n\
+            Six==1.12.0\n\
+            django<3\n\
+            urllib3==1.24.3\n\
+        ").unwrap();
+
+        let lock = to_lock(&path).unwrap();
+        let mut out = vec![];
+        lock.write(&mut out).unwrap();
+        let out: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(out["dependencies"], json!({
+            "": {"dependencies": {
+                "django": null,
+                "requests": null,
+                "six": ["python_version < \"3\""],
+            }},
+            "django": {"python": {"name": "Django", "version": "2.2"}},
+            "requests": {"python": {"name": "requests", "version": "2.22.0"}},
+            "six": {"python": {"name": "six", "version": "1.12.0"}},
+        }));
+        assert_eq!(out["hashes"], json!({"requests": ["sha256:abc"]}));
+    }
+
+    #[test]
+    fn test_to_lock_unpinned() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("requirements.txt");
+        write(&path, "requests>=2\n").unwrap();
+
+        match to_lock(&path) {
+            Err(Error::UnsupportedLineError(ref s)) => {
+                assert_eq!(s, "requests>=2");
+            },
+            r => panic!("unexpected result {:?}", r.map(|_| ())),
+        }
+    }
+}
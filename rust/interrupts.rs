@@ -0,0 +1,98 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+mod unix {
+    use std::sync::atomic::Ordering;
+
+    use libc;
+
+    use super::INTERRUPTED;
+
+    extern "C" fn handle(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+
+    pub struct Guard(libc::sighandler_t);
+
+    impl Guard {
+        pub fn install() -> Self {
+            let handler = handle as extern "C" fn(libc::c_int);
+            let previous = unsafe {
+                libc::signal(libc::SIGINT, handler as libc::sighandler_t)
+            };
+            Guard(previous)
+        }
+    }
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            unsafe { libc::signal(libc::SIGINT, self.0); }
+        }
+    }
+}
+
+/// Restores the previous SIGINT handling when dropped.
+pub struct Guard {
+    #[cfg(unix)] _inner: unix::Guard,
+}
+
+/// Catch SIGINT (Ctrl-C) until the returned guard is dropped.
+///
+/// Instead of terminating immediately, the interruption is recorded, so the
+/// caller can stop at a safe point (checked with `interrupted()`) and clean
+/// up. Children in the foreground process group receive the signal from the
+/// terminal themselves. This does nothing on non-Unix platforms.
+pub fn catch() -> Guard {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    Guard {
+        #[cfg(unix)] _inner: unix::Guard::install(),
+    }
+}
+
+/// Whether SIGINT is received since `catch()` is called.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The signal is raised in a forked child, so the recorded interruption
+    // does not leak into other tests running in parallel.
+    #[cfg(unix)]
+    #[test]
+    fn test_catch() {
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0, "fork failed");
+        if pid == 0 {
+            unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL); }
+            let guard = catch();
+            let before = interrupted();
+            unsafe { libc::raise(libc::SIGINT); }
+            if before || !interrupted() {
+                unsafe { libc::_exit(1); }
+            }
+
+            // The default handling is restored, and terminates the child.
+            drop(guard);
+            unsafe {
+                libc::raise(libc::SIGINT);
+                libc::_exit(2);
+            }
+        }
+
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        let (signaled, code, signal) = unsafe {(
+            libc::WIFSIGNALED(status),
+            libc::WEXITSTATUS(status),
+            libc::WTERMSIG(status),
+        )};
+        assert!(signaled, "child exited with {}", code);
+        assert_eq!(signal, libc::SIGINT);
+    }
+}
@@ -1,5 +1,5 @@
 use std::cell::{Ref, RefCell};
-use std::collections::{HashMap, hash_map};
+use std::collections::{BTreeMap, HashMap, HashSet, hash_map};
 use std::fmt::{self, Formatter};
 use std::rc::Rc;
 use std::slice::Iter;
@@ -11,6 +11,7 @@ use serde::de::{
     SeqAccess,
     Visitor,
 };
+use serde::ser::{Serialize, SerializeMap, Serializer};
 
 use super::{Hashes, PythonPackage, Sources};
 use super::pypackages::{Entry as PythonPackageEntry};
@@ -25,6 +26,21 @@ impl Marker {
     }
 }
 
+impl fmt::Display for Marker {
+    /// Combine the markers into one expression.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0.as_slice() {
+            [m] => write!(f, "{}", m),
+            markers => {
+                let parts: Vec<_> = markers.iter()
+                    .map(|m| format!("({})", m))
+                    .collect();
+                write!(f, "{}", parts.join(" or "))
+            },
+        }
+    }
+}
+
 impl From<Vec<String>> for Marker {
     fn from(v: Vec<String>) -> Self {
         Self(v)
@@ -40,6 +56,14 @@ impl IntoIterator for Marker {
     }
 }
 
+impl Serialize for Marker {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.collect_seq(self.0.iter())
+    }
+}
+
 impl<'de> Deserialize<'de> for Marker {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where D: Deserializer<'de>
@@ -89,6 +113,7 @@ impl<'a> Iterator for IterPackageDependency<'a> {
 pub struct Dependency {
     key: String,
     python: Option<PythonPackage>,
+    markers: Option<Marker>,
     dependencies: Vec<(DependencyCell, Option<Marker>)>,
 }
 
@@ -101,15 +126,47 @@ impl Dependency {
         self.python.as_ref()
     }
 
+    /// Markers on the package itself. The package is only installed if any
+    /// of them evaluates to true, regardless of markers on edges to it.
+    pub fn markers(&self) -> Option<&Marker> {
+        self.markers.as_ref()
+    }
+
     pub fn dependencies(&self) -> IterPackageDependency {
         IterPackageDependency(self.dependencies.iter())
     }
 }
 
+impl Serialize for Dependency {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let dependencies: BTreeMap<String, Option<&Marker>> = self
+            .dependencies
+            .iter()
+            .map(|(d, m)| (d.borrow().key.to_string(), m.as_ref()))
+            .collect();
+
+        let mut map = serializer.serialize_map(None)?;
+        if !dependencies.is_empty() {
+            map.serialize_entry("dependencies", &dependencies)?;
+        }
+        if let Some(ref markers) = self.markers {
+            map.serialize_entry("markers", markers)?;
+        }
+        if let Some(ref python) = self.python {
+            map.serialize_entry("python", python)?;
+        }
+        map.end()
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub(super) struct DependencyEntry {
     python: Option<PythonPackageEntry>,
 
+    markers: Option<Marker>,
+
     #[serde(default)]
     dependencies: HashMap<String, Option<Marker>>,
 }
@@ -127,6 +184,10 @@ impl DependencyEntry {
         }).transpose()
     }
 
+    pub fn take_markers(&mut self) -> Option<Marker> {
+        self.markers.take()
+    }
+
     pub fn into_dependencies(self) -> HashMap<String, Option<Marker>> {
         self.dependencies
     }
@@ -141,6 +202,21 @@ impl<'a> Iterator for IterDependency<'a> {
     }
 }
 
+/// Error connecting two dependency entries.
+#[derive(Debug, Eq, PartialEq)]
+pub enum DependenceError {
+    /// The entry with this key does not exist.
+    NotFound(String),
+    /// The entry with this key is borrowed elsewhere and can't be modified.
+    BorrowConflict(String),
+}
+
+/// Whether a dependency key identifies a section, i.e. the default section
+/// (empty key) or an extra (key in brackets).
+fn is_section(key: &str) -> bool {
+    key.is_empty() || (key.starts_with('[') && key.ends_with(']'))
+}
+
 #[derive(Default)]
 pub struct Dependencies(HashMap<String, DependencyCell>);
 
@@ -157,11 +233,172 @@ impl Dependencies {
         self.0.get(&format!("[{}]", extra)).map(|r| r.borrow())
     }
 
-    #[allow(dead_code)]
+    /// Names of extra sections, sorted.
+    pub fn extras(&self) -> impl Iterator<Item=&str> {
+        let mut names: Vec<_> = self.0.keys()
+            .filter(|k| !k.is_empty() && is_section(k))
+            .map(|k| &k[1..k.len() - 1])
+            .collect();
+        names.sort_unstable();
+        names.into_iter()
+    }
+
+    /// Look up a package entry by its key. Sections are not returned.
+    pub fn package(&self, key: &str) -> Option<Ref<Dependency>> {
+        if is_section(key) {
+            return None;
+        }
+        self.0.get(key).map(|r| r.borrow())
+    }
+
     pub fn iter(&self) -> IterDependency {
         IterDependency(self.0.iter())
     }
 
+    /// Dependencies carrying a Python package, sorted by canonical name.
+    ///
+    /// Entries with the same canonical name (e.g. aliases) are ordered by key
+    /// so the result is always deterministic.
+    pub fn sorted_packages(&self) -> Vec<DependencyRef> {
+        let mut packages: Vec<_> = self.iter()
+            .filter_map(|(k, d)| {
+                let name = d.python()?.canonical_name();
+                Some((name, k, d))
+            })
+            .collect();
+        packages.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+        packages.into_iter().map(|(_, _, d)| d).collect()
+    }
+
+    /// Keys of dependencies reachable from any section, sections included.
+    fn reachable_keys(&self) -> HashSet<String> {
+        let mut stack: Vec<_> = self.0.iter()
+            .filter(|(k, _)| is_section(k))
+            .map(|(_, v)| Rc::clone(v))
+            .collect();
+        let mut seen = HashSet::new();
+        while let Some(cell) = stack.pop() {
+            let dep = cell.borrow();
+            if !seen.insert(dep.key.clone()) {
+                continue;
+            }
+            stack.extend(dep.dependencies.iter().map(|(d, _)| Rc::clone(d)));
+        }
+        seen
+    }
+
+    /// Find a dependency cycle in the graph.
+    ///
+    /// Returns keys on the cycle, starting and ending with the same key, or
+    /// `None` if the graph is acyclic. Keys are walked in sorted order, so
+    /// the result is deterministic.
+    pub fn find_cycle(&self) -> Option<Vec<String>> {
+        fn visit(
+            dep: &Dependency,
+            path: &mut Vec<String>,
+            done: &mut HashSet<String>,
+        ) -> Option<Vec<String>> {
+            if let Some(i) = path.iter().position(|k| k == &dep.key) {
+                let mut cycle = path[i..].to_vec();
+                cycle.push(dep.key.clone());
+                return Some(cycle);
+            }
+            if done.contains(&dep.key) {
+                return None;
+            }
+            path.push(dep.key.clone());
+            let mut children: Vec<_> = dep.dependencies.iter()
+                .map(|(d, _)| d.borrow())
+                .collect();
+            children.sort_by(|a, b| a.key.cmp(&b.key));
+            for child in children {
+                if let Some(cycle) = visit(&child, path, done) {
+                    return Some(cycle);
+                }
+            }
+            path.pop();
+            done.insert(dep.key.clone());
+            None
+        }
+
+        let mut keys: Vec<_> = self.0.keys().collect();
+        keys.sort();
+        let mut done = HashSet::new();
+        for key in keys {
+            let dep = self.0[key].borrow();
+            if let Some(cycle) = visit(&dep, &mut vec![], &mut done) {
+                return Some(cycle);
+            }
+        }
+        None
+    }
+
+    /// Length of the longest dependency chain below the entry `key`.
+    ///
+    /// Edges closing a cycle are ignored. The depth of each entry is only
+    /// computed once, so entries in a cycle get the depth found when the walk
+    /// first reaches them. Children are walked in key order, so the result is
+    /// always the same for a given lock file. Returns 0 if `key` is not found.
+    pub fn max_depth(&self, key: &str) -> usize {
+        fn visit(
+            dep: &Dependency,
+            visiting: &mut HashSet<String>,
+            memo: &mut HashMap<String, usize>,
+        ) -> usize {
+            if let Some(d) = memo.get(&dep.key) {
+                return *d;
+            }
+            visiting.insert(dep.key.clone());
+            let mut d = 0;
+            let mut children: Vec<_> = dep.dependencies.iter()
+                .map(|(d, _)| d.borrow())
+                .collect();
+            children.sort_by(|a, b| a.key.cmp(&b.key));
+            for child in children {
+                if !visiting.contains(&child.key) {
+                    d = d.max(1 + visit(&child, visiting, memo));
+                }
+            }
+            visiting.remove(&dep.key);
+            memo.insert(dep.key.clone(), d);
+            d
+        }
+
+        match self.0.get(key) {
+            Some(cell) => {
+                visit(&cell.borrow(), &mut HashSet::new(), &mut HashMap::new())
+            },
+            None => 0,
+        }
+    }
+
+    /// Keys of packages not reachable from any section, sorted.
+    ///
+    /// These entries are never installed, and can be safely removed from the
+    /// lock file.
+    pub fn unreferenced_packages(&self) -> Vec<String> {
+        let reachable = self.reachable_keys();
+        let mut keys: Vec<_> = self.0.keys()
+            .filter(|k| !is_section(k) && !reachable.contains(k.as_str()))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Pin packages to versions, keyed by their canonical names.
+    ///
+    /// This modifies dependencies in-place (they are in `RefCell`s).
+    pub fn pin_versions(&self, versions: &HashMap<String, String>) {
+        for cell in self.0.values() {
+            if let Some(ref mut python) = cell.borrow_mut().python {
+                if let Some(v) = versions.get(&python.canonical_name()) {
+                    python.set_version(v);
+                }
+            }
+        }
+    }
+
     pub fn add_dependency(
         &mut self,
         key: &str,
@@ -170,28 +407,57 @@ impl Dependencies {
         let dep = Dependency {
             key: key.to_string(),
             python,
+            markers: None,
             dependencies: vec![],
         };
         self.0.insert(key.to_string(), Rc::new(RefCell::new(dep)))
     }
 
+    /// Set markers on the package itself. Returns false if `key` is not
+    /// found.
+    pub fn set_markers(&self, key: &str, markers: Option<Marker>) -> bool {
+        match self.0.get(key) {
+            Some(cell) => {
+                cell.borrow_mut().markers = markers;
+                true
+            },
+            None => false,
+        }
+    }
+
     pub fn add_dependence(
         &mut self,
         dependent: &str,
         depended: &str,
         marker: Option<Marker>,
-    ) -> Result<(), String> {
+    ) -> Result<(), DependenceError> {
         let depended = self.0.get(depended)
-            .ok_or_else(|| depended.to_string())?
+            .ok_or_else(|| DependenceError::NotFound(depended.to_string()))?
             .clone();
         let mut dependent = self.0.get(dependent)
-            .ok_or_else(|| dependent.to_string())?
-            .borrow_mut();  // TODO: Return an error if this borrow fails?
+            .ok_or_else(|| DependenceError::NotFound(dependent.to_string()))?
+            .try_borrow_mut()
+            .map_err(|_| {
+                DependenceError::BorrowConflict(dependent.to_string())
+            })?;
         dependent.dependencies.push((depended, marker));
         Ok(())
     }
 }
 
+impl Serialize for Dependencies {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let sorted: BTreeMap<_, _> = self.0.iter().collect();
+        let mut map = serializer.serialize_map(Some(sorted.len()))?;
+        for (k, v) in sorted {
+            map.serialize_entry(k, &*v.borrow())?;
+        }
+        map.end()
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -269,6 +535,119 @@ mod tests {
         assert_eq!(entry.python, None);
     }
 
+    #[test]
+    fn test_sorted_packages() {
+        let mut dependencies = Dependencies::new();
+        for (key, name) in &[
+            ("zope-interface", "zope.interface"),
+            ("", ""),
+            ("django", "Django"),
+            ("a-b", "A_B"),
+        ] {
+            let package = if name.is_empty() {
+                None
+            } else {
+                let entry = PythonPackageEntry::new_versioned(name, "1", None);
+                Some(entry.into_python_package::<serde_json::Error>(
+                    &Sources::default(), None,
+                ).unwrap())
+            };
+            dependencies.add_dependency(key, package);
+        }
+
+        let names = |deps: &Dependencies| -> Vec<String> {
+            deps.sorted_packages().iter()
+                .map(|d| d.python().unwrap().canonical_name())
+                .collect()
+        };
+        assert_eq!(names(&dependencies), ["a-b", "django", "zope-interface"]);
+        assert_eq!(names(&dependencies), names(&dependencies));
+    }
+
+    #[test]
+    fn test_unreferenced_packages() {
+        let mut dependencies = Dependencies::new();
+        for key in &["", "[dev]", "foo", "bar", "baz", "orphan"] {
+            dependencies.add_dependency(key, None);
+        }
+        dependencies.add_dependence("", "foo", None).unwrap();
+        dependencies.add_dependence("foo", "bar", None).unwrap();
+        dependencies.add_dependence("[dev]", "baz", None).unwrap();
+        dependencies.add_dependence("orphan", "foo", None).unwrap();
+
+        assert_eq!(dependencies.unreferenced_packages(), vec!["orphan"]);
+    }
+
+    #[test]
+    fn test_add_dependence_self_edge() {
+        let mut dependencies = Dependencies::new();
+        dependencies.add_dependency("a", None);
+        dependencies.add_dependence("a", "a", None).unwrap();
+        assert_eq!(
+            dependencies.add_dependence("a", "b", None),
+            Err(DependenceError::NotFound(String::from("b"))),
+        );
+
+        let a = dependencies.package("a").unwrap();
+        let keys: Vec<_> = a.dependencies().map(|(d, _)| d.key().to_string())
+            .collect();
+        assert_eq!(keys, vec!["a"]);
+        drop(a);
+        assert_eq!(
+            dependencies.find_cycle(),
+            Some(vec![String::from("a"), String::from("a")]),
+        );
+        assert_eq!(dependencies.max_depth("a"), 0);
+    }
+
+    #[test]
+    fn test_add_dependence_borrow_conflict() {
+        let mut dependencies = Dependencies::new();
+        dependencies.add_dependency("a", None);
+        let cell = dependencies.0["a"].clone();
+        let _borrowed = cell.borrow();
+        assert_eq!(
+            dependencies.add_dependence("a", "a", None),
+            Err(DependenceError::BorrowConflict(String::from("a"))),
+        );
+    }
+
+    #[test]
+    fn test_extras() {
+        let mut dependencies = Dependencies::new();
+        for key in &["", "[test]", "foo", "[dev]", "[bar"] {
+            dependencies.add_dependency(key, None);
+        }
+        let extras: Vec<_> = dependencies.extras().collect();
+        assert_eq!(extras, vec!["dev", "test"]);
+        assert_eq!(Dependencies::new().extras().count(), 0);
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut dependencies = Dependencies::new();
+        for key in &["", "a", "b", "c", "d", "e", "f"] {
+            dependencies.add_dependency(key, None);
+        }
+        // Diamond "" -> a/b -> c, then chain c -> d -> e.
+        for (p, c) in &[
+            ("", "a"), ("", "b"), ("a", "c"), ("b", "c"),
+            ("c", "d"), ("d", "e"),
+            ("", "f"),
+        ] {
+            dependencies.add_dependence(p, c, None).unwrap();
+        }
+        assert_eq!(dependencies.max_depth(""), 4);
+        assert_eq!(dependencies.max_depth("b"), 3);
+        assert_eq!(dependencies.max_depth("e"), 0);
+        assert_eq!(dependencies.max_depth("missing"), 0);
+
+        // The cycle-closing edge e -> a is ignored.
+        dependencies.add_dependence("e", "a", None).unwrap();
+        assert_eq!(dependencies.max_depth(""), 4);
+        assert_eq!(dependencies.max_depth("a"), 3);
+    }
+
     #[test]
     fn test_dependency_entry_no_dependencies() {
         let entry: DependencyEntry = from_str("{}").unwrap();
@@ -9,6 +9,7 @@ use serde::de::{
     Unexpected,
     Visitor,
 };
+use serde::ser::{Serialize, Serializer};
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Hash {
@@ -60,6 +61,14 @@ impl<'de> Deserialize<'de> for Hash {
     }
 }
 
+impl Serialize for Hash {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.collect_str(self)
+    }
+}
+
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct Hashes(HashSet<Hash>);
 
@@ -69,6 +78,16 @@ impl Hashes {
     }
 }
 
+impl Serialize for Hashes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut hashes: Vec<_> = self.0.iter().map(Hash::to_string).collect();
+        hashes.sort();
+        serializer.collect_seq(hashes)
+    }
+}
+
 impl<'de> Deserialize<'de> for Hashes {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where D: Deserializer<'de>
@@ -1,5 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{self, Formatter};
+use std::io::Write;
 
 use serde::de::{
     self,
@@ -8,9 +9,13 @@ use serde::de::{
     MapAccess,
     Visitor,
 };
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::ser::PrettyFormatter;
+use url::Url;
 
 use super::{
     Dependencies,
+    DependenceError,
     DependencyEntry,
     Hashes,
     Sources,
@@ -22,7 +27,16 @@ pub struct Lock {
 }
 
 impl<'a> Lock {
-    #[allow(dead_code)]
+    /// A lock with an empty default section, and PyPI as the only source.
+    pub fn empty() -> Self {
+        let mut sources = Sources::default();
+        let url = Url::parse("https://pypi.org/simple").unwrap();
+        sources.add("pypi", url, false);
+        let mut dependencies = Dependencies::new();
+        dependencies.add_dependency("", None);
+        Self { sources, dependencies }
+    }
+
     pub fn sources(&self) -> &Sources {
         &self.sources
     }
@@ -30,6 +44,34 @@ impl<'a> Lock {
     pub fn dependencies(&self) -> &Dependencies {
         &self.dependencies
     }
+
+    /// Write the lock file in the normalized format.
+    pub fn write<W>(&self, mut writer: W) -> serde_json::Result<()>
+        where W: Write
+    {
+        let formatter = PrettyFormatter::with_indent(b"    ");
+        let mut ser = serde_json::Serializer::with_formatter(
+            &mut writer, formatter,
+        );
+        self.serialize(&mut ser)?;
+        writeln!(writer).map_err(serde_json::Error::io)
+    }
+}
+
+impl Serialize for Lock {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let hashes: BTreeMap<_, _> = self.dependencies.iter()
+            .filter_map(|(k, d)| Some((k, d.python()?.hashes()?.clone())))
+            .collect();
+
+        let mut map = serializer.serialize_map(Some(3))?;
+        map.serialize_entry("dependencies", &self.dependencies)?;
+        map.serialize_entry("hashes", &hashes)?;
+        map.serialize_entry("sources", &self.sources)?;
+        map.end()
+    }
 }
 
 impl<'de> Deserialize<'de> for Lock {
@@ -97,6 +139,7 @@ impl<'de> Deserialize<'de> for Lock {
                 for (k, mut v) in dents.into_iter() {
                     let p = v.swap_out_python(&sources, hashes.remove(&k))?;
                     dependencies.add_dependency(&k, p);
+                    dependencies.set_markers(&k, v.take_markers());
                     links.push((k, v.into_dependencies()));
                 }
 
@@ -104,10 +147,20 @@ impl<'de> Deserialize<'de> for Lock {
                 for (p, links) in links.into_iter() {
                     for (c, m) in links.into_iter() {
                         let result = dependencies.add_dependence(&p, &c, m);
-                        if let Err(k) = result {
-                            return Err(de::Error::custom(format!(
-                                "unresolvable dependency name {:?}", k,
-                            )));
+                        match result {
+                            Ok(()) => {},
+                            Err(DependenceError::NotFound(k)) => {
+                                return Err(de::Error::custom(format!(
+                                    "unresolvable dependency name {:?} \
+                                     (required by {:?})",
+                                    k, p,
+                                )));
+                            },
+                            Err(DependenceError::BorrowConflict(k)) => {
+                                return Err(de::Error::custom(format!(
+                                    "dependency entry {:?} is in use", k,
+                                )));
+                            },
                         }
                     }
                 }
@@ -123,7 +176,7 @@ impl<'de> Deserialize<'de> for Lock {
 mod tests {
     use super::*;
     use std::collections::HashSet;
-    use serde_json::from_str;
+    use serde_json::{from_str, Value};
 
     #[test]
     fn test_simple_dependency_graph() {
@@ -162,4 +215,111 @@ mod tests {
             (String::from("foo"), true),
         ].iter().cloned().collect::<HashSet<_>>());
     }
+
+    #[test]
+    fn test_unresolvable_dependency() {
+        let result = from_str::<Lock>(r#"{
+            "dependencies": {
+                "": {"dependencies": {"foo": null}},
+                "foo": {"dependencies": {"bar": null}}
+            }
+        }"#);
+        let message = result.err().unwrap().to_string();
+        assert!(message.starts_with(
+            "unresolvable dependency name \"bar\" (required by \"foo\")",
+        ), "{}", message);
+    }
+
+    static ROUND_TRIP_JSON: &str = r#"{
+        "dependencies": {
+            "": {"dependencies": {"bar": null, "foo": ["os_name == 'nt'"]}},
+            "bar": {
+                "python": {
+                    "name": "Bar",
+                    "source": "pypi",
+                    "version": "2.2.0"
+                }
+            },
+            "foo": {"python": {"name": "foo", "version": "1.0"}},
+            "qux": {
+                "markers": ["sys_platform == 'win32'"],
+                "python": {
+                    "name": "qux",
+                    "no_verify_ssl": true,
+                    "url": "https://example.com/qux.zip"
+                }
+            }
+        },
+        "hashes": {
+            "bar": ["sha256:1234", "sha256:abcd"],
+            "foo": ["sha256:5678"]
+        },
+        "sources": {
+            "pypi": {"url": "https://pypi.org/simple"}
+        }
+    }"#;
+
+    #[test]
+    fn test_write_round_trip() {
+        let lock: Lock = from_str(ROUND_TRIP_JSON).unwrap();
+        let mut out = vec![];
+        lock.write(&mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("{\n    \"dependencies\": {\n"));
+        assert!(out.ends_with("}\n"));
+        assert_eq!(
+            from_str::<Value>(&out).unwrap(),
+            from_str::<Value>(ROUND_TRIP_JSON).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_write_round_trip_named_source() {
+        static JSON: &str = r#"{
+            "dependencies": {
+                "": {"dependencies": {"foo": null}},
+                "foo": {"python": {
+                    "name": "foo",
+                    "source": "primary",
+                    "version": "1.0"
+                }}
+            },
+            "hashes": {},
+            "sources": {
+                "primary": {"name": "pypi", "url": "https://pypi.org/simple"}
+            }
+        }"#;
+
+        let lock: Lock = from_str(JSON).unwrap();
+        let mut out = vec![];
+        lock.write(&mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            from_str::<Value>(&out).unwrap(),
+            from_str::<Value>(JSON).unwrap(),
+        );
+        from_str::<Lock>(&out).unwrap();
+    }
+
+    #[test]
+    fn test_pin_versions() {
+        let lock: Lock = from_str(ROUND_TRIP_JSON).unwrap();
+        let versions = [
+            (String::from("bar"), String::from("2.2.0")),
+            (String::from("foo"), String::from("1.0.1")),
+            (String::from("qux"), String::from("3.0")),
+        ].iter().cloned().collect();
+        lock.dependencies().pin_versions(&versions);
+
+        let mut expected = from_str::<Value>(ROUND_TRIP_JSON).unwrap();
+        expected["dependencies"]["foo"]["python"]["version"] = "1.0.1".into();
+        expected["hashes"].as_object_mut().unwrap().remove("foo");
+
+        let mut out = vec![];
+        lock.write(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(from_str::<Value>(&out).unwrap(), expected);
+    }
 }
@@ -4,12 +4,13 @@ mod locks;
 mod pypackages;
 mod sources;
 
-use self::deps::DependencyEntry;
+use self::deps::{DependenceError, DependencyEntry};
 
 pub use self::deps::{Dependencies, Dependency, Marker};
 pub use self::hashes::{Hash, Hashes};
 pub use self::locks::Lock;
 pub use self::pypackages::{
+    canonicalize_name,
     Package as PythonPackage,
     Specifier as PythonPackageSpecifier,
 };
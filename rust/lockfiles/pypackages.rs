@@ -1,12 +1,23 @@
 use std::path::PathBuf;
 use std::rc::Rc;
 
+use regex::Regex;
 use serde::de;
+use serde::ser::{Serialize, SerializeMap, Serializer};
 use url::Url;
 
 use super::{Hashes, Source, Sources};
 
 
+lazy_static! {
+    static ref NAME_SEPARATOR_RE: Regex = Regex::new(r"[-_.]+").unwrap();
+}
+
+// Normalize the name as specified in PEP 503.
+pub fn canonicalize_name(name: &str) -> String {
+    NAME_SEPARATOR_RE.replace_all(name, "-").to_lowercase()
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Specifier {
     Version(String, Option<Rc<Source>>),
@@ -23,35 +34,96 @@ pub struct Package {
 }
 
 impl Package {
-    #[cfg(test)]
     pub fn name(&self) -> &str {
         &self.name
     }
 
-    pub fn to_requirement_txt(&self) -> (bool, String) {
+    pub fn canonical_name(&self) -> String {
+        canonicalize_name(&self.name)
+    }
+
+    pub fn hashes(&self) -> Option<&Hashes> {
+        self.hashes.as_ref()
+    }
+
+    /// Pin a named requirement to the given version.
+    ///
+    /// Hashes are dropped if the version changes, since they won't match
+    /// artifacts of the new version. Other kinds of requirements are not
+    /// affected.
+    pub fn set_version(&mut self, version: &str) {
+        if let Specifier::Version(ref mut v, _) = self.specifier {
+            if v != version {
+                *v = version.to_string();
+                self.hashes = None;
+            }
+        }
+    }
+
+    /// The version of a named requirement.
+    pub fn version(&self) -> Option<&str> {
+        match self.specifier {
+            Specifier::Version(ref version, _) => Some(version),
+            _ => None,
+        }
+    }
+
+    /// Human-readable form of the specifier, e.g. `== 1.0` for a named
+    /// requirement, or the URL for a URL requirement.
+    pub fn specifier_display(&self) -> String {
+        match self.specifier {
+            Specifier::Version(ref version, _) => format!("== {}", version),
+            Specifier::Url(ref url, _) => url.to_string(),
+            Specifier::Path(ref path) => path.to_string_lossy().into_owned(),
+            Specifier::Vcs(ref url, ref rev) => format!("{}@{}", url, rev),
+        }
+    }
+
+    /// The source of a named requirement, if specified.
+    pub fn source(&self) -> Option<&Rc<Source>> {
+        match self.specifier {
+            Specifier::Version(_, ref source) => source.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Format the package as a line in requirements.txt.
+    ///
+    /// If a named requirement does not specify a source, all `sources` are
+    /// used as indexes in priority order.
+    pub fn to_requirement_txt(&self, sources: &Sources) -> (bool, String) {
+        let (hashed, line) = self.to_requirement_line();
+        let mut args = vec![line];
+        args.extend(self.pip_options(sources));
+        (hashed, args.join(" "))
+    }
+
+    /// pip options needed to install the package, i.e. indexes and trusted
+    /// hosts to find it.
+    pub fn pip_options(&self, sources: &Sources) -> Vec<String> {
+        match self.specifier {
+            Specifier::Version(..) => sources.index_options(self.source()),
+            Specifier::Url(ref url, true) => match url.host_str() {
+                Some(host) => vec![format!("--trusted-host={}", host)],
+                None => vec![],
+            },
+            _ => vec![],
+        }
+    }
+
+    /// Format the package as a line in requirements.txt, without pip
+    /// options. The caller is responsible to supply them.
+    pub fn to_requirement_line(&self) -> (bool, String) {
         let mut args = vec![];
 
         match self.specifier {
-            Specifier::Version(ref version, ref source) => {
+            Specifier::Version(ref version, _) => {
                 args.push(format!("{} == {}", self.name, version));
-                if let Some(ref source) = source {
-                    args.push(format!("--index-url={}", source.base_url()));
-                    if source.no_verify_ssl() {
-                        if let Some(host) = source.base_url().host_str() {
-                            args.push(format!("--trusted-host={}", host));
-                        }
-                    }
-                }
             },
-            Specifier::Url(ref url, no_verify_ssl) => {
+            Specifier::Url(ref url, _) => {
                 let mut url = url.clone();
-                url.set_fragment(Some(&format!("egg={}", self.name)));
+                url.set_fragment(Some(&egg_fragment(&url, &self.name)));
                 args.push(url.to_string());
-                if no_verify_ssl {
-                    if let Some(host) = url.host_str() {
-                        args.push(format!("--trusted-host={}", host));
-                    }
-                }
             },
             Specifier::Path(ref path) => {
                 // TODO: Do a better job handling non-representable paths?
@@ -63,7 +135,7 @@ impl Package {
 
                 let mut url = url.clone();
                 url.set_path(&path);
-                url.set_fragment(Some(&format!("egg={}", self.name)));
+                url.set_fragment(Some(&egg_fragment(&url, &self.name)));
                 args.push(url.to_string());
             },
         }
@@ -79,13 +151,64 @@ impl Package {
     }
 }
 
+// Fragment for a URL requirement line. Existing `key=value` pairs (e.g.
+// `sha256=` or `subdirectory=`) are kept, and `egg=` is prepended unless
+// already present.
+fn egg_fragment(url: &Url, name: &str) -> String {
+    let existing = url.fragment().unwrap_or("");
+    let pairs: Vec<_> = existing.split('&')
+        .filter(|p| !p.is_empty())
+        .collect();
+    if pairs.iter().any(|p| p.starts_with("egg=")) {
+        return existing.to_owned();
+    }
+    let mut fragment = format!("egg={}", name);
+    for pair in pairs {
+        fragment.push('&');
+        fragment.push_str(pair);
+    }
+    fragment
+}
+
+impl Serialize for Package {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        // Keys are emitted in sorted order, as recommended by the format.
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("name", &self.name)?;
+        match self.specifier {
+            Specifier::Version(ref version, ref source) => {
+                if let Some(ref source) = source {
+                    map.serialize_entry("source", source.key())?;
+                }
+                map.serialize_entry("version", version)?;
+            },
+            Specifier::Url(ref url, no_verify_ssl) => {
+                if no_verify_ssl {
+                    map.serialize_entry("no_verify_ssl", &true)?;
+                }
+                map.serialize_entry("url", url.as_str())?;
+            },
+            Specifier::Path(ref path) => {
+                map.serialize_entry("path", path)?;
+            },
+            Specifier::Vcs(ref url, ref rev) => {
+                map.serialize_entry("rev", rev)?;
+                map.serialize_entry("vcs", url.as_str())?;
+            },
+        }
+        map.end()
+    }
+}
+
 #[derive(Debug, Deserialize, Eq, PartialEq)]
 #[serde(untagged)]
 enum EntrySpecifier {
     Version { version: String, source: Option<String> },
     Url {
         #[serde(with = "url_serde")] url: Url,
-        #[serde(rename = "no_verify_ssl")] trust: bool,
+        #[serde(default, rename = "no_verify_ssl")] trust: bool,
     },
     Path { path: PathBuf },
     Vcs { #[serde(with = "url_serde")] vcs: Url, rev: String },
@@ -141,6 +264,13 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_canonicalize_name() {
+        assert_eq!(canonicalize_name("Django"), "django");
+        assert_eq!(canonicalize_name("zope.interface"), "zope-interface");
+        assert_eq!(canonicalize_name("Foo__Bar-.baz"), "foo-bar-baz");
+    }
+
     #[test]
     fn test_entry() {
         static JSON: &str = r#"{
@@ -154,4 +284,112 @@ mod tests {
             "certifi", "2017.7.27.1", Some("default"),
         ));
     }
+
+    #[test]
+    fn test_name_and_specifier() {
+        let sources = Sources::default();
+        let package = Entry::new_versioned("Foo.Bar", "1.0", None)
+            .into_python_package::<serde_json::Error>(&sources, None)
+            .unwrap();
+        assert_eq!(package.name(), "Foo.Bar");
+        assert_eq!(
+            &package.specifier,
+            &Specifier::Version(String::from("1.0"), None),
+        );
+    }
+
+    #[test]
+    fn test_to_requirement_txt_source_order() {
+        static JSON: &str = r#"{
+            "pypi": {"url": "https://pypi.org/simple", "priority": 1},
+            "private": {
+                "url": "https://example.com/simple",
+                "no_verify_ssl": true,
+                "priority": 0
+            }
+        }"#;
+        let sources: Sources = from_str(JSON).unwrap();
+
+        let package = Entry::new_versioned("foo", "1.0", None)
+            .into_python_package::<serde_json::Error>(&sources, None)
+            .unwrap();
+        assert_eq!(package.to_requirement_txt(&sources), (false, String::from(
+            "foo == 1.0 \
+             --index-url=https://example.com/simple \
+             --trusted-host=example.com \
+             --extra-index-url=https://pypi.org/simple",
+        )));
+
+        let package = Entry::new_versioned("foo", "1.0", Some("pypi"))
+            .into_python_package::<serde_json::Error>(&sources, None)
+            .unwrap();
+        assert_eq!(package.to_requirement_txt(&sources), (false, String::from(
+            "foo == 1.0 --index-url=https://pypi.org/simple",
+        )));
+    }
+
+    #[test]
+    fn test_vcs_entry() {
+        static JSON: &str = r#"{
+            "name": "pip",
+            "vcs": "git+https://github.com/pypa/pip.git",
+            "rev": "abc123"
+        }"#;
+
+        let sources = Sources::default();
+        let entry: Entry = from_str(JSON).unwrap();
+        let package = entry
+            .into_python_package::<serde_json::Error>(&sources, None)
+            .unwrap();
+        assert_eq!(&package.specifier, &Specifier::Vcs(
+            Url::parse("git+https://github.com/pypa/pip.git").unwrap(),
+            String::from("abc123"),
+        ));
+        assert_eq!(
+            package.to_requirement_txt(&sources),
+            (false, String::from(
+                "git+https://github.com/pypa/pip.git@abc123#egg=pip",
+            )),
+        );
+    }
+
+    #[test]
+    fn test_vcs_entry_subdirectory() {
+        static JSON: &str = r#"{
+            "name": "foo",
+            "vcs": "git+https://example.com/foo.git#subdirectory=src",
+            "rev": "abc123"
+        }"#;
+
+        let sources = Sources::default();
+        let entry: Entry = from_str(JSON).unwrap();
+        let package = entry
+            .into_python_package::<serde_json::Error>(&sources, None)
+            .unwrap();
+        assert_eq!(package.to_requirement_line(), (false, String::from(
+            "git+https://example.com/foo.git@abc123#egg=foo&subdirectory=src",
+        )));
+    }
+
+    #[test]
+    fn test_url_requirement_line_fragment() {
+        let line = |url: &str| Package {
+            name: String::from("foo"),
+            specifier: Specifier::Url(Url::parse(url).unwrap(), false),
+            hashes: None,
+        }.to_requirement_line().1;
+
+        assert_eq!(
+            line("https://example.com/foo.zip"),
+            "https://example.com/foo.zip#egg=foo",
+        );
+        assert_eq!(
+            line("https://example.com/foo.zip#subdirectory=x"),
+            "https://example.com/foo.zip#egg=foo&subdirectory=x",
+        );
+        assert_eq!(
+            line("https://example.com/foo.zip#egg=bar"),
+            "https://example.com/foo.zip#egg=bar",
+        );
+    }
 }
@@ -10,29 +10,98 @@ use serde::de::{
     Unexpected,
     Visitor,
 };
+use serde::ser::{Serialize, SerializeMap, Serializer};
 use url::Url;
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct Source {
+    key: String,
     name: String,
     base_url: Url,
     no_verify_ssl: bool,
+    priority: Option<u32>,
+    extra: bool,
 }
 
 impl Source {
+    /// Key of the source in the lock file. Packages refer to the source by
+    /// this.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Name of the source. This is the key unless declared otherwise.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn base_url(&self) -> &Url {
         &self.base_url
     }
     pub fn no_verify_ssl(&self) -> bool {
         self.no_verify_ssl
     }
+
+    /// Sources with lower values should be tried first.
+    pub fn priority(&self) -> Option<u32> {
+        self.priority
+    }
+
+    /// Whether this source is an additional index to PyPI, instead of a
+    /// replacement to it.
+    pub fn extra(&self) -> bool {
+        self.extra
+    }
+}
+
+impl Serialize for Source {
+    /// The key is not written, since it is the key of the entry. The name is
+    /// only written if it differs from the key.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        let mut map = serializer.serialize_map(None)?;
+        if self.name != self.key {
+            map.serialize_entry("name", &self.name)?;
+        }
+        if self.no_verify_ssl {
+            map.serialize_entry("no_verify_ssl", &true)?;
+        }
+        if let Some(priority) = self.priority {
+            map.serialize_entry("priority", &priority)?;
+        }
+        if self.extra {
+            map.serialize_entry("extra", &true)?;
+        }
+        map.serialize_entry("url", self.base_url.as_str())?;
+        map.end()
+    }
 }
 
-struct SourceEntry(Url, bool);
+/// Parse a URL usable as an index, i.e. an http(s) URL with a host, or a
+/// file URL.
+fn parse_url(s: &str) -> Option<Url> {
+    let url = Url::parse(s).ok()?;
+    match url.scheme() {
+        "http" | "https" if url.has_host() => Some(url),
+        "file" => Some(url),
+        _ => None,
+    }
+}
+
+struct SourceEntry(Url, bool, Option<u32>, bool, Option<String>);
 
 impl SourceEntry {
-    fn into_source(self, name: String) -> Source {
-        Source { name, base_url: self.0, no_verify_ssl: self.1 }
+    /// Build a source keyed by `key`, named by the entry's `name` if given.
+    fn into_source(self, key: String) -> Source {
+        Source {
+            name: self.4.unwrap_or_else(|| key.clone()),
+            key,
+            base_url: self.0,
+            no_verify_ssl: self.1,
+            priority: self.2,
+            extra: self.3,
+        }
     }
 }
 
@@ -42,7 +111,7 @@ impl<'de> Deserialize<'de> for SourceEntry {
     {
         #[derive(Deserialize)]
         #[serde(field_identifier, rename_all = "snake_case")]
-        enum Field { Url, NoVerifySsl }
+        enum Field { Url, NoVerifySsl, Priority, Extra, Name }
 
         struct SourceEntryVisitor;
 
@@ -50,7 +119,10 @@ impl<'de> Deserialize<'de> for SourceEntry {
             type Value = SourceEntry;
 
             fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
-                formatter.write_str("`url` or `no_ssl_verified`")
+                formatter.write_str(
+                    "`url`, `no_ssl_verified`, `priority`, `extra`, or \
+                     `name`",
+                )
             }
 
             fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
@@ -58,6 +130,9 @@ impl<'de> Deserialize<'de> for SourceEntry {
             {
                 let mut url: Option<String> = None;
                 let mut ssl: Option<bool> = None;
+                let mut priority: Option<u32> = None;
+                let mut extra: Option<bool> = None;
+                let mut name: Option<String> = None;
                 while let Some(key) = map.next_key()? {
                     match key {
                         Field::Url => {
@@ -74,15 +149,43 @@ impl<'de> Deserialize<'de> for SourceEntry {
                             }
                             ssl = Some(map.next_value()?);
                         },
+                        Field::Priority => {
+                            if priority.is_some() {
+                                return Err(de::Error::duplicate_field(
+                                    "priority",
+                                ));
+                            }
+                            priority = Some(map.next_value()?);
+                        },
+                        Field::Extra => {
+                            if extra.is_some() {
+                                return Err(de::Error::duplicate_field(
+                                    "extra",
+                                ));
+                            }
+                            extra = Some(map.next_value()?);
+                        },
+                        Field::Name => {
+                            if name.is_some() {
+                                return Err(de::Error::duplicate_field(
+                                    "name",
+                                ));
+                            }
+                            name = Some(map.next_value()?);
+                        },
                     }
                 }
 
                 let url = url.ok_or_else(|| de::Error::missing_field("url"))?;
-                let url = Url::parse(&url).map_err(|_| {
-                    de::Error::invalid_value(Unexpected::Str(&url), &"URL")
+                let url = parse_url(&url).ok_or_else(|| {
+                    de::Error::invalid_value(
+                        Unexpected::Str(&url),
+                        &"source URL must be absolute with http(s) scheme",
+                    )
                 })?;
                 let ssl = ssl.unwrap_or_default();
-                Ok(SourceEntry(url, ssl))
+                let extra = extra.unwrap_or_default();
+                Ok(SourceEntry(url, ssl, priority, extra, name))
             }
         }
         deserializer.deserialize_map(SourceEntryVisitor)
@@ -93,11 +196,28 @@ impl<'de> Deserialize<'de> for SourceEntry {
 pub struct Sources(HashMap<String, Rc<Source>>);
 
 impl Sources {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Sources keyed by name, ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item=(&str, &Rc<Source>)> {
+        let mut items: Vec<_> = self.0.iter()
+            .map(|(k, v)| (k.as_str(), v))
+            .collect();
+        items.sort_by_key(|&(k, _)| k);
+        items.into_iter()
+    }
+
     pub fn get(&self, key: &str) -> Option<Rc<Source>> {
         self.0.get(key).map(Clone::clone)
     }
 
-    #[allow(dead_code)]
     pub fn add<S>(
         &mut self,
         key: S,
@@ -107,9 +227,69 @@ impl Sources {
         where S: Into<String>
     {
         let key = key.into();
-        let source = Source { name: key.to_string(), base_url, no_verify_ssl };
+        let source = Source {
+            key: key.to_string(),
+            name: key.to_string(),
+            base_url,
+            no_verify_ssl,
+            priority: None,
+            extra: false,
+        };
         self.0.insert(key, Rc::new(source))
     }
+
+    /// pip options to find packages from a source.
+    ///
+    /// If `source` is not given, all sources are used in priority order.
+    /// The first non-extra source is the lock's default index, and replaces
+    /// PyPI as the main index; other sources are added as extra indexes. If
+    /// all sources are extra, no `--index-url` is given, so PyPI stays the
+    /// main index for packages without a source.
+    pub fn index_options(&self, source: Option<&Rc<Source>>) -> Vec<String> {
+        let indexes = match source {
+            Some(source) => vec![Rc::clone(source)],
+            None => self.ordered(),
+        };
+        let mut options = vec![];
+        let mut has_primary = false;
+        for source in indexes.iter() {
+            let url = source.base_url();
+            if has_primary || source.extra() {
+                options.push(format!("--extra-index-url={}", url));
+            } else {
+                options.push(format!("--index-url={}", url));
+                has_primary = true;
+            }
+            if source.no_verify_ssl() {
+                if let Some(host) = url.host_str() {
+                    options.push(format!("--trusted-host={}", host));
+                }
+            }
+        }
+        options
+    }
+
+    /// Sources in the order they should be tried.
+    ///
+    /// Sources with a priority come first, lowest value first. Others are
+    /// put after them, ordered by key so the result is stable.
+    pub fn ordered(&self) -> Vec<Rc<Source>> {
+        let mut sources: Vec<_> = self.0.values().cloned().collect();
+        sources.sort_by(|a, b| {
+            let ka = (a.priority().is_none(), a.priority(), a.key());
+            let kb = (b.priority().is_none(), b.priority(), b.key());
+            ka.cmp(&kb)
+        });
+        sources
+    }
+}
+
+impl Serialize for Sources {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.collect_map(self.iter().map(|(k, v)| (k, &**v)))
+    }
 }
 
 impl<'de> Deserialize<'de> for Sources {
@@ -153,9 +333,12 @@ mod tests {
     impl Source {
         fn new(name: &str, base_url: &str, no_verify_ssl: bool) -> Self {
             Self {
+                key: name.to_string(),
                 name: name.to_string(),
                 base_url: Url::parse(base_url).unwrap(),
-                no_verify_ssl
+                no_verify_ssl,
+                priority: None,
+                extra: false,
             }
         }
     }
@@ -181,4 +364,141 @@ mod tests {
             Source::new("alibaba", "https://mirrors.aliyun.com/simple", true),
         );
     }
+
+    #[test]
+    fn test_sources_round_trip() {
+        let mut sources = Sources::default();
+        assert!(sources.is_empty());
+        let url = Url::parse("https://example.com/simple").unwrap();
+        sources.add("private", url, true);
+        let url = Url::parse("https://pypi.org/simple").unwrap();
+        sources.add("pypi", url, false);
+
+        let json = serde_json::to_string(&sources).unwrap();
+        let loaded: Sources = from_str(&json).unwrap();
+        assert_eq!(loaded.len(), 2);
+        let keys: Vec<_> = loaded.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["private", "pypi"]);
+        for ((ka, va), (kb, vb)) in sources.iter().zip(loaded.iter()) {
+            assert_eq!(ka, kb);
+            assert_eq!(va, vb);
+            assert_eq!(va.name(), ka);
+        }
+    }
+
+    #[test]
+    fn test_source_name() {
+        static JSON: &str = r#"{
+            "primary": {"url": "https://pypi.org/simple", "name": "pypi"}
+        }"#;
+
+        let sources: Sources = from_str(JSON).unwrap();
+        let source = sources.get("primary").unwrap();
+        assert_eq!(source.key(), "primary");
+        assert_eq!(source.name(), "pypi");
+        assert!(sources.get("pypi").is_none());
+
+        let json: serde_json::Value = serde_json::to_value(&sources).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "primary": {"url": "https://pypi.org/simple", "name": "pypi"},
+        }));
+    }
+
+    #[test]
+    fn test_source_url_without_scheme() {
+        for url in &["pypi.org/simple", "pypi.org:443/simple"] {
+            let json = format!(r#"{{"pypi": {{"url": "{}"}}}}"#, url);
+            let e = from_str::<Sources>(&json).err().unwrap();
+            assert!(
+                e.to_string().contains("must be absolute with http(s)"),
+                "unexpected error {}", e,
+            );
+        }
+    }
+
+    #[test]
+    fn test_source_file_url() {
+        static JSON: &str = r#"{
+            "local": {"url": "file:///srv/wheelhouse"}
+        }"#;
+
+        let sources: Sources = from_str(JSON).unwrap();
+        let source = sources.get("local").unwrap();
+        assert_eq!(source.base_url().as_str(), "file:///srv/wheelhouse");
+    }
+
+    #[test]
+    fn test_file_index_options() {
+        static JSON: &str = r#"{
+            "local": {"url": "file:///srv/simple", "no_verify_ssl": true}
+        }"#;
+
+        // A file URL has no host to trust.
+        let sources: Sources = from_str(JSON).unwrap();
+        assert_eq!(
+            sources.index_options(None),
+            vec!["--index-url=file:///srv/simple"],
+        );
+    }
+
+    #[test]
+    fn test_index_options_all_extra() {
+        // E.g. converted from poetry.lock, where PyPI is always kept.
+        static JSON: &str = r#"{
+            "private": {
+                "url": "https://example.com/simple",
+                "priority": 0,
+                "extra": true
+            },
+            "mirror": {
+                "url": "https://mirror.example.com/simple",
+                "priority": 1,
+                "extra": true
+            }
+        }"#;
+
+        let sources: Sources = from_str(JSON).unwrap();
+        assert_eq!(sources.index_options(None), vec![
+            "--extra-index-url=https://example.com/simple",
+            "--extra-index-url=https://mirror.example.com/simple",
+        ]);
+    }
+
+    #[test]
+    fn test_sources_ordered() {
+        static JSON: &str = r#"{
+            "pypi": {"url": "https://pypi.org/simple", "priority": 1},
+            "private": {"url": "https://example.com/simple", "priority": 0},
+            "b": {"url": "https://b.example.com/simple"},
+            "a": {"url": "https://a.example.com/simple"}
+        }"#;
+
+        let sources: Sources = from_str(JSON).unwrap();
+        let names: Vec<_> = sources.ordered().iter()
+            .map(|s| s.key().to_string())
+            .collect();
+        assert_eq!(names, vec!["private", "pypi", "a", "b"]);
+    }
+
+    #[test]
+    fn test_source_extra() {
+        static JSON: &str = r#"{
+            "pypi": {"url": "https://pypi.org/simple"},
+            "private": {"url": "https://example.com/simple", "extra": true}
+        }"#;
+
+        let sources: Sources = from_str(JSON).unwrap();
+        assert!(!sources.0["pypi"].extra());
+        assert!(sources.0["private"].extra());
+
+        let private = sources.get("private");
+        assert_eq!(
+            sources.index_options(private.as_ref()),
+            vec!["--extra-index-url=https://example.com/simple"],
+        );
+        assert_eq!(sources.index_options(None), vec![
+            "--extra-index-url=https://example.com/simple",
+            "--index-url=https://pypi.org/simple",
+        ]);
+    }
 }
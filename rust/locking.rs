@@ -0,0 +1,540 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use regex::Regex;
+use serde_json::{self, Map, Value};
+use url::Url;
+
+use crate::lockfiles::{canonicalize_name, Lock};
+use crate::projects::{self, Project};
+use crate::requirements::{requirement_name, REQUIREMENTS_IN};
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidReportError(String),
+    LockWriteError(serde_json::Error),
+    ProjectError(projects::Error),
+    RequirementsNotFoundError(PathBuf),
+    ResolveCommandError(Option<i32>),
+    SystemError(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidReportError(ref s) => {
+                write!(f, "invalid installation report: {}", s)
+            },
+            Error::LockWriteError(ref e) => {
+                write!(f, "failed to write lock file: {}", e)
+            },
+            Error::ProjectError(ref e) => e.fmt(f),
+            Error::RequirementsNotFoundError(ref p) => {
+                write!(f, "pyproject.toml or requirements.in not found in \
+                           {:?}", p)
+            },
+            Error::ResolveCommandError(Some(c)) => {
+                write!(f, "dependency resolution failed with status {}", c)
+            },
+            Error::ResolveCommandError(None) => {
+                write!(f, "dependency resolution terminated by signal")
+            },
+            Error::SystemError(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::SystemError(e)
+    }
+}
+
+impl From<projects::Error> for Error {
+    fn from(e: projects::Error) -> Self {
+        Error::ProjectError(e)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+static DEFAULT_SOURCE_NAME: &str = "pypi";
+static DEFAULT_SOURCE_URL: &str = "https://pypi.org/simple";
+
+/// Split a PEP 508 requirement into its canonical name and marker.
+fn parse_requirement(s: &str) -> Option<(String, Option<String>)> {
+    let (spec, marker) = match s.find(';') {
+        Some(i) => (&s[..i], Some(s[i + 1..].trim().to_string())),
+        None => (s, None),
+    };
+    Some((requirement_name(spec)?, marker))
+}
+
+lazy_static! {
+    static ref EXTRA_RE: Regex = Regex::new(
+        r#"extra\s*==\s*(?:'([^']*)'|"([^"]*)")"#,
+    ).unwrap();
+}
+
+/// Split a marker into clauses joined by `and` outside of parentheses.
+///
+/// The marker is kept whole if it also has `or` outside of parentheses.
+fn marker_clauses(marker: &str) -> Vec<&str> {
+    let mut clauses = vec![];
+    let mut has_or = false;
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in marker.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if q == c => { quote = None; },
+            (Some(_), _) => {},
+            (None, '\'') | (None, '"') => { quote = Some(c); },
+            (None, '(') => { depth += 1; },
+            (None, ')') => { depth -= 1; },
+            (None, ' ') if depth == 0 && marker[i..].starts_with(" and ") => {
+                clauses.push(marker[start..i].trim());
+                start = i + " and ".len();
+            },
+            (None, ' ') if depth == 0 && marker[i..].starts_with(" or ") => {
+                has_or = true;
+            },
+            _ => {},
+        }
+    }
+    if has_or {
+        return vec![marker.trim()];
+    }
+    clauses.push(marker[start..].trim());
+    clauses
+}
+
+/// Match clauses about extras in `marker` against `requested_extras`.
+///
+/// A clause is about extras if it only compares `extra`, e.g. `extra == 'a'`
+/// or `(extra == 'a' or extra == 'b')`. `None` is returned if such a clause
+/// names none of the requested extras. Otherwise the rest of the marker is
+/// returned, without clauses about extras since pip has already taken
+/// requested extras into account when resolving.
+fn match_extras(marker: &str, requested_extras: &[String]) -> Option<String> {
+    let mut rest = vec![];
+    for clause in marker_clauses(marker) {
+        let names: Vec<_> = EXTRA_RE.captures_iter(clause)
+            .filter_map(|c| c.get(1).or_else(|| c.get(2)))
+            .map(|m| canonicalize_name(m.as_str()))
+            .collect();
+        let is_extra = !names.is_empty() && EXTRA_RE.replace_all(clause, "")
+            .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+            .all(|w| w.is_empty() || w == "or");
+        if !is_extra {
+            rest.push(clause);
+        } else if !names.iter().any(|n| requested_extras.contains(n)) {
+            return None;
+        }
+    }
+    Some(rest.join(" and "))
+}
+
+fn invalid<T>(what: &str) -> Result<T> {
+    Err(Error::InvalidReportError(what.to_string()))
+}
+
+/// Dependencies of a package, as described in its `Requires-Dist` metadata.
+///
+/// Only requirements found in `resolved` are included. Requirements with a
+/// marker referring to extras are only included if one of the extras is in
+/// `requested_extras` (canonicalized), with the rest of the marker kept.
+fn requires_dist(
+    metadata: &Value,
+    resolved: &HashSet<String>,
+    requested_extras: &[String],
+) -> Map<String, Value> {
+    let mut dependencies = Map::new();
+    let requirements = metadata.get("requires_dist")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    for requirement in requirements.iter().filter_map(Value::as_str) {
+        let (key, marker) = match parse_requirement(requirement) {
+            Some(v) => v,
+            None => continue,
+        };
+        if !resolved.contains(&key) {
+            continue;
+        }
+        let marker = match marker {
+            Some(m) => match match_extras(&m, requested_extras) {
+                Some(ref m) if m.is_empty() => Value::Null,
+                Some(m) => Value::Array(vec![Value::String(m)]),
+                None => { continue; },
+            },
+            None => Value::Null,
+        };
+        dependencies.insert(key, marker);
+    }
+    dependencies
+}
+
+/// Describe an installation report entry as a Python package in the lock.
+fn python_entry(
+    name: &str,
+    metadata: &Value,
+    info: &Value,
+    is_direct: bool,
+) -> Result<Value> {
+    let mut python = Map::new();
+    python.insert(String::from("name"), Value::from(name));
+
+    let url = match info.get("url").and_then(Value::as_str) {
+        Some(url) => url,
+        None => { return invalid("download_info without url"); },
+    };
+    if info.get("dir_info").is_some() {
+        let path = Url::parse(url).ok()
+            .and_then(|u| u.to_file_path().ok())
+            .ok_or_else(|| Error::InvalidReportError(url.to_string()))?;
+        python.insert(
+            String::from("path"),
+            path.to_str().map(Value::from).unwrap_or(Value::Null),
+        );
+    } else if let Some(vcs) = info.get("vcs_info") {
+        let kind = vcs.get("vcs").and_then(Value::as_str).unwrap_or("git");
+        let rev = vcs.get("commit_id").and_then(Value::as_str);
+        let vcs = format!("{}+{}", kind, url);
+        python.insert(String::from("vcs"), Value::from(vcs));
+        python.insert(
+            String::from("rev"),
+            rev.map(Value::from).unwrap_or(Value::Null),
+        );
+    } else if is_direct {
+        python.insert(String::from("url"), Value::from(url));
+    } else {
+        let version = metadata.get("version").and_then(Value::as_str);
+        python.insert(
+            String::from("version"),
+            version.map(Value::from).unwrap_or(Value::Null),
+        );
+    }
+    Ok(Value::Object(python))
+}
+
+/// Hashes of the downloaded artifact, in the lock file's format.
+fn artifact_hashes(info: &Value) -> Vec<Value> {
+    let archive = match info.get("archive_info") {
+        Some(v) => v,
+        None => { return vec![]; },
+    };
+    if let Some(hashes) = archive.get("hashes").and_then(Value::as_object) {
+        return hashes.iter()
+            .filter_map(|(k, v)| Some(format!("{}:{}", k, v.as_str()?)))
+            .map(Value::String)
+            .collect();
+    }
+    // Legacy single-hash format, e.g. "sha256=<value>".
+    archive.get("hash").and_then(Value::as_str)
+        .map(|h| vec![Value::String(h.replacen('=', ":", 1))])
+        .unwrap_or_default()
+}
+
+/// Build a lock from pip's installation report (`pip install --report`).
+///
+/// If the project at `root` itself is in the report, it is omitted from the
+/// lock, and its dependencies form the default section. Otherwise packages
+/// requested explicitly are put into the default section.
+fn lock_from_report(report: &Value, root: &Path) -> Result<Lock> {
+    let items = match report.get("install").and_then(Value::as_array) {
+        Some(v) => v,
+        None => { return invalid("missing install list"); },
+    };
+
+    let mut entries = vec![];
+    for item in items {
+        let metadata = match item.get("metadata") {
+            Some(v) => v,
+            None => { return invalid("missing metadata"); },
+        };
+        let name = match metadata.get("name").and_then(Value::as_str) {
+            Some(v) => v,
+            None => { return invalid("missing package name"); },
+        };
+        entries.push((canonicalize_name(name), name, metadata, item));
+    }
+    let resolved: HashSet<_> = entries.iter()
+        .map(|(k, _, _, _)| k.clone())
+        .collect();
+
+    let root_url = Url::from_directory_path(root).ok()
+        .map(|u| u.as_str().trim_end_matches('/').to_string());
+    let mut default = Map::new();
+    let mut dependencies = BTreeMap::new();
+    let mut hashes = Map::new();
+    for (key, name, metadata, item) in entries {
+        let info = item.get("download_info").unwrap_or(&Value::Null);
+        let extras: Vec<_> = item.get("requested_extras")
+            .and_then(Value::as_array)
+            .map(|v| v.iter().filter_map(Value::as_str))
+            .into_iter()
+            .flatten()
+            .map(canonicalize_name)
+            .collect();
+
+        let url = info.get("url").and_then(Value::as_str);
+        let is_root = info.get("dir_info").is_some()
+            && url.is_some()
+            && url == root_url.as_deref();
+        if is_root {
+            default.extend(requires_dist(metadata, &resolved, &extras));
+            continue;
+        }
+
+        let requested = item.get("requested")
+            .and_then(Value::as_bool)
+            .unwrap_or_default();
+        if requested {
+            default.insert(key.clone(), Value::Null);
+        }
+
+        let is_direct = item.get("is_direct")
+            .and_then(Value::as_bool)
+            .unwrap_or_default();
+        let mut entry = Map::new();
+        entry.insert(
+            String::from("python"),
+            python_entry(name, metadata, info, is_direct)?,
+        );
+        entry.insert(
+            String::from("dependencies"),
+            Value::Object(requires_dist(metadata, &resolved, &extras)),
+        );
+        dependencies.insert(key.clone(), Value::Object(entry));
+
+        let artifact_hashes = artifact_hashes(info);
+        if !artifact_hashes.is_empty() {
+            hashes.insert(key, Value::Array(artifact_hashes));
+        }
+    }
+
+    let mut section = Map::new();
+    section.insert(String::from("dependencies"), Value::Object(default));
+    dependencies.insert(String::new(), Value::Object(section));
+
+    let lock = json!({
+        "dependencies": dependencies,
+        "hashes": hashes,
+        "sources": {DEFAULT_SOURCE_NAME: {"url": DEFAULT_SOURCE_URL}},
+    });
+    serde_json::from_value(lock).map_err(|e| {
+        Error::InvalidReportError(e.to_string())
+    })
+}
+
+/// Resolve the project's requirements into a lock.
+///
+/// Requirements are read from `requirements.in` if it exists in the project
+/// root, or from the project's `pyproject.toml`. pip (23 or later) is run
+/// with the project's base interpreter to resolve them.
+fn resolve(project: &Project) -> Result<Lock> {
+    let root = project.root();
+    let requirements_in = root.join(REQUIREMENTS_IN);
+
+    let mut cmd = Command::new(project.base_interpreter().location());
+    cmd.args([
+        "-m", "pip", "install",
+        "--dry-run", "--ignore-installed", "--quiet",
+        "--report", "-",
+    ]);
+    if requirements_in.is_file() {
+        cmd.arg("--requirement").arg(&requirements_in);
+    } else if root.join("pyproject.toml").is_file() {
+        cmd.arg(root);
+    } else {
+        return Err(Error::RequirementsNotFoundError(root.to_path_buf()));
+    }
+    cmd.env("PIP_DISABLE_PIP_VERSION_CHECK", "1");
+    cmd.env("PIP_REQUIRE_VIRTUALENV", "0");
+
+    let output = cmd.output()?;
+    if !output.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        return Err(Error::ResolveCommandError(output.status.code()));
+    }
+    let report: Value = serde_json::from_slice(&output.stdout).map_err(|e| {
+        Error::InvalidReportError(e.to_string())
+    })?;
+    lock_from_report(&report, root)
+}
+
+/// Resolve the project's requirements, and write the lock file.
+pub fn lock(project: &Project) -> Result<()> {
+    let lock = resolve(project)?;
+    let path = project.lock_path();
+    let writer = BufWriter::new(File::create(path)?);
+    lock.write(writer).map_err(Error::LockWriteError)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_requirement() {
+        assert_eq!(
+            parse_requirement("Foo.Bar[baz] (>=1.0) ; os_name == 'nt'"),
+            Some((
+                String::from("foo-bar"),
+                Some(String::from("os_name == 'nt'")),
+            )),
+        );
+        assert_eq!(
+            parse_requirement("six"),
+            Some((String::from("six"), None)),
+        );
+        assert_eq!(parse_requirement(">=1.0"), None);
+    }
+
+    #[test]
+    fn test_marker_clauses() {
+        assert_eq!(
+            marker_clauses("extra == 'a and b' and (os_name == 'nt' and \
+                            python_version < '3')"),
+            vec![
+                "extra == 'a and b'",
+                "(os_name == 'nt' and python_version < '3')",
+            ],
+        );
+        assert_eq!(
+            marker_clauses("extra == 'a' and os_name == 'nt' or extra == 'b'"),
+            vec!["extra == 'a' and os_name == 'nt' or extra == 'b'"],
+        );
+    }
+
+    #[test]
+    fn test_requires_dist_extras() {
+        let metadata = json!({
+            "requires_dist": [
+                "idna<4,>=2.5",
+                "PySocks!=1.5.7; extra == 'socks' and python_version < '3'",
+                "chardet; extra == \"use_chardet_on_py3\"",
+                "six; (extra == 'a' or extra == 'socks')",
+            ],
+        });
+        let resolved: HashSet<_> = ["idna", "pysocks", "chardet", "six"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let dependencies = requires_dist(&metadata, &resolved, &[]);
+        assert_eq!(Value::Object(dependencies), json!({"idna": null}));
+
+        let extras = vec![String::from("socks")];
+        let dependencies = requires_dist(&metadata, &resolved, &extras);
+        assert_eq!(Value::Object(dependencies), json!({
+            "idna": null,
+            "pysocks": ["python_version < '3'"],
+            "six": null,
+        }));
+
+        let extras = vec![String::from("use-chardet-on-py3")];
+        let dependencies = requires_dist(&metadata, &resolved, &extras);
+        assert_eq!(Value::Object(dependencies), json!({
+            "idna": null,
+            "chardet": null,
+        }));
+    }
+
+    #[test]
+    fn test_lock_from_report() {
+        let root = std::env::temp_dir().join("project");
+        let root_url = Url::from_directory_path(&root).unwrap();
+        let report = json!({
+            "version": "1",
+            "install": [
+                {
+                    "download_info": {
+                        "url": root_url.as_str().trim_end_matches('/'),
+                        "dir_info": {},
+                    },
+                    "is_direct": true,
+                    "requested": true,
+                    "metadata": {
+                        "name": "project",
+                        "version": "0.1",
+                        "requires_dist": [
+                            "Requests>=2.0",
+                            "pytest; extra == 'test'",
+                        ],
+                    },
+                },
+                {
+                    "download_info": {
+                        "url": "https://files.example.com/requests.whl",
+                        "archive_info": {"hashes": {"sha256": "abc"}},
+                    },
+                    "is_direct": false,
+                    "requested": false,
+                    "metadata": {
+                        "name": "Requests",
+                        "version": "2.31.0",
+                        "requires_dist": [
+                            "idna<4,>=2.5",
+                            "certifi; python_version >= '3'",
+                            "PySocks!=1.5.7; extra == 'socks'",
+                        ],
+                    },
+                },
+                {
+                    "download_info": {
+                        "url": "https://files.example.com/idna.whl",
+                        "archive_info": {"hash": "sha256=def"},
+                    },
+                    "is_direct": false,
+                    "requested": false,
+                    "metadata": {"name": "idna", "version": "3.4"},
+                },
+                {
+                    "download_info": {
+                        "url": "https://example.com/certifi.zip",
+                        "archive_info": {},
+                    },
+                    "is_direct": true,
+                    "requested": false,
+                    "metadata": {"name": "certifi", "version": "2023.7.22"},
+                },
+            ],
+        });
+
+        let lock = lock_from_report(&report, &root).unwrap();
+        let mut buf = vec![];
+        lock.write(&mut buf).unwrap();
+        let written: Value = serde_json::from_slice(&buf).unwrap();
+        assert_json_eq!(written, json!({
+            "dependencies": {
+                "": {"dependencies": {"requests": null}},
+                "certifi": {
+                    "python": {
+                        "name": "certifi",
+                        "url": "https://example.com/certifi.zip",
+                    },
+                },
+                "idna": {"python": {"name": "idna", "version": "3.4"}},
+                "requests": {
+                    "python": {"name": "Requests", "version": "2.31.0"},
+                    "dependencies": {
+                        "certifi": ["python_version >= '3'"],
+                        "idna": null,
+                    },
+                },
+            },
+            "hashes": {
+                "idna": ["sha256:def"],
+                "requests": ["sha256:abc"],
+            },
+            "sources": {"pypi": {"url": "https://pypi.org/simple"}},
+        }));
+    }
+}
@@ -7,22 +7,31 @@
 extern crate dunce;
 extern crate ini;
 extern crate regex;
-extern crate serde_json;
+#[macro_use] extern crate serde_json;
 extern crate tempfile;
 extern crate unindent;
 extern crate url;
 extern crate url_serde;
 extern crate which;
 
+#[cfg(unix)] extern crate libc;
+
 #[cfg(test)] #[macro_use] extern crate assert_json_diff;
 
 mod commands;
+mod distributions;
 mod entrypoints;
 mod foreign;
+mod interrupts;
+mod locking;
 mod lockfiles;
+mod outputs;
 mod projects;
+mod ptys;
 mod pythons;
+mod requirements;
 mod sync;
+#[cfg(test)] mod testutils;
 mod vendors;
 
 fn main() {
@@ -0,0 +1,134 @@
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, IsTerminal};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Possible values of the `--color` option.
+pub static COLOR_CHOICES: &[&str] = &["auto", "always", "never"];
+
+fn resolve_color(
+    flag: Option<&str>,
+    force_color: Option<OsString>,
+    no_color: Option<OsString>,
+    tty: bool,
+) -> bool {
+    match flag {
+        Some("always") => { return true; },
+        Some("never") => { return false; },
+        _ => {},
+    }
+    if let Some(v) = force_color {
+        return v != "0";
+    }
+    if let Some(v) = no_color {
+        if !v.is_empty() {
+            return false;
+        }
+    }
+    tty
+}
+
+/// Decide whether output to stdout should be colored.
+///
+/// An explicit `--color` value (other than "auto") wins. Otherwise the
+/// `FORCE_COLOR` and `NO_COLOR` environment variables are checked in that
+/// order, and color is only used if stdout is a terminal.
+pub fn init_color(flag: Option<&str>) {
+    let color = resolve_color(
+        flag,
+        env::var_os("FORCE_COLOR"),
+        env::var_os("NO_COLOR"),
+        io::stdout().is_terminal(),
+    );
+    COLOR.store(color, Ordering::Relaxed);
+}
+
+/// Whether output should be colored, as decided by `init_color()`.
+pub fn color() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// How much output subprocesses (e.g. pip) should produce.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+impl Verbosity {
+    pub fn from_flags(quiet: bool, verbose: bool) -> Self {
+        if quiet {
+            Verbosity::Quiet
+        } else if verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        }
+    }
+
+    /// Verbosity options to pass to pip.
+    pub fn pip_args(self) -> &'static [&'static str] {
+        match self {
+            Verbosity::Quiet => &["--quiet"],
+            Verbosity::Normal => &[],
+            Verbosity::Verbose => &["-v"],
+        }
+    }
+
+    /// Apply the verbosity to a pip command about to be run.
+    ///
+    /// Quiet discards the child's stdout (stderr is kept so errors are still
+    /// visible). Verbose echoes the command line to stderr.
+    pub fn configure_pip(self, cmd: &mut Command) {
+        cmd.args(self.pip_args());
+        match self {
+            Verbosity::Quiet => { cmd.stdout(Stdio::null()); },
+            Verbosity::Normal => {},
+            Verbosity::Verbose => { eprintln!("{:?}", cmd); },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(
+        flag: Option<&str>,
+        force_color: Option<&str>,
+        no_color: Option<&str>,
+    ) -> bool {
+        let force_color = force_color.map(OsString::from);
+        let no_color = no_color.map(OsString::from);
+        resolve_color(flag, force_color, no_color, false)
+    }
+
+    #[test]
+    fn test_resolve_color() {
+        assert!(!resolve(None, None, None));
+        assert!(resolve(None, Some("1"), None));
+        assert!(!resolve(None, Some("0"), None));
+        assert!(!resolve(None, None, Some("1")));
+        assert!(resolve(None, Some("1"), Some("1")));
+        assert!(resolve_color(None, None, Some(OsString::new()), true));
+        assert!(!resolve_color(None, None, Some("1".into()), true));
+
+        assert!(resolve(Some("always"), None, Some("1")));
+        assert!(!resolve(Some("never"), Some("1"), None));
+        assert!(resolve(Some("auto"), Some("1"), None));
+    }
+
+    #[test]
+    fn test_verbosity() {
+        assert_eq!(Verbosity::from_flags(false, false), Verbosity::Normal);
+        assert_eq!(Verbosity::from_flags(true, false), Verbosity::Quiet);
+        assert_eq!(Verbosity::from_flags(false, true), Verbosity::Verbose);
+        assert_eq!(Verbosity::Quiet.pip_args(), &["--quiet"]);
+        assert!(Verbosity::Normal.pip_args().is_empty());
+    }
+}
@@ -1,18 +1,20 @@
 use std::env;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::fs::File;
-use std::io::{self, BufReader};
+use std::io::{self, BufReader, BufWriter};
 use std::path::{Path, PathBuf};
-use std::process::{Command, ExitStatus};
+use std::process::{Command, ExitStatus, Stdio};
 
 use dunce;
 use serde_json;
 use unindent::unindent;
 
-use crate::entrypoints::EntryPoints;
-use crate::foreign::Foreign;
+use crate::distributions::{self, Distribution};
+use crate::entrypoints::{EntryPoint, EntryPoints};
+use crate::foreign::{self, Foreign};
 use crate::lockfiles::Lock;
+use crate::ptys;
 use crate::pythons::{self, Interpreter};
 
 #[derive(Debug)]
@@ -21,10 +23,16 @@ pub enum Error {
     EnvironmentNotFoundError(PathBuf, String),
     EnvironmentSetupError(env::JoinPathsError),
     ForeignLockFileNotFoundError(PathBuf),
+    ForeignLockFormatUnknownError(PathBuf),
+    ForeignRequirementsError(foreign::requirements::Error),
+    InterpreterRecordInvalidError(PathBuf, serde_json::Error),
     LockFileNotFoundError(PathBuf),
     LockFileInvalidError(serde_json::Error),
+    ModuleCheckError(Option<i32>),
     ProjectNotFoundError(PathBuf),
+    ProjectNotInitializedError(PathBuf),
     PythonInterpreterError(pythons::Error),
+    SitePackagesNotFoundError(PathBuf),
     SystemEnvironmentError(io::Error),
 }
 
@@ -41,14 +49,35 @@ impl fmt::Display for Error {
             Error::ForeignLockFileNotFoundError(ref p) => {
                 write!(f, "foreign lock file not found in directory {:?}", p)
             },
+            Error::ForeignLockFormatUnknownError(ref p) => {
+                write!(f, "unknown lock file format {:?} (expected \
+                           Pipfile.lock, poetry.lock, or conda-lock.yml \
+                           content)", p)
+            },
+            Error::ForeignRequirementsError(ref e) => e.fmt(f),
+            Error::InterpreterRecordInvalidError(ref p, ref e) => {
+                write!(f, "invalid interpreter record {:?}: {}", p, e)
+            },
             Error::LockFileNotFoundError(ref p) => {
                 write!(f, "lock file expected but not found at {:?}", p)
             },
             Error::LockFileInvalidError(ref e) => e.fmt(f),
+            Error::ModuleCheckError(Some(c)) => {
+                write!(f, "module check failed with status {}", c)
+            },
+            Error::ModuleCheckError(None) => {
+                write!(f, "module check terminated by signal")
+            },
             Error::ProjectNotFoundError(ref p) => {
                 write!(f, "project not found in {:?}", p)
             },
+            Error::ProjectNotInitializedError(ref p) => {
+                write!(f, "project in {:?} not initialized", p)
+            },
             Error::PythonInterpreterError(ref e) => e.fmt(f),
+            Error::SitePackagesNotFoundError(ref p) => {
+                write!(f, "site-packages expected but not found at {:?}", p)
+            },
             Error::SystemEnvironmentError(ref e) => e.fmt(f),
         }
     }
@@ -80,32 +109,320 @@ impl From<pythons::Error> for Error {
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// Check whether each module can be found by the command's interpreter.
+///
+/// All modules are checked in one subprocess, and results are returned in
+/// the same order.
+fn find_importable(mut cmd: Command, modules: &[&str]) -> Result<Vec<bool>> {
+    let code = unindent(
+        "
+        import sys
+        try:
+            from importlib.util import find_spec
+        except ImportError:
+            from pkgutil import find_loader as find_spec
+        def check(name):
+            try:
+                return find_spec(name) is not None
+            except Exception:
+                return False
+        for name in sys.argv[1:]:
+            print(int(check(name)))
+        ",
+    );
+    let out = cmd.arg("-c").arg(&code).args(modules).output()?;
+    if !out.status.success() {
+        return Err(Error::ModuleCheckError(out.status.code()));
+    }
+    let results: Vec<_> = String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .map(|l| l.trim() == "1")
+        .collect();
+    if results.len() == modules.len() {
+        Ok(results)
+    } else {
+        Err(Error::ModuleCheckError(out.status.code()))
+    }
+}
+
+/// Annotate entry points by whether each of their modules can be imported.
+fn check_entry_points<I>(
+    cmd: Command,
+    entry_points: I,
+) -> Result<Vec<(String, EntryPoint, bool)>>
+    where I: IntoIterator<Item=(String, EntryPoint)>
+{
+    let entry_points: Vec<_> = entry_points.into_iter().collect();
+    let runnable = {
+        let modules: Vec<_> = entry_points.iter()
+            .map(|(_, e)| e.module())
+            .collect();
+        find_importable(cmd, &modules)?
+    };
+    Ok(entry_points.into_iter()
+        .zip(runnable)
+        .map(|((n, e), r)| (n, e, r))
+        .collect())
+}
+
+/// Python code to run an entry point as if it is the script at `script`.
+///
+/// The code does not touch stdin, so the entry point can read from it.
+fn entry_point_code(script: &str, entry: &EntryPoint) -> String {
+    let function = entry.function();
+    unindent(&format!(
+        "
+        import sys
+        from {} import {}
+        if __name__ == '__main__':
+            sys.argv[0] = {:?}
+            sys.exit({}())
+        ",
+        entry.module(),
+        function.split('.').next().unwrap_or(function),
+        script,
+        function,
+    ))
+}
+
+/// Read a lock file.
+pub fn read_lock_file(path: &Path) -> Result<Lock> {
+    if path.is_file() {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    } else {
+        Err(Error::LockFileNotFoundError(path.to_path_buf()))
+    }
+}
+
+pub static INTERPRETER_RECORD_NAME: &str = "molt-interpreter.json";
+
+/// The interpreter an environment is created with.
+///
+/// This is written into the environment root, so the interpreter can be
+/// found again without being specified.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct InterpreterRecord {
+    pub path: PathBuf,
+    pub version: String,
+    /// Root of the project the environment is created for.
+    ///
+    /// This identifies the project when `__pypackages__` is overridden with
+    /// an absolute path, i.e. is not inside the project.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<PathBuf>,
+}
+
+impl InterpreterRecord {
+    pub fn write(&self, env_root: &Path) -> io::Result<()> {
+        let f = File::create(env_root.join(INTERPRETER_RECORD_NAME))?;
+        serde_json::to_writer_pretty(BufWriter::new(f), self)?;
+        Ok(())
+    }
+
+    /// Read the record in an environment, if there is one.
+    fn read(env_root: &Path) -> Result<Option<Self>> {
+        let p = env_root.join(INTERPRETER_RECORD_NAME);
+        if !p.is_file() {
+            return Ok(None);
+        }
+        let reader = BufReader::new(File::open(&p)?);
+        match serde_json::from_reader(reader) {
+            Ok(record) => Ok(Some(record)),
+            Err(e) => Err(Error::InterpreterRecordInvalidError(p, e)),
+        }
+    }
+}
+
+/// Interpreters recorded in environments of the project at `root`.
+///
+/// Each entry contains the name of the environment directory (i.e. the
+/// compatibility tag) and its record. Entries are sorted by name.
+pub fn recorded_interpreters(
+    root: &Path,
+    layout: &Layout,
+) -> Result<Vec<(String, InterpreterRecord)>> {
+    records_in(&layout.pypackages_in(root))
+}
+
+fn records_in(
+    pypackages: &Path,
+) -> Result<Vec<(String, InterpreterRecord)>> {
+    if !pypackages.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut records = vec![];
+    for entry in pypackages.read_dir()? {
+        let entry = entry?;
+        if let Some(record) = InterpreterRecord::read(&entry.path())? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            records.push((name, record));
+        }
+    }
+    records.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(records)
+}
+
+/// How a project root was identified.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProjectState {
+    /// The root contains `__pypackages__`.
+    Initialized,
+    /// The root contains a project marker (e.g. `pyproject.toml`), but no
+    /// `__pypackages__` has been created yet.
+    Uninitialized,
+}
+
+static PROJECT_MARKERS: &[&str] = &["pyproject.toml", "Pipfile"];
+
+pub static LOCK_FILE_NAME: &str = "molt.lock.json";
+
+/// Where environments of projects are.
+///
+/// This is set from global options, and passed along to find projects.
+#[derive(Clone, Debug)]
+pub struct Layout {
+    pypackages: PathBuf,
+    env: Option<PathBuf>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self { pypackages: PathBuf::from("__pypackages__"), env: None }
+    }
+}
+
+impl Layout {
+    /// Use `pypackages` in place of `__pypackages__` if given, and `env` as
+    /// the environment of projects if given.
+    ///
+    /// `pypackages` is set from the global `--pypackages` option (or
+    /// `MOLT_PYPACKAGES`). A relative path is resolved against the project
+    /// root, so a plain name is also looked for when finding the root.
+    ///
+    /// `env` is set from the global `--env-dir` option. It is used as-is, so
+    /// the caller should resolve a relative path against the current
+    /// directory, not the project root.
+    pub fn new(pypackages: Option<&Path>, env: Option<&Path>) -> Self {
+        let pypackages = match pypackages {
+            Some(p) => p.to_path_buf(),
+            None => PathBuf::from("__pypackages__"),
+        };
+        Self { pypackages, env: env.map(Path::to_path_buf) }
+    }
+
+    /// The `__pypackages__` directory of a project at `root`.
+    pub fn pypackages_in(&self, root: &Path) -> PathBuf {
+        root.join(&self.pypackages)
+    }
+}
+
+/// Find the project root containing, or above, `directory`.
+///
+/// The nearest ancestor with `__pypackages__` is preferred. If there is none,
+/// the nearest ancestor with a project marker file is returned instead.
+///
+/// If `__pypackages__` is overridden with an absolute path, an ancestor is
+/// instead preferred if an environment in the path records it as the root.
+pub fn find_root(
+    directory: &Path,
+    layout: &Layout,
+) -> Result<(PathBuf, ProjectState)> {
+    let pypackages = layout.pypackages.as_path();
+    let mut p = dunce::canonicalize(directory)?;
+
+    // An absolute path is outside the walk, so environments in it are
+    // matched to a root by their records instead.
+    let recorded_roots: Vec<_> = if pypackages.is_relative() {
+        vec![]
+    } else {
+        records_in(pypackages)?.into_iter()
+            .filter_map(|(_, record)| record.root)
+            .collect()
+    };
+
+    let mut marked = None;
+    loop {
+        // The path may not be a directory, either because a file is passed,
+        // or the directory is removed during the walk. Skip to its parent
+        // instead of looking inside.
+        if p.is_dir() {
+            let initialized = if pypackages.is_relative() {
+                p.join(pypackages).is_dir()
+            } else {
+                recorded_roots.contains(&p)
+            };
+            if initialized {
+                return Ok((p, ProjectState::Initialized));
+            }
+            if marked.is_none() && PROJECT_MARKERS.iter().any(|n| {
+                p.join(n).is_file()
+            }) {
+                marked = Some(p.clone());
+            }
+        }
+        if !p.pop() {
+            break;
+        }
+    }
+    match marked {
+        Some(p) => Ok((p, ProjectState::Uninitialized)),
+        None => Err(Error::ProjectNotFoundError(directory.to_path_buf())),
+    }
+}
+
+/// PYTHONPATH with `extra_paths` put before site-packages, followed by the
+/// one inherited from the user.
+fn pythonpath(
+    extra_paths: &[PathBuf],
+    site_packages: &Path,
+) -> Result<OsString> {
+    let paths: Vec<_> = extra_paths.iter().map(PathBuf::as_path)
+        .chain(std::iter::once(site_packages))
+        .collect();
+    Ok(pythons::pythonpath(&paths)?)
+}
+
+fn run_status(cmd: &mut Command, tty: bool) -> Result<ExitStatus> {
+    if tty {
+        ptys::status(cmd)
+    } else {
+        // Be explicit, so tools reading stdin (e.g. formatters) work when
+        // data is piped into molt.
+        cmd.stdin(Stdio::inherit()).status()
+    }.map_err(Error::from)
+}
+
 pub struct Project {
     interpreter: Interpreter,
     root: PathBuf,
+    state: ProjectState,
+    layout: Layout,
 }
 
 impl Project {
-    pub fn find(directory: &Path, interpreter: Interpreter) -> Result<Self> {
-        let mut p = dunce::canonicalize(directory)?;
-        loop {
-            if !p.is_dir() {
-                continue;
-            }
-            if p.join("__pypackages__").is_dir() {
-                return Ok(Self { root: p, interpreter });
-            }
-            // TODO: Should we also look for other project markers like
-            // pyproject.toml, Pipfile, etc.?
-            if !p.pop() {
-                break;
-            }
-        }
-        Err(Error::ProjectNotFoundError(directory.to_path_buf()))
+    pub fn find(
+        directory: &Path,
+        interpreter: Interpreter,
+        layout: &Layout,
+    ) -> Result<Self> {
+        let (root, state) = find_root(directory, layout)?;
+        let layout = layout.clone();
+        Ok(Self { interpreter, root, state, layout })
+    }
+
+    pub fn find_in_cwd(
+        interpreter: Interpreter,
+        layout: &Layout,
+    ) -> Result<Self> {
+        Self::find(&env::current_dir()?, interpreter, layout)
+    }
+
+    pub fn state(&self) -> ProjectState {
+        self.state
     }
 
-    pub fn find_in_cwd(interpreter: Interpreter) -> Result<Self> {
-        Self::find(&env::current_dir()?, interpreter)
+    pub fn root(&self) -> &Path {
+        &self.root
     }
 
     // TODO: We might be able to remove this after removing pip-install.
@@ -113,17 +430,19 @@ impl Project {
         &self.interpreter
     }
 
+    /// Path to the project's lock file. The file may not exist.
+    pub fn lock_path(&self) -> PathBuf {
+        self.root.join(LOCK_FILE_NAME)
+    }
+
+    #[allow(dead_code)]
+    #[deprecated(note = "use lock_path() instead")]
     pub fn persumed_lock_file_path(&self) -> PathBuf {
-        self.root.join("molt.lock.json")
+        self.lock_path()
     }
 
     pub fn read_lock_file(&self) -> Result<Lock> {
-        let p = self.persumed_lock_file_path();
-        if p.is_file() {
-            Ok(serde_json::from_reader(BufReader::new(File::open(p)?))?)
-        } else {
-            Err(Error::LockFileNotFoundError(p))
-        }
+        read_lock_file(&self.lock_path())
     }
 
     pub fn command(&self, io_encoding: Option<&str>) -> Result<Command> {
@@ -132,59 +451,135 @@ impl Project {
             .map_err(Error::from)
     }
 
+    /// The environment to use in place of one in `__pypackages__`, if any.
+    fn env_override(&self) -> Option<&Path> {
+        self.layout.env.as_deref()
+    }
+
     fn persumed_pypackages(&self) -> PathBuf {
-        self.root.join("__pypackages__")
+        self.layout.pypackages_in(&self.root)
     }
 
     pub fn presumed_env_root(&self) -> Result<PathBuf> {
+        if let Some(p) = self.env_override() {
+            return Ok(p.to_owned());
+        }
         let pypackages = self.persumed_pypackages();
         self.interpreter.presumed_env_root(&pypackages).map_err(Error::from)
     }
 
     pub fn env_root(&self) -> Result<PathBuf> {
+        // Fail early without probing the interpreter if there is nowhere
+        // an environment can be in.
+        if self.env_override().is_none()
+                && self.state() == ProjectState::Uninitialized
+                && !self.persumed_pypackages().is_dir() {
+            return Err(Error::ProjectNotInitializedError(self.root.clone()));
+        }
         let p = self.presumed_env_root()?;
         if p.is_dir() {
             Ok(p)
         } else {
-            Err(Error::EnvironmentNotFoundError(
-                self.root.to_owned(), self.interpreter.name().to_owned(),
-            ))
+            Err(self.environment_not_found())
         }
     }
 
-    fn site_packages(&self) -> Result<PathBuf> {
+    /// Error for a missing environment, reported against the overriding
+    /// environment if there is one, or the project root otherwise.
+    fn environment_not_found(&self) -> Error {
+        let root = self.env_override().unwrap_or(&self.root);
+        Error::EnvironmentNotFoundError(
+            root.to_owned(), self.interpreter.name().to_owned(),
+        )
+    }
+
+    pub fn presumed_site_packages(&self) -> Result<PathBuf> {
+        if self.env_override().is_some() {
+            let env_root = self.presumed_env_root()?;
+            return self.interpreter.site_packages_in(&env_root)
+                .map_err(Error::from);
+        }
         let pypackages = self.persumed_pypackages();
-        let p = self.interpreter.presumed_site_packages(&pypackages)?;
+        self.interpreter.presumed_site_packages(&pypackages)
+            .map_err(Error::from)
+    }
+
+    /// The site-packages directory to read installed packages from.
+    ///
+    /// If molt's own layout does not exist, a `__pypackages__/X.Y/lib`
+    /// directory created by another PEP 582 tool is used instead. Packages
+    /// are always installed into molt's layout.
+    pub fn site_packages(&self) -> Result<PathBuf> {
+        let p = self.presumed_site_packages()?;
         if p.is_dir() {
-            Ok(p)
-        } else {
-            Err(Error::EnvironmentNotFoundError(
-                self.root.to_owned(), self.interpreter.name().to_owned(),
-            ))
+            return Ok(p);
         }
+        if self.env_override().is_none() {
+            let pypackages = self.persumed_pypackages();
+            let pep582 = self.interpreter.pep582_site_packages(&pypackages)?;
+            if pep582.is_dir() {
+                return Ok(pep582);
+            }
+        }
+        // Distinguish a missing environment from an unexpected layout.
+        self.env_root()?;
+        Err(Error::SitePackagesNotFoundError(p))
     }
 
-    #[allow(dead_code)]
-    fn bindir(&self) -> Result<PathBuf> {
+    pub fn presumed_bindir(&self) -> Result<PathBuf> {
         #[cfg(target_os = "windows")] static BINDIR_NAME: &str = "Scripts";
         #[cfg(not(target_os = "windows"))] static BINDIR_NAME: &str = "bin";
 
-        let p = self.presumed_env_root()?.join(BINDIR_NAME);
+        Ok(self.presumed_env_root()?.join(BINDIR_NAME))
+    }
+
+    pub fn bindir(&self) -> Result<PathBuf> {
+        self.env_root()?;
+        let p = self.presumed_bindir()?;
         if p.is_dir() {
             Ok(p)
         } else {
-            Err(Error::EnvironmentNotFoundError(
-                self.root.to_owned(), self.interpreter.name().to_owned(),
-            ))
+            Err(self.environment_not_found())
         }
     }
 
+    /// Distributions currently installed into the environment.
+    pub fn installed_distributions(&self) -> Result<Vec<Distribution>> {
+        distributions::find(&self.site_packages()?).map_err(Error::from)
+    }
+
     pub fn entry_points(&self) -> Result<EntryPoints> {
-        Ok(EntryPoints::new(&(self.site_packages()?)))
+        Ok(EntryPoints::new(&(self.site_packages()?))?)
     }
 
-    fn run_interpreter(&self) -> Result<Command> {
-        let mut cmd = self.interpreter.command(None, &self.site_packages()?)?;
+    /// The entry point `run` would use for `command`.
+    pub fn entry_point(&self, command: &str) -> Result<EntryPoint> {
+        self.entry_points()?
+            .find(|(name, _)| name == command)
+            .map(|(_, entry)| entry)
+            .ok_or_else(|| Error::CommandNotFoundError(command.to_owned()))
+    }
+
+    /// Entry points, each with whether it can currently be run.
+    pub fn checked_entry_points(
+        &self,
+    ) -> Result<Vec<(String, EntryPoint, bool)>> {
+        check_entry_points(self.command(Some("utf-8"))?, self.entry_points()?)
+    }
+
+    fn run_interpreter(
+        &self,
+        gui: bool,
+        extra_paths: &[PathBuf],
+    ) -> Result<Command> {
+        let site_packages = self.site_packages()?;
+        let mut cmd = if gui {
+            self.interpreter.gui_command(None, &site_packages)?
+        } else {
+            self.interpreter.command(None, &site_packages)?
+        };
+        // Only what the user runs sees their PYTHONPATH.
+        cmd.env("PYTHONPATH", pythonpath(extra_paths, &site_packages)?);
 
         // TODO: Is this a good idea? I don't think so since the executables
         // in the environment aren't really meant to be used. They might not
@@ -209,51 +604,638 @@ impl Project {
         Ok(cmd)
     }
 
-    pub fn run<I, S>(&self, command: &str, args: I) -> Result<ExitStatus>
+    /// Where pip would install the script for `command`.
+    ///
+    /// This is used as argv[0], so it looks like the script is run (e.g. for
+    /// argparse's prog). The command name is used as-is if the environment
+    /// has no bin directory, or the path is not representable.
+    fn script_path(&self, command: &str) -> String {
+        self.bindir().ok()
+            .and_then(|p| p.join(command).to_str().map(String::from))
+            .unwrap_or_else(|| command.to_string())
+    }
+
+    pub fn run<I, S>(
+        &self,
+        command: &str,
+        args: I,
+        tty: bool,
+        extra_paths: &[PathBuf],
+    ) -> Result<ExitStatus>
         where I: IntoIterator<Item=S>, S: AsRef<OsStr>
     {
-        for (name, entry) in EntryPoints::new(&self.site_packages()?) {
-            if name == command {
-                let function = entry.function();
-                let code = unindent(&format!(
-                    "
-                    import sys
-                    from {} import {}
-                    if __name__ == '__main__':
-                        sys.argv[0] = {:?}
-                        sys.exit({}())
-                    ",
-                    entry.module(),
-                    function.split('.').next().unwrap_or(function),
-                    name,
-                    function,
-                ));
-
-                // TODO: On Windows we should honor the entry.gui flag. Maybe
-                // we should find pythonw.exe during interpreter discovery?
-                return self.run_interpreter()?
-                    .arg("-c")
-                    .arg(&code)
-                    .args(args)
-                    .status()
-                    .map_err(Error::from);
-            }
-        }
-        Err(Error::CommandNotFoundError(command.to_owned()))
+        let entry = self.entry_point(command)?;
+        let code = entry_point_code(&self.script_path(command), &entry);
+        let mut cmd = self.run_interpreter(entry.is_gui(), extra_paths)?;
+        // Python puts arguments after "-c <code>" into sys.argv[1:], and
+        // the code replaces argv[0] ("-c") with the script.
+        cmd.arg("-c").arg(&code).args(args);
+        run_status(&mut cmd, tty)
     }
 
-    pub fn py<I, S>(&self, args: I) -> Result<ExitStatus>
+    /// Run a module like `python -m`, in the same environment as `run()`.
+    pub fn run_module<I, S>(
+        &self,
+        module: &str,
+        args: I,
+        tty: bool,
+        extra_paths: &[PathBuf],
+    ) -> Result<ExitStatus>
         where I: IntoIterator<Item=S>, S: AsRef<OsStr>
     {
-        self.run_interpreter()?.args(args).status().map_err(Error::from)
+        let mut cmd = self.run_interpreter(false, extra_paths)?;
+        cmd.arg("-m").arg(module).args(args);
+        run_status(&mut cmd, tty)
     }
 
-    pub fn convert_foreign_lock(&self) -> Result<i32> {
-        Ok(self.interpreter.convert_foreign_lock(
-            Foreign::find_in(&self.root).ok_or_else(|| {
+    pub fn py<I, S>(
+        &self,
+        args: I,
+        extra_paths: &[PathBuf],
+    ) -> Result<ExitStatus>
+        where I: IntoIterator<Item=S>, S: AsRef<OsStr>
+    {
+        self.run_interpreter(false, extra_paths)?
+            .args(args)
+            .status()
+            .map_err(Error::from)
+    }
+
+    /// Convert a foreign lock file into a molt lock file.
+    ///
+    /// `from` is the foreign lock file, with format inferred from its name
+    /// or content.
+    /// If not given, one is discovered in the project root. `to` defaults
+    /// to the project's lock file path.
+    pub fn convert_foreign_lock(
+        &self,
+        from: Option<&Path>,
+        to: Option<&Path>,
+        dedupe_hashes: bool,
+    ) -> Result<i32> {
+        let foreign = match from {
+            Some(p) => {
+                if !p.is_file() {
+                    return Err(Error::LockFileNotFoundError(p.to_owned()));
+                }
+                Foreign::from_path(p).ok_or_else(|| {
+                    Error::ForeignLockFormatUnknownError(p.to_owned())
+                })?
+            },
+            None => Foreign::find_in(&self.root).ok_or_else(|| {
                 Error::ForeignLockFileNotFoundError(self.root.to_owned())
             })?,
-            &self.persumed_lock_file_path(),
+        };
+        let to = to.map_or_else(|| self.lock_path(), Path::to_path_buf);
+        if let Foreign::RequirementsTxt(ref p) = foreign {
+            // This is simple enough to do without the interpreter.
+            let lock = foreign::requirements::to_lock(p)
+                .map_err(Error::ForeignRequirementsError)?;
+            lock.write(BufWriter::new(File::create(&to)?))
+                .map_err(Error::LockFileInvalidError)?;
+            return Ok(0);
+        }
+        Ok(self.interpreter.convert_foreign_lock(
+            foreign,
+            &to,
+            dedupe_hashes,
         )?)
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, write};
+    use tempfile::TempDir;
+    use crate::testutils::find_interpreters;
+
+    #[test]
+    fn test_lock_path() {
+        let tmp = TempDir::new().unwrap();
+        let root = dunce::canonicalize(tmp.path()).unwrap();
+        write(root.join("pyproject.toml"), "").unwrap();
+
+        let interpreter = Interpreter::new("python", PathBuf::from("python"));
+        let project = Project::find(&root, interpreter, &Layout::default())
+            .unwrap();
+        assert_eq!(project.lock_path(), root.join("molt.lock.json"));
+        #[allow(deprecated)]
+        let old = project.persumed_lock_file_path();
+        assert_eq!(project.lock_path(), old);
+
+        match project.read_lock_file() {
+            Err(Error::LockFileNotFoundError(p)) => {
+                assert_eq!(p, project.lock_path());
+            },
+            _ => panic!("lock file should not be found"),
+        }
+    }
+
+    #[test]
+    fn test_env_root_uninitialized() {
+        let tmp = TempDir::new().unwrap();
+        let root = dunce::canonicalize(tmp.path()).unwrap();
+        write(root.join("pyproject.toml"), "").unwrap();
+
+        let interpreter = Interpreter::new("python", PathBuf::from("python"));
+        let project = Project::find(&root, interpreter, &Layout::default())
+            .unwrap();
+        assert_eq!(project.state(), ProjectState::Uninitialized);
+        match project.env_root() {
+            Err(Error::ProjectNotInitializedError(p)) => assert_eq!(p, root),
+            _ => panic!("project should not be initialized"),
+        }
+    }
+
+    #[test]
+    fn test_env_override() {
+        for interpreter in find_interpreters() {
+            let tmp = TempDir::new().unwrap();
+            let root = dunce::canonicalize(tmp.path()).unwrap();
+            write(root.join("pyproject.toml"), "").unwrap();
+            let env = root.join("venv");
+
+            let layout = Layout::new(None, Some(&env));
+            let project = Project::find(&root, interpreter, &layout).unwrap();
+            assert_eq!(project.presumed_env_root().unwrap(), env);
+            let site_packages = project.presumed_site_packages().unwrap();
+            assert!(site_packages.starts_with(&env));
+            let bindir = project.presumed_bindir().unwrap();
+            assert_eq!(bindir.parent(), Some(env.as_path()));
+
+            match project.env_root() {
+                Err(Error::EnvironmentNotFoundError(p, _)) => {
+                    assert_eq!(p, env);
+                },
+                r => panic!("unexpected result {:?}", r),
+            }
+
+            // The environment exists but is not set up.
+            create_dir_all(&env).unwrap();
+            match project.bindir() {
+                Err(Error::EnvironmentNotFoundError(p, _)) => {
+                    assert_eq!(p, env);
+                },
+                r => panic!("unexpected result {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_site_packages_pep582() {
+        for interpreter in find_interpreters() {
+            let tmp = TempDir::new().unwrap();
+            let root = dunce::canonicalize(tmp.path()).unwrap();
+            write(root.join("pyproject.toml"), "").unwrap();
+            let project = Project::find(&root, interpreter, &Layout::default())
+                .unwrap();
+
+            let pypackages = root.join("__pypackages__");
+            let pep582 = project.interpreter
+                .pep582_site_packages(&pypackages).unwrap();
+            create_dir_all(&pep582).unwrap();
+            assert_eq!(project.site_packages().unwrap(), pep582);
+
+            // molt's own layout wins if both exist.
+            let p = project.interpreter
+                .presumed_site_packages(&pypackages).unwrap();
+            create_dir_all(&p).unwrap();
+            assert_eq!(project.site_packages().unwrap(), p);
+        }
+    }
+
+    #[test]
+    fn test_site_packages_not_found() {
+        for interpreter in find_interpreters() {
+            let tmp = TempDir::new().unwrap();
+            let root = dunce::canonicalize(tmp.path()).unwrap();
+            write(root.join("pyproject.toml"), "").unwrap();
+            let project = Project::find(&root, interpreter, &Layout::default())
+                .unwrap();
+
+            match project.site_packages() {
+                Err(Error::EnvironmentNotFoundError(..)) => {},
+                r => panic!("unexpected result {:?}", r),
+            }
+
+            create_dir_all(project.presumed_env_root().unwrap()).unwrap();
+            match project.site_packages() {
+                Err(Error::SitePackagesNotFoundError(p)) => {
+                    assert_eq!(p, project.presumed_site_packages().unwrap());
+                },
+                r => panic!("unexpected result {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_recorded_interpreters() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path();
+        let layout = Layout::default();
+        assert!(recorded_interpreters(root, &layout).unwrap().is_empty());
+
+        let pypackages = root.join("__pypackages__");
+        for (tag, version) in &[("cp37", "python3.7"), ("cp36", "python3.6")] {
+            let env_root = pypackages.join(tag);
+            create_dir_all(&env_root).unwrap();
+            InterpreterRecord {
+                path: PathBuf::from(format!("/usr/bin/{}", version)),
+                version: version.to_string(),
+                root: None,
+            }.write(&env_root).unwrap();
+        }
+        create_dir_all(pypackages.join("unrecorded")).unwrap();
+
+        let records = recorded_interpreters(root, &layout).unwrap();
+        let tags: Vec<_> = records.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(tags, vec!["cp36", "cp37"]);
+        assert_eq!(records[1].1, InterpreterRecord {
+            path: PathBuf::from("/usr/bin/python3.7"),
+            version: String::from("python3.7"),
+            root: None,
+        });
+
+        write(pypackages.join("cp37").join(INTERPRETER_RECORD_NAME), "{")
+            .unwrap();
+        match recorded_interpreters(root, &layout) {
+            Err(Error::InterpreterRecordInvalidError(..)) => {},
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_find_root_marker() {
+        let tmp = TempDir::new().unwrap();
+        let root = dunce::canonicalize(tmp.path()).unwrap();
+        let sub = root.join("src").join("foo");
+        create_dir_all(&sub).unwrap();
+
+        assert!(find_root(&sub, &Layout::default()).is_err());
+
+        write(root.join("pyproject.toml"), "").unwrap();
+        assert_eq!(
+            find_root(&sub, &Layout::default()).unwrap(),
+            (root.clone(), ProjectState::Uninitialized),
+        );
+
+        write(root.join("src").join("Pipfile"), "").unwrap();
+        assert_eq!(
+            find_root(&sub, &Layout::default()).unwrap(),
+            (root.join("src"), ProjectState::Uninitialized),
+        );
+    }
+
+    #[test]
+    fn test_find_root_prefers_pypackages() {
+        let tmp = TempDir::new().unwrap();
+        let root = dunce::canonicalize(tmp.path()).unwrap();
+        let sub = root.join("src");
+        create_dir_all(root.join("__pypackages__")).unwrap();
+        create_dir_all(&sub).unwrap();
+        write(sub.join("pyproject.toml"), "").unwrap();
+
+        assert_eq!(
+            find_root(&sub, &Layout::default()).unwrap(),
+            (root, ProjectState::Initialized),
+        );
+    }
+
+    #[test]
+    fn test_find_root_custom_pypackages() {
+        let tmp = TempDir::new().unwrap();
+        let root = dunce::canonicalize(tmp.path()).unwrap();
+        let sub = root.join("src");
+        create_dir_all(root.join("vendor").join("env")).unwrap();
+        create_dir_all(sub.join("__pypackages__")).unwrap();
+
+        let pypackages = Path::new("vendor/env");
+        assert_eq!(
+            find_root(&sub, &Layout::new(Some(pypackages), None)).unwrap(),
+            (root.clone(), ProjectState::Initialized),
+        );
+
+        // An absolute path does not make every directory a root.
+        write(root.join("pyproject.toml"), "").unwrap();
+        let layout = Layout::new(Some(&root.join("vendor")), None);
+        assert_eq!(
+            find_root(&sub, &layout).unwrap(),
+            (root.clone(), ProjectState::Uninitialized),
+        );
+    }
+
+    #[test]
+    fn test_find_root_recorded_in_absolute_pypackages() {
+        let tmp = TempDir::new().unwrap();
+        let base = dunce::canonicalize(tmp.path()).unwrap();
+        let root = base.join("project");
+        let sub = root.join("src");
+        create_dir_all(&sub).unwrap();
+
+        let pypackages = base.join("envs");
+        let env_root = pypackages.join("cp37-cp37m-linux_x86_64");
+        create_dir_all(&env_root).unwrap();
+        InterpreterRecord {
+            path: PathBuf::from("/usr/bin/python3.7"),
+            version: String::from("python3.7"),
+            root: Some(root.clone()),
+        }.write(&env_root).unwrap();
+
+        // The root is recognized without a project marker.
+        assert_eq!(
+            find_root(&sub, &Layout::new(Some(&pypackages), None)).unwrap(),
+            (root.clone(), ProjectState::Initialized),
+        );
+
+        // Other projects are not affected.
+        let other = base.join("other");
+        create_dir_all(&other).unwrap();
+        write(other.join("pyproject.toml"), "").unwrap();
+        assert_eq!(
+            find_root(&other, &Layout::new(Some(&pypackages), None)).unwrap(),
+            (other, ProjectState::Uninitialized),
+        );
+    }
+
+    #[test]
+    fn test_find_root_from_non_directory() {
+        let tmp = TempDir::new().unwrap();
+        let root = dunce::canonicalize(tmp.path()).unwrap();
+        write(root.join("Pipfile"), "").unwrap();
+        write(root.join("setup.py"), "").unwrap();
+
+        // This used to loop forever since the file is never popped.
+        assert_eq!(
+            find_root(&root.join("setup.py"), &Layout::default()).unwrap(),
+            (root, ProjectState::Uninitialized),
+        );
+    }
+
+    /// Site-packages with a distribution declaring `entry_points`, and a
+    /// top-level module `module` with `source`.
+    fn entry_point_fixture(
+        entry_points: &str,
+        module: &str,
+        source: &str,
+    ) -> (TempDir, Vec<(String, EntryPoint)>) {
+        let tmp = TempDir::new().unwrap();
+        let dist_info = tmp.path().join(format!("{}-1.0.dist-info", module));
+        create_dir_all(&dist_info).unwrap();
+        write(dist_info.join("entry_points.txt"), unindent(entry_points))
+            .unwrap();
+        write(tmp.path().join(format!("{}.py", module)), unindent(source))
+            .unwrap();
+
+        let mut entry_points: Vec<_> = EntryPoints::new(tmp.path()).unwrap()
+            .collect();
+        entry_points.sort_by(|a, b| a.0.cmp(&b.0));
+        (tmp, entry_points)
+    }
+
+    #[test]
+    fn test_entry_point_stdin() {
+        use std::io::Write;
+
+        let (tmp, entry_points) = entry_point_fixture("
+            [console_scripts]
+            echo = echo_stdin:main
+        ", "echo_stdin", "
+            import sys
+            def main():
+                sys.stdout.write(sys.stdin.read())
+        ");
+        let (name, entry) = &entry_points[0];
+        let code = entry_point_code(name, entry);
+
+        for interpreter in find_interpreters() {
+            let mut child = interpreter.command(None, tmp.path()).unwrap()
+                .arg("-c").arg(&code)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap();
+            child.stdin.take().unwrap().write_all(b"a\nb\n").unwrap();
+            let output = child.wait_with_output().unwrap();
+            assert!(output.status.success());
+            assert_eq!(output.stdout, b"a\nb\n");
+        }
+    }
+
+    #[test]
+    fn test_script_path() {
+        let tmp = TempDir::new().unwrap();
+        let root = dunce::canonicalize(tmp.path()).unwrap();
+        write(root.join("pyproject.toml"), "").unwrap();
+
+        let interpreter = Interpreter::new("python", PathBuf::from("python"));
+        let layout = Layout::new(None, Some(&root.join("env")));
+        let project = Project::find(&root, interpreter, &layout).unwrap();
+        assert_eq!(project.script_path("prog"), "prog");
+
+        create_dir_all(project.presumed_bindir().unwrap()).unwrap();
+        let script = project.presumed_bindir().unwrap().join("prog");
+        assert_eq!(project.script_path("prog"), script.to_str().unwrap());
+    }
+
+    #[test]
+    fn test_entry_point_argv0() {
+        let (tmp, entry_points) = entry_point_fixture("
+            [console_scripts]
+            prog = prog:main
+        ", "prog", "
+            import sys
+            def main():
+                sys.stdout.write(sys.argv[0])
+        ");
+        let (name, entry) = &entry_points[0];
+        let script = tmp.path().join("bin").join(name);
+        let code = entry_point_code(script.to_str().unwrap(), entry);
+
+        for interpreter in find_interpreters() {
+            let output = interpreter.command(None, tmp.path()).unwrap()
+                .arg("-c").arg(&code)
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            assert_eq!(output.stdout, script.to_str().unwrap().as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_pythonpath() {
+        let site_packages = Path::new("env").join("site-packages");
+        let extra = [PathBuf::from("src"), PathBuf::from("tests")];
+        let p = pythonpath(&extra, &site_packages).unwrap();
+        assert_eq!(
+            env::split_paths(&p).take(3).collect::<Vec<_>>(),
+            vec![PathBuf::from("src"), PathBuf::from("tests"), site_packages],
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pythonpath_not_representable() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let p = PathBuf::from(OsStr::from_bytes(b"src\xff"));
+        let site_packages = Path::new("site-packages");
+        match pythonpath(std::slice::from_ref(&p), site_packages) {
+            Err(Error::PythonInterpreterError(
+                pythons::Error::PathRepresentationError(e),
+            )) => {
+                assert_eq!(e, p);
+            },
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_args() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = TempDir::new().unwrap();
+        let root = dunce::canonicalize(tmp.path()).unwrap();
+        write(root.join("pyproject.toml"), "").unwrap();
+        let log = root.join("log");
+
+        // Fake interpreter that logs the code and the arguments after it.
+        let python = root.join("python");
+        write(&python, format!(
+            "#!/bin/sh\nprintf '%s\\n' \"$2\" >> {0:?}\nshift 2\n\
+             echo \"$@\" >> {0:?}\n",
+            log,
+        )).unwrap();
+        let permissions = std::fs::Permissions::from_mode(0o755);
+        std::fs::set_permissions(&python, permissions).unwrap();
+        let interpreter =
+            Interpreter::with_version("python", python, "python3.7");
+
+        let layout = Layout::new(None, Some(&root.join("env")));
+        let project = Project::find(&root, interpreter, &layout).unwrap();
+        let site_packages = project.presumed_site_packages().unwrap();
+        let dist_info = site_packages.join("prog-1.0.dist-info");
+        create_dir_all(&dist_info).unwrap();
+        write(dist_info.join("entry_points.txt"), unindent("
+            [console_scripts]
+            prog = prog:main
+        ")).unwrap();
+        create_dir_all(project.presumed_bindir().unwrap()).unwrap();
+
+        let status = project.run("prog", ["a", "b", "-c"], false, &[])
+            .unwrap();
+        assert!(status.success());
+
+        let script = project.presumed_bindir().unwrap().join("prog");
+        let log = std::fs::read_to_string(&log).unwrap();
+        let argv0 = format!("sys.argv[0] = {:?}", script);
+        assert!(log.contains(&argv0), "{}", log);
+        assert!(log.ends_with("\na b -c\n"), "{}", log);
+    }
+
+    #[test]
+    fn test_entry_point_argv() {
+        let (tmp, entry_points) = entry_point_fixture("
+            [console_scripts]
+            prog = prog:main
+        ", "prog", "
+            import json, sys
+            def main():
+                sys.stdout.write(json.dumps(sys.argv))
+        ");
+        let (name, entry) = &entry_points[0];
+        let code = entry_point_code(name, entry);
+
+        for interpreter in find_interpreters() {
+            let output = interpreter.command(None, tmp.path()).unwrap()
+                .arg("-c").arg(&code)
+                .args(["a", "b", "-c"])
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            let argv: Vec<String> = serde_json::from_slice(&output.stdout)
+                .unwrap();
+            assert_eq!(argv, vec!["prog", "a", "b", "-c"]);
+        }
+    }
+
+    #[test]
+    fn test_entry_point_exit() {
+        let (tmp, entry_points) = entry_point_fixture("
+            [console_scripts]
+            exit-int = exits:exit_int
+            exit-none = exits:exit_none
+            exit-str = exits:exit_str
+        ", "exits", "
+            def exit_int():
+                return 3
+            def exit_none():
+                return None
+            def exit_str():
+                return 'oops'
+        ");
+
+        // Return values are handled like pip-installed console scripts do,
+        // i.e. by passing them to sys.exit().
+        let expected = [(3, ""), (0, ""), (1, "oops")];
+
+        for interpreter in find_interpreters() {
+            for ((name, entry), (code, err)) in
+                    entry_points.iter().zip(expected.iter()) {
+                let output = interpreter.command(None, tmp.path()).unwrap()
+                    .arg("-c").arg(entry_point_code(name, entry))
+                    .output()
+                    .unwrap();
+                assert_eq!(output.status.code(), Some(*code), "{}", name);
+                let stderr = String::from_utf8(output.stderr).unwrap();
+                assert_eq!(stderr.trim(), *err, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_entry_point_code() {
+        let (_tmp, entry_points) = entry_point_fixture("
+            [console_scripts]
+            prog = prog.cli:Main.run
+        ", "prog", "");
+        let (_, entry) = &entry_points[0];
+
+        // The function's return value becomes the exit status.
+        assert_eq!(entry_point_code("/env/bin/prog", entry), unindent(r#"
+            import sys
+            from prog.cli import Main
+            if __name__ == '__main__':
+                sys.argv[0] = "/env/bin/prog"
+                sys.exit(Main.run())
+        "#));
+    }
+
+    #[test]
+    fn test_check_entry_points() {
+        let tmp = TempDir::new().unwrap();
+        let dist_info = tmp.path().join("foo-1.0.dist-info");
+        create_dir_all(&dist_info).unwrap();
+        write(dist_info.join("entry_points.txt"), unindent("
+            [console_scripts]
+            good = foo_present:main
+            bad = foo_missing:main
+        ")).unwrap();
+        write(tmp.path().join("foo_present.py"), "def main(): pass").unwrap();
+
+        for interpreter in find_interpreters() {
+            let cmd = interpreter.command(None, tmp.path()).unwrap();
+            let mut results: Vec<_> = check_entry_points(
+                cmd, EntryPoints::new(tmp.path()).unwrap(),
+            ).unwrap().into_iter().map(|(n, _, r)| (n, r)).collect();
+            results.sort();
+            assert_eq!(results, vec![
+                (String::from("bad"), false),
+                (String::from("good"), true),
+            ]);
+        }
+    }
+}
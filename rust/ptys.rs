@@ -0,0 +1,98 @@
+use std::io;
+use std::process::{Command, ExitStatus};
+
+#[cfg(unix)]
+mod unix {
+    use std::fs::File;
+    use std::io::{self, Read, Write};
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::process::{Command, ExitStatus, Stdio};
+    use std::{ptr, thread};
+
+    use libc;
+
+    fn open() -> io::Result<(File, File)> {
+        let mut master: libc::c_int = -1;
+        let mut slave: libc::c_int = -1;
+        let rc = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        unsafe { Ok((File::from_raw_fd(master), File::from_raw_fd(slave))) }
+    }
+
+    fn stdio(f: &File) -> io::Result<Stdio> {
+        let fd = unsafe { libc::dup(f.as_raw_fd()) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { Stdio::from_raw_fd(fd) })
+    }
+
+    pub fn status(cmd: &mut Command) -> io::Result<ExitStatus> {
+        let (mut master, slave) = open()?;
+        cmd.stdin(stdio(&slave)?);
+        cmd.stdout(stdio(&slave)?);
+        cmd.stderr(stdio(&slave)?);
+        let mut child = cmd.spawn()?;
+
+        // Close all our handles to the slave end, otherwise reading from the
+        // master never finishes. The command holds copies of them as well.
+        drop(slave);
+        cmd.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+
+        // The stdin pump is left detached since it blocks on our stdin. It
+        // dies with us anyway.
+        let mut writer = master.try_clone()?;
+        thread::spawn(move || io::copy(&mut io::stdin(), &mut writer));
+
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        let mut buf = [0; 4096];
+        loop {
+            // Linux returns EIO instead of EOF after the slave is closed.
+            let n = match master.read(&mut buf) {
+                Ok(0) | Err(_) => { break; },
+                Ok(n) => n,
+            };
+            stdout.write_all(&buf[..n])?;
+            stdout.flush()?;
+        }
+        child.wait()
+    }
+}
+
+/// Run the command attached to a pseudo-terminal.
+///
+/// The child's stdio all point to the terminal, so it behaves as if run
+/// interactively even if our own stdio is redirected. Output is forwarded to
+/// our stdout. This is the same as `Command::status()` on non-Unix platforms.
+pub fn status(cmd: &mut Command) -> io::Result<ExitStatus> {
+    #[cfg(unix)] { unix::status(cmd) }
+    #[cfg(not(unix))] { cmd.status() }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_isatty() {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("test -t 0 && test -t 1 && test -t 2");
+        assert!(status(&mut cmd).unwrap().success());
+
+        // Sanity check that this test actually tests something.
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg("test -t 1").stdout(std::process::Stdio::null());
+        assert!(!cmd.status().unwrap().success());
+    }
+}
@@ -1,9 +1,12 @@
-use std::ffi::OsStr;
+use std::cell::RefCell;
+use std::env;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::io;
-use std::iter::empty;
+use std::iter::{empty, once};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::string::FromUtf8Error;
 
 use tempfile::TempDir;
 use unindent::unindent;
@@ -17,7 +20,10 @@ pub enum Error {
     LookupError(which::Error),
     InvocationError(io::Error),
     IncompatibleInterpreterError(String),
+    OutputDecodeError(FromUtf8Error),
     PathRepresentationError(PathBuf),
+    ProbeExitError(String, Option<i32>),
+    UnsupportedForeignLockError(PathBuf),
 }
 
 impl fmt::Display for Error {
@@ -29,9 +35,19 @@ impl fmt::Display for Error {
                 const N: &str = env!("CARGO_PKG_NAME");
                 write!(f, "interpreter {:?} not compatible for {}", s, N)
             },
+            Error::OutputDecodeError(ref e) => e.fmt(f),
             Error::PathRepresentationError(ref p) => {
                 write!(f, "{:?} not representable", p)
             },
+            Error::ProbeExitError(ref s, Some(c)) => {
+                write!(f, "interpreter {:?} exited with status {}", s, c)
+            },
+            Error::ProbeExitError(ref s, None) => {
+                write!(f, "interpreter {:?} terminated by signal", s)
+            },
+            Error::UnsupportedForeignLockError(ref p) => {
+                write!(f, "{:?} can't be converted by the interpreter", p)
+            },
         }
     }
 }
@@ -42,6 +58,12 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<FromUtf8Error> for Error {
+    fn from(e: FromUtf8Error) -> Error {
+        Error::OutputDecodeError(e)
+    }
+}
+
 impl From<which::Error> for Error {
     fn from(e: which::Error) -> Error {
         Error::LookupError(e)
@@ -60,19 +82,149 @@ macro_rules! path_to_str {
 }
 
 
+/// PYTHONPATH with `paths` put before the one inherited from the user.
+///
+/// `paths` come first, so the environment's packages take precedence. This
+/// is only meant for commands the user runs; molt's own commands (pip, and
+/// probes) should not see the user's PYTHONPATH.
+pub fn pythonpath(paths: &[&Path]) -> Result<OsString> {
+    pythonpath_with(paths, env::var_os("PYTHONPATH"))
+}
+
+fn pythonpath_with(
+    paths: &[&Path],
+    inherited: Option<OsString>,
+) -> Result<OsString> {
+    // Paths are passed to Python as strings, so refuse to mangle them.
+    for p in paths {
+        if p.to_str().is_none() || env::join_paths(once(p)).is_err() {
+            return Err(Error::PathRepresentationError(p.to_path_buf()));
+        }
+    }
+    let mut all: Vec<_> = paths.iter().map(|p| p.to_path_buf()).collect();
+    // An empty entry means the current directory to Python, so only keep
+    // entries if the user actually set any.
+    if let Some(inherited) = inherited.filter(|v| !v.is_empty()) {
+        all.extend(env::split_paths(&inherited));
+    }
+    Ok(env::join_paths(all).expect("paths should be checked"))
+}
+
+#[derive(Clone, Debug)]
+pub struct InterpreterInfo {
+    compatibility_tag: String,
+    version: String,
+}
+
+impl InterpreterInfo {
+    pub fn compatibility_tag(&self) -> &str {
+        &self.compatibility_tag
+    }
+
+    /// The "pythonX.Y" string used to name the lib directory.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+}
+
+fn find_gui_location(location: &Path) -> Option<PathBuf> {
+    if !cfg!(windows) {
+        return None;
+    }
+    let p = location.with_file_name("pythonw.exe");
+    if p.is_file() { Some(p) } else { None }
+}
+
+/// Possible values of the `--tag-granularity` option.
+pub static TAG_GRANULARITY_CHOICES: &[&str] = &["full", "abi", "python"];
+
+/// How much of the compatibility tag names an environment.
+///
+/// A coarser tag lets compatible interpreters (e.g. two patch releases with
+/// different tags) share one environment.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TagGranularity {
+    /// The full tag, e.g. `cp38-cp38-manylinux_2_17_x86_64`.
+    Full,
+    /// Python and ABI, e.g. `cp38-cp38`.
+    Abi,
+    /// Python only, e.g. `cp38`.
+    Python,
+}
+
+impl TagGranularity {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "full" => Some(TagGranularity::Full),
+            "abi" => Some(TagGranularity::Abi),
+            "python" => Some(TagGranularity::Python),
+            _ => None,
+        }
+    }
+
+    fn truncate(self, tag: &str) -> String {
+        let n = match self {
+            TagGranularity::Full => return tag.to_string(),
+            TagGranularity::Abi => 2,
+            TagGranularity::Python => 1,
+        };
+        tag.splitn(n + 1, '-').take(n).collect::<Vec<_>>().join("-")
+    }
+}
+
 pub struct Interpreter {
     name: String,
     location: PathBuf,
 
+    // pythonw.exe next to the interpreter, used to run GUI scripts. This is
+    // only looked for on Windows.
+    gui_location: Option<PathBuf>,
+
     // Self cache to avoid repeated querying of compatibility tag.
-    comptagcache: Option<String>,
+    comptagcache: RefCell<Option<String>>,
+
+    // Self cache to avoid repeated querying of the "pythonX.Y" string.
+    versioncache: RefCell<Option<String>>,
+
+    // Whether the caches above are used. Disabled for debugging.
+    caching: bool,
+
+    // How much of the compatibility tag is used to name the environment.
+    tag_granularity: TagGranularity,
+
+    // Number of probes run, so tests can check caching behavior.
+    #[cfg(test)] probecount: std::cell::Cell<usize>,
 }
 
 impl Interpreter {
-    fn new<S>(name: S, location: PathBuf) -> Self
+    pub(crate) fn new<S>(name: S, location: PathBuf) -> Self
         where S: Into<String>
     {
-        Self { name: name.into(), location, comptagcache: None }
+        Self {
+            name: name.into(),
+            gui_location: find_gui_location(&location),
+            location,
+            comptagcache: RefCell::new(None),
+            versioncache: RefCell::new(None),
+            caching: true,
+            tag_granularity: TagGranularity::Full,
+            #[cfg(test)] probecount: std::cell::Cell::new(0),
+        }
+    }
+
+    /// Like `new()`, but with the version (e.g. "python3.7") already known,
+    /// so paths can be computed without probing.
+    #[cfg(test)]
+    pub(crate) fn with_version<S>(
+        name: S,
+        location: PathBuf,
+        version: &str,
+    ) -> Self
+        where S: Into<String>
+    {
+        let interpreter = Self::new(name, location);
+        *interpreter.versioncache.borrow_mut() = Some(version.to_owned());
+        interpreter
     }
 
     pub fn discover<I, S>(name: &str, program: S, args: I) -> Result<Self>
@@ -90,13 +242,23 @@ impl Interpreter {
             .output()?;
 
         if out.status.success() {
-            let loc = PathBuf::from(String::from_utf8(out.stdout).unwrap());
+            let loc = PathBuf::from(String::from_utf8(out.stdout)?);
             Ok(Self::new(name, loc))
         } else {
             Err(Error::IncompatibleInterpreterError(name.to_owned()))
         }
     }
 
+    /// Always re-run probes instead of using cached values.
+    pub fn disable_caching(&mut self) {
+        self.caching = false;
+    }
+
+    /// Name environments by a coarser compatibility tag.
+    pub fn set_tag_granularity(&mut self, granularity: TagGranularity) {
+        self.tag_granularity = granularity;
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -105,12 +267,38 @@ impl Interpreter {
         &self.location
     }
 
+    /// Location of the interpreter to run GUI scripts with.
+    ///
+    /// This is pythonw.exe on Windows if available. Other platforms do not
+    /// distinguish between GUI and console, and use `location()` instead.
+    pub fn gui_location(&self) -> &Path {
+        self.gui_location.as_ref().unwrap_or(&self.location)
+    }
+
     pub fn command(
         &self,
         io_encoding: Option<&str>,
         pkgs: &Path,
     ) -> Result<Command> {
-        let mut cmd = Command::new(&self.location);
+        self.command_with(&self.location, io_encoding, pkgs)
+    }
+
+    /// Like `command()`, but runs with the GUI interpreter.
+    pub fn gui_command(
+        &self,
+        io_encoding: Option<&str>,
+        pkgs: &Path,
+    ) -> Result<Command> {
+        self.command_with(self.gui_location(), io_encoding, pkgs)
+    }
+
+    fn command_with(
+        &self,
+        program: &Path,
+        io_encoding: Option<&str>,
+        pkgs: &Path,
+    ) -> Result<Command> {
+        let mut cmd = Command::new(program);
         if let Some(encoding) = io_encoding {
             cmd.env("PYTHONIOENCODING", encoding);
         }
@@ -134,80 +322,210 @@ impl Interpreter {
         Ok(cmd)
     }
 
-    pub fn create_venv(&self, env_dir: &Path, prompt: &str) -> Result<()> {
+    /// Run code with vendored assets available, and return its stdout.
+    ///
+    /// `populate` is called to put the assets into a temporary directory,
+    /// which is added to the interpreter's path. Stderr is passed through
+    /// so the user can see what went wrong, and a non-zero exit becomes
+    /// `ProbeExitError`.
+    fn probe_code<F>(&self, code: &str, populate: F) -> Result<String>
+        where F: FnOnce(&Path) -> io::Result<()>
+    {
+        #[cfg(test)] self.probecount.set(self.probecount.get() + 1);
+
         let tmp_dir = TempDir::new()?;
-        vendors::VirtEnv::populate_to(tmp_dir.path())?;
+        populate(tmp_dir.path())?;
+
+        let out = self.interpret(
+            Some("utf-8"),
+            code,
+            tmp_dir.path(),
+            empty::<&str>(),
+        )?.stderr(Stdio::inherit()).output()?;
 
-        let code = format!(
+        if !out.status.success() {
+            return Err(Error::ProbeExitError(
+                self.name.to_owned(),
+                out.status.code(),
+            ));
+        }
+        Ok(String::from_utf8(out.stdout)?)
+    }
+
+    fn create_venv_code(
+        env_dir: &Path,
+        prompt: &str,
+        copies: bool,
+        system: bool,
+    ) -> Result<String> {
+        Ok(format!(
             "import virtenv; virtenv.create(\
              python=None, env_dir={:?}, prompt={:?},\
-             system=False, bare=True)",
+             system={}, bare=True{})",
             path_to_str!(env_dir),
             prompt,
-        );
+            if system { "True" } else { "False" },
+            // Only pass this when requested, to use virtenv's default.
+            if copies { ", symlinks=False" } else { "" },
+        ))
+    }
 
-        // TODO: Show message based on status code.
-        let _status = self.interpret(
-            None,
-            &code,
-            tmp_dir.path(),
-            empty::<&str>(),
-        )?.status()?;
+    /// Create an environment at `env_dir`.
+    ///
+    /// If `system` is true, the base interpreter's site-packages is visible
+    /// in the environment.
+    pub fn create_venv(
+        &self,
+        env_dir: &Path,
+        prompt: &str,
+        copies: bool,
+        system: bool,
+    ) -> Result<()> {
+        let code = Self::create_venv_code(env_dir, prompt, copies, system)?;
+        self.probe_code(&code, vendors::VirtEnv::populate_to)?;
         Ok(())
     }
 
-    pub fn compatibility_tag(&self) -> Result<String> {
-        if let Some(ref s) = self.comptagcache {
-            return Ok(s.to_string());
+    /// Query the compatibility tag and version string in one go.
+    ///
+    /// Both values are cached, so the interpreter is only invoked the first
+    /// time this is called, unless caching is disabled.
+    pub fn probe(&self) -> Result<InterpreterInfo> {
+        if let (Some(ref t), Some(ref v)) = (
+            self.cached(&self.comptagcache),
+            self.cached(&self.versioncache),
+        ) {
+            return Ok(InterpreterInfo {
+                compatibility_tag: t.to_string(),
+                version: v.to_string(),
+            });
         }
 
-        let tmp_dir = TempDir::new()?;
-        vendors::Pep425::populate_to(tmp_dir.path())?;
-
-        let out = self.interpret(
-            Some("utf-8"),
+        let val = self.probe_code(
             "from __future__ import print_function; \
-             import pep425; print(next(pep425.sys_tags()), end='')",
-            tmp_dir.path(),
-            empty::<&str>(),
-        )?.output()?;
+             import sys; import pep425; \
+             print(next(pep425.sys_tags())); \
+             print('python{}.{}'.format(*sys.version_info), end='')",
+            vendors::Pep425::populate_to,
+        )?;
+        let mut lines = val.lines();
+        let (tag, version) = match (lines.next(), lines.next()) {
+            (Some(t), Some(v)) if !t.is_empty() && !v.is_empty() => (t, v),
+            _ => {
+                return Err(Error::IncompatibleInterpreterError(
+                    self.name.to_owned(),
+                ));
+            },
+        };
 
-        // TODO: Show error if out.status() is not OK.
+        if self.caching {
+            *self.comptagcache.borrow_mut() = Some(tag.to_owned());
+            *self.versioncache.borrow_mut() = Some(version.to_owned());
+        }
+        Ok(InterpreterInfo {
+            compatibility_tag: tag.to_owned(),
+            version: version.to_owned(),
+        })
+    }
 
-        let val = String::from_utf8(out.stdout).unwrap();
-        if val.is_empty() {
-            Err(Error::IncompatibleInterpreterError(self.name.to_owned()))
+    fn cached(&self, cache: &RefCell<Option<String>>) -> Option<String> {
+        if self.caching {
+            cache.borrow().clone()
         } else {
-            Ok(val)
+            None
         }
     }
 
+    pub fn compatibility_tag(&self) -> Result<String> {
+        if let Some(s) = self.cached(&self.comptagcache) {
+            return Ok(s);
+        }
+        Ok(self.probe()?.compatibility_tag().to_string())
+    }
+
+    /// All tags the interpreter supports, most specific first.
+    ///
+    /// The environment is keyed by the first one. The list is probed every
+    /// time instead of cached, since it is only used for inspection.
+    pub fn compatibility_tags(&self) -> Result<Vec<String>> {
+        let val = self.probe_code(
+            "from __future__ import print_function; import pep425; \
+             print('\\n'.join(str(t) for t in pep425.sys_tags()))",
+            vendors::Pep425::populate_to,
+        )?;
+        Ok(val.lines().filter(|l| !l.is_empty()).map(String::from).collect())
+    }
+
+    /// Whether each `latest` version is newer than the paired `locked` one.
+    ///
+    /// Versions are compared with the vendored `packaging.version`, in one
+    /// probe for all pairs. If either version in a pair is invalid, they are
+    /// compared as strings, and any difference counts as newer.
+    pub fn newer_versions(&self, pairs: &[(&str, &str)]) -> Result<Vec<bool>> {
+        if pairs.is_empty() {
+            return Ok(vec![]);
+        }
+        let pairs = pairs.iter()
+            .map(|(latest, locked)| format!("({:?}, {:?})", latest, locked))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let code = unindent(&format!(
+            r#"
+            from __future__ import print_function
+            from packaging.version import InvalidVersion, Version
+            def is_newer(latest, locked):
+                try:
+                    return Version(latest) > Version(locked)
+                except InvalidVersion:
+                    return latest != locked
+            for latest, locked in [{}]:
+                print(is_newer(latest, locked))
+            "#,
+            pairs,
+        ));
+        let val = self.probe_code(&code, vendors::Packaging::populate_to)?;
+        Ok(val.lines().map(|l| l == "True").collect())
+    }
+
+    /// The compatibility tag cut down to `granularity`.
+    pub fn env_tag(&self, granularity: TagGranularity) -> Result<String> {
+        Ok(granularity.truncate(&self.compatibility_tag()?))
+    }
+
     pub fn presumed_env_root(&self, pypackages: &Path) -> Result<PathBuf> {
-        Ok(pypackages.join(self.compatibility_tag()?))
+        Ok(pypackages.join(self.env_tag(self.tag_granularity)?))
     }
 
     pub fn presumed_site_packages(
         &self,
         pypackages: &Path,
     ) -> Result<PathBuf> {
-        let env_dir = self.presumed_env_root(pypackages)?;
+        self.site_packages_in(&self.presumed_env_root(pypackages)?)
+    }
 
+    /// Site-packages inside an environment at `env_dir`.
+    pub fn site_packages_in(&self, env_dir: &Path) -> Result<PathBuf> {
         if cfg!(windows) {
             return Ok(env_dir.join("Lib").join("site-packages"));
         }
 
-        let out = Command::new(&self.location)
-            .env("PYTHONIOENCODING", "utf-8")
-            .arg("-c")
-            .arg("from __future__ import print_function; \
-                  import sys; \
-                  print('python{}.{}'.format(*sys.version_info), end='')")
-            .output()?;
+        let name = self.python_version()?;
+        Ok(env_dir.join("lib").join(&name).join("site-packages"))
+    }
 
-        // TODO: Show error if out.status() is not OK.
+    /// Site-packages in the PEP 582 layout, i.e. `__pypackages__/X.Y/lib`,
+    /// as used by other PEP 582 tools.
+    pub fn pep582_site_packages(&self, pypackages: &Path) -> Result<PathBuf> {
+        let version = self.python_version()?;
+        let version = version.trim_start_matches("python");
+        Ok(pypackages.join(version).join("lib"))
+    }
 
-        let name = String::from_utf8(out.stdout).unwrap();
-        Ok(env_dir.join("lib").join(&name).join("site-packages"))
+    fn python_version(&self) -> Result<String> {
+        if let Some(s) = self.cached(&self.versioncache) {
+            return Ok(s);
+        }
+        Ok(self.probe()?.version)
     }
 
     // This extra function is so tests can silence warnings, but the interface
@@ -216,57 +534,80 @@ impl Interpreter {
         &self,
         foreign: Foreign,
         output: &Path,
+        dedupe_hashes: bool,
         quiet: bool,
     ) -> Result<i32> {
-        // Silence all warnings from Python.
-        // This needs to be in one line, otherwise unindent breaks.
-        static QUIET_CODE: &str = "import warnings; \
-            warnings.formatwarning = lambda *_, **__: ''";
+        // Warnings are collected and reported once per kind, unless quiet.
+        let quiet = if quiet { "True" } else { "False" };
 
         let code = unindent(&match foreign {
             Foreign::PipfileLock(ref p) => format!(
                 "
                 import io
+                import molt.foreign
                 import molt.foreign.pipfile_lock
                 import plette
-                {}
                 with io.open({:?}, encoding='utf-8') as f:
                     pipfile_lock = plette.Lockfile.load(f)
-                lockfile = molt.foreign.pipfile_lock.to_lock_file(pipfile_lock)
+                with molt.foreign.reported_warnings(quiet={}):
+                    lockfile = molt.foreign.pipfile_lock.to_lock_file(
+                        pipfile_lock,
+                    )
                 with io.open({:?}, 'w', encoding='utf-8') as f:
                     lockfile.dump(f)
                 ",
-                if quiet { QUIET_CODE } else { "" },
                 path_to_str!(p),
+                quiet,
                 path_to_str!(output),
             ),
             Foreign::PoetryLock(ref p) => format!(
                 "
                 import io
+                import molt.foreign
                 import molt.foreign.poetry_lock
-                {}
                 with io.open({:?}, encoding='utf-8') as f:
                     poetry_lock = molt.foreign.poetry_lock.load(f)
-                lockfile = molt.foreign.poetry_lock.to_lock_file(poetry_lock)
+                with molt.foreign.reported_warnings(quiet={}):
+                    lockfile = molt.foreign.poetry_lock.to_lock_file(
+                        poetry_lock, dedupe_hashes={},
+                    )
+                with io.open({:?}, 'w', encoding='utf-8') as f:
+                    lockfile.dump(f)
+                ",
+                path_to_str!(p),
+                quiet,
+                if dedupe_hashes { "True" } else { "False" },
+                path_to_str!(output),
+            ),
+            Foreign::CondaLock(ref p) => format!(
+                "
+                import io
+                import molt.foreign
+                import molt.foreign.conda_lock
+                with io.open({:?}, encoding='utf-8') as f:
+                    conda_lock = molt.foreign.conda_lock.load(f)
+                with molt.foreign.reported_warnings(quiet={}):
+                    lockfile = molt.foreign.conda_lock.to_lock_file(conda_lock)
                 with io.open({:?}, 'w', encoding='utf-8') as f:
                     lockfile.dump(f)
                 ",
-                if quiet { QUIET_CODE } else { "" },
                 path_to_str!(p),
+                quiet,
                 path_to_str!(output),
             ),
+            // This is converted without Python; see projects.rs.
+            Foreign::RequirementsTxt(ref p) => {
+                return Err(Error::UnsupportedForeignLockError(p.clone()));
+            },
         });
 
-        let tmp_dir = TempDir::new()?;
-        vendors::Molt::populate_to(tmp_dir.path())?;
-
-        let mut cmd = self.interpret(
-            Some("utf-8"),
-            &code,
-            tmp_dir.path(),
-            empty::<&str>(),
-        )?;
-        Ok(cmd.status()?.code().unwrap_or(-1))
+        // The conversion reports failure by exit status, which is passed on
+        // to the caller instead of being treated as an error.
+        match self.probe_code(&code, vendors::Molt::populate_to) {
+            Ok(_) => Ok(0),
+            Err(Error::ProbeExitError(_, c)) => Ok(c.unwrap_or(-1)),
+            Err(e) => Err(e),
+        }
     }
 
     #[inline]
@@ -274,8 +615,9 @@ impl Interpreter {
         &self,
         foreign: Foreign,
         output: &Path,
+        dedupe_hashes: bool,
     ) -> Result<i32> {
-        self.convert_foreign_lock_impl(foreign, output, false)
+        self.convert_foreign_lock_impl(foreign, output, dedupe_hashes, false)
     }
 }
 
@@ -283,32 +625,219 @@ impl Interpreter {
 mod tests {
     use super::*;
     use std::fs::read_to_string;
+    use crate::testutils::find_interpreters;
     use serde_json::from_str;
     use tempfile::NamedTempFile;
 
-    struct Interpreters(Option<std::fs::ReadDir>);
-
-    impl Iterator for Interpreters {
-        type Item = Interpreter;
-        fn next(&mut self) -> Option<Self::Item> {
-            loop {
-                let env = self.0.as_mut()?.next()?.ok()?.path();
-                let exe = if cfg!(windows) {
-                    env.join("Scripts").join("python.exe")
-                } else {
-                    env.join("bin").join("python")
-                };
-                if exe.is_file() {
-                    let name = env.file_name().unwrap().to_string_lossy();
-                    return Some(Interpreter::new(name, exe));
-                }
+    #[test]
+    fn test_gui_location() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let python = tmp.path().join("python.exe");
+        std::fs::write(&python, "").unwrap();
+        std::fs::write(tmp.path().join("pythonw.exe"), "").unwrap();
+
+        let interpreter = Interpreter::new("python", python.clone());
+        if cfg!(windows) {
+            assert_eq!(
+                interpreter.gui_location(),
+                tmp.path().join("pythonw.exe"),
+            );
+        } else {
+            assert_eq!(interpreter.gui_location(), python);
+        }
+    }
+
+    #[test]
+    fn test_create_venv_code_copies() {
+        let env_dir = Path::new("env");
+
+        let code = Interpreter::create_venv_code(env_dir, "foo", true, false);
+        assert!(code.unwrap().ends_with(", symlinks=False)"));
+
+        let code = Interpreter::create_venv_code(env_dir, "foo", false, false);
+        assert!(!code.unwrap().contains("symlinks"));
+    }
+
+    #[test]
+    fn test_create_venv_code_system() {
+        let env_dir = Path::new("env");
+
+        let code = Interpreter::create_venv_code(env_dir, "foo", false, true);
+        assert!(code.unwrap().contains("system=True,"));
+
+        let code = Interpreter::create_venv_code(env_dir, "foo", false, false);
+        assert!(code.unwrap().contains("system=False,"));
+    }
+
+    #[test]
+    fn test_python_version_cached() {
+        for interpreter in find_interpreters() {
+            assert!(interpreter.versioncache.borrow().is_none());
+            let version = interpreter.python_version().unwrap();
+            assert!(version.starts_with("python"));
+            assert_eq!(
+                interpreter.versioncache.borrow().as_ref(),
+                Some(&version),
+            );
+            assert_eq!(interpreter.python_version().unwrap(), version);
+        }
+    }
+
+    #[test]
+    fn test_probe_populates_caches() {
+        for interpreter in find_interpreters() {
+            let info = interpreter.probe().unwrap();
+            assert_eq!(
+                interpreter.comptagcache.borrow().as_ref().map(String::as_str),
+                Some(info.compatibility_tag()),
+            );
+            assert_eq!(
+                interpreter.versioncache.borrow().as_ref().map(String::as_str),
+                Some(info.version()),
+            );
+            assert_eq!(
+                interpreter.compatibility_tag().unwrap(),
+                info.compatibility_tag(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_pythonpath_inherited() {
+        let site_packages = Path::new("env").join("site-packages");
+        let user = env::join_paths(["user", "other"]).unwrap();
+        let p = pythonpath_with(&[&site_packages], Some(user)).unwrap();
+        assert_eq!(env::split_paths(&p).collect::<Vec<_>>(), vec![
+            site_packages.clone(),
+            PathBuf::from("user"),
+            PathBuf::from("other"),
+        ]);
+
+        for inherited in [None, Some(OsString::new())] {
+            let p = pythonpath_with(&[&site_packages], inherited).unwrap();
+            assert_eq!(p, site_packages.as_os_str());
+        }
+    }
+
+    #[test]
+    fn test_convert_requirements_txt() {
+        let interpreter = Interpreter::new("python", PathBuf::from("python"));
+        let path = PathBuf::from("requirements.txt");
+        let foreign = Foreign::RequirementsTxt(path.clone());
+        let output = Path::new("molt.lock.json");
+        match interpreter.convert_foreign_lock(foreign, output, false) {
+            Err(Error::UnsupportedForeignLockError(p)) => assert_eq!(p, path),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_command_pythonpath_isolated() {
+        let interpreter = Interpreter::new("python", PathBuf::from("python"));
+        let pkgs = Path::new("env").join("site-packages");
+        let cmd = interpreter.command(None, &pkgs).unwrap();
+        let pythonpath = cmd.get_envs()
+            .find(|(k, _)| *k == "PYTHONPATH")
+            .and_then(|(_, v)| v);
+        assert_eq!(pythonpath, Some(pkgs.as_os_str()));
+    }
+
+    #[test]
+    fn test_tag_granularity() {
+        let tag = "cp38-cp38-manylinux_2_17_x86_64";
+        for (name, expected) in &[
+            ("full", tag),
+            ("abi", "cp38-cp38"),
+            ("python", "cp38"),
+        ] {
+            let granularity = TagGranularity::from_name(name).unwrap();
+            assert_eq!(granularity.truncate(tag), *expected);
+        }
+        assert_eq!(TagGranularity::from_name("platform"), None);
+    }
+
+    #[test]
+    fn test_compatibility_tags() {
+        for interpreter in find_interpreters() {
+            let tags = interpreter.compatibility_tags().unwrap();
+            assert!(tags.len() > 1, "{:?}", tags);
+            assert_eq!(tags[0], interpreter.compatibility_tag().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_newer_versions() {
+        let ordered = [
+            "1.0.dev0", "1.0a1", "1.0a2.dev1", "1.0b1", "1.0rc1", "1.0",
+            "1.0+local.1", "1.0+local.2", "1.0.post1.dev0", "1.0.post1",
+            "1.1", "1.10", "1!0.1",
+        ];
+        let mut pairs = vec![];
+        for (i, older) in ordered.iter().enumerate() {
+            for newer in &ordered[i + 1..] {
+                pairs.push((*newer, *older));
+                pairs.push((*older, *newer));
             }
         }
+        // Each newer-older pair is followed by its reverse.
+        let expected: Vec<_> = (0..pairs.len()).map(|i| i % 2 == 0).collect();
+
+        // Different spellings of the same version, and invalid versions.
+        let same = [
+            ("1.0", "1.0.0"), ("1.0rc1", "1.0-c1"), ("v1.0", "1.0"),
+            ("garbage", "garbage"),
+        ];
+
+        for interpreter in find_interpreters() {
+            let newer = interpreter.newer_versions(&pairs).unwrap();
+            assert_eq!(newer, expected);
+            let newer = interpreter.newer_versions(&same).unwrap();
+            assert_eq!(newer, vec![false; same.len()]);
+            let newer = interpreter.newer_versions(&[("garbage", "1.0")]);
+            assert_eq!(newer.unwrap(), vec![true]);
+        }
+    }
+
+    fn populate_nothing(_: &Path) -> io::Result<()> {
+        Ok(())
     }
 
-    fn find_interpreters() -> Interpreters {
-        let tox_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(".tox");
-        Interpreters(tox_dir.read_dir().ok())
+    #[test]
+    fn test_probe_code() {
+        for interpreter in find_interpreters() {
+            let out = interpreter.probe_code(
+                "import sys; sys.stdout.write('molt')",
+                populate_nothing,
+            );
+            assert_eq!(out.unwrap(), "molt");
+        }
+    }
+
+    #[test]
+    fn test_probe_code_failure() {
+        for interpreter in find_interpreters() {
+            let out = interpreter.probe_code(
+                "import sys; sys.exit(3)",
+                populate_nothing,
+            );
+            match out {
+                Err(Error::ProbeExitError(ref n, Some(3))) => {
+                    assert_eq!(n, interpreter.name());
+                },
+                r => panic!("unexpected result {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_disable_caching() {
+        for mut interpreter in find_interpreters() {
+            interpreter.disable_caching();
+            let tag = interpreter.compatibility_tag().unwrap();
+            assert_eq!(interpreter.compatibility_tag().unwrap(), tag);
+            assert_eq!(interpreter.probecount.get(), 2);
+            assert!(interpreter.comptagcache.borrow().is_none());
+        }
     }
 
     #[test]
@@ -327,7 +856,7 @@ mod tests {
                 let real_out = NamedTempFile::new().unwrap().into_temp_path();
 
                 let result = interpreter.convert_foreign_lock_impl(
-                    foreign, &real_out, true,
+                    foreign, &real_out, false, true,
                 );
                 assert_eq!(result.unwrap(), 0);
 
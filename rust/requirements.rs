@@ -0,0 +1,351 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{read_to_string, write};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use dunce;
+use regex::Regex;
+
+use crate::lockfiles::canonicalize_name;
+
+pub static REQUIREMENTS_IN: &str = "requirements.in";
+
+#[derive(Debug)]
+pub enum Error {
+    IncludeCycleError(PathBuf),
+    InvalidRequirementError(String),
+    NotDirectRequirementError(String),
+    RequirementNotFoundError(String),
+    SystemError(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::IncludeCycleError(ref p) => {
+                write!(f, "requirements file {:?} includes itself", p)
+            },
+            Error::InvalidRequirementError(ref s) => {
+                write!(f, "invalid requirement {:?}", s)
+            },
+            Error::NotDirectRequirementError(ref s) => {
+                write!(f, "{:?} is not a direct requirement (it is \
+                           required by other packages)", s)
+            },
+            Error::RequirementNotFoundError(ref s) => {
+                write!(f, "requirement {:?} not found", s)
+            },
+            Error::SystemError(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::SystemError(e)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+lazy_static! {
+    static ref NAME_RE: Regex =
+        Regex::new(r"^\s*([A-Za-z0-9][A-Za-z0-9._-]*)").unwrap();
+}
+
+/// Canonical name of a PEP 508 requirement.
+pub fn requirement_name(spec: &str) -> Option<String> {
+    let name = NAME_RE.captures(spec)?.get(1)?.as_str();
+    Some(canonicalize_name(name))
+}
+
+/// A requirements.in file, kept line by line so comments and options are
+/// preserved when it is written back.
+pub struct RequirementsFile {
+    lines: Vec<String>,
+}
+
+impl RequirementsFile {
+    /// Read the file at `path`. A missing file is treated as empty.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = match read_to_string(path) {
+            Ok(s) => s,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                String::new()
+            },
+            Err(e) => { return Err(e.into()); },
+        };
+        let lines = content.lines().map(String::from).collect();
+        Ok(Self { lines })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut content = self.lines.join("\n");
+        content.push('\n');
+        write(path, content)?;
+        Ok(())
+    }
+
+    /// Index of the line requiring `name` (canonicalized).
+    ///
+    /// Comments and option lines (e.g. `--index-url`) are never matched.
+    fn find(&self, name: &str) -> Option<usize> {
+        self.lines.iter().position(|line| {
+            let line = line.trim_start();
+            !line.starts_with('#')
+                && !line.starts_with('-')
+                && requirement_name(line).as_deref() == Some(name)
+        })
+    }
+
+    /// Add a requirement. If the package is already required, the existing
+    /// line is replaced in place. Returns whether a line is replaced.
+    pub fn add(&mut self, spec: &str) -> Result<bool> {
+        let spec = spec.trim();
+        let name = requirement_name(spec).ok_or_else(|| {
+            Error::InvalidRequirementError(spec.to_string())
+        })?;
+        match self.find(&name) {
+            Some(i) => {
+                self.lines[i] = spec.to_string();
+                Ok(true)
+            },
+            None => {
+                self.lines.push(spec.to_string());
+                Ok(false)
+            },
+        }
+    }
+
+    /// Remove the requirement of a package. Returns whether it is found.
+    pub fn remove(&mut self, name: &str) -> bool {
+        match self.find(&canonicalize_name(name)) {
+            Some(i) => {
+                self.lines.remove(i);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+/// Split a requirements.txt file into logical lines.
+///
+/// Lines ending with a backslash are joined with the next one. Comments
+/// (starting with `#` at the beginning of a line or after whitespace) are
+/// stripped, and empty lines are dropped.
+fn logical_lines(content: &str) -> Vec<String> {
+    let mut lines = vec![];
+    let mut current = String::new();
+    for line in content.lines() {
+        if let Some(line) = line.strip_suffix('\\') {
+            current.push_str(line);
+            continue;
+        }
+        current.push_str(line);
+        let line = match current.find(" #").or_else(|| current.find("\t#")) {
+            Some(i) => &current[..i],
+            None if current.trim_start().starts_with('#') => "",
+            None => &current,
+        };
+        let line = line.trim();
+        if !line.is_empty() {
+            lines.push(line.to_string());
+        }
+        current.clear();
+    }
+    if !current.trim().is_empty() {
+        lines.push(current.trim().to_string());
+    }
+    lines
+}
+
+/// Target of an include line, and whether it is a constraint file.
+fn include_target(line: &str) -> Option<(&str, bool)> {
+    for (option, constraint) in &[
+        ("--requirement", false),
+        ("--constraint", true),
+        ("-r", false),
+        ("-c", true),
+    ] {
+        if let Some(rest) = line.strip_prefix(option) {
+            let rest = rest.strip_prefix('=').unwrap_or(rest).trim();
+            if !rest.is_empty() {
+                return Some((rest, *constraint));
+            }
+        }
+    }
+    None
+}
+
+/// A requirements.txt file with its includes resolved.
+///
+/// `-r`/`--requirement` includes are read recursively, relative to the
+/// including file, and their lines are merged into `lines`. Lines read via
+/// `-c`/`--constraint` (including files a constraint file includes) are
+/// collected into `constraints` instead, and only apply to packages also
+/// listed in `lines`; see `constraint()`.
+#[derive(Debug, Default)]
+pub struct RequirementsTxt {
+    lines: Vec<String>,
+    constraints: Vec<String>,
+}
+
+impl RequirementsTxt {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut txt = Self::default();
+        txt.read(path, false, &mut vec![], &mut HashSet::new())?;
+        Ok(txt)
+    }
+
+    fn read(
+        &mut self,
+        path: &Path,
+        constraint: bool,
+        stack: &mut Vec<PathBuf>,
+        done: &mut HashSet<(PathBuf, bool)>,
+    ) -> Result<()> {
+        let path = dunce::canonicalize(path)?;
+        if stack.contains(&path) {
+            return Err(Error::IncludeCycleError(path));
+        }
+        if !done.insert((path.clone(), constraint)) {
+            return Ok(());
+        }
+        let content = read_to_string(&path)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("")).to_owned();
+
+        stack.push(path);
+        for line in logical_lines(&content) {
+            if let Some((target, c)) = include_target(&line) {
+                self.read(&dir.join(target), constraint || c, stack, done)?;
+            } else if constraint {
+                self.constraints.push(line);
+            } else {
+                self.lines.push(line);
+            }
+        }
+        stack.pop();
+        Ok(())
+    }
+
+    /// Requirement and option lines, in the order they appear.
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Constraint on a package (canonicalized name), if there is one.
+    pub fn constraint(&self, name: &str) -> Option<&str> {
+        self.constraints.iter()
+            .find(|line| requirement_name(line).as_deref() == Some(name))
+            .map(String::as_str)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_add_remove() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join(REQUIREMENTS_IN);
+        write(&path, "# Web.\n--index-url https://x.org\nDjango>=2.0\n")
+            .unwrap();
+
+        let mut reqs = RequirementsFile::load(&path).unwrap();
+        assert!(!reqs.add("requests").unwrap());
+        assert!(reqs.add("django ~= 3.0").unwrap());
+        assert!(reqs.add("!!").is_err());
+        reqs.save(&path).unwrap();
+        assert_eq!(
+            read_to_string(&path).unwrap(),
+            "# Web.\n--index-url https://x.org\ndjango ~= 3.0\nrequests\n",
+        );
+
+        let mut reqs = RequirementsFile::load(&path).unwrap();
+        assert!(reqs.remove("Django"));
+        assert!(!reqs.remove("six"));
+        assert!(!reqs.remove("index-url"));
+        reqs.save(&path).unwrap();
+        assert_eq!(
+            read_to_string(&path).unwrap(),
+            "# Web.\n--index-url https://x.org\nrequests\n",
+        );
+    }
+
+    #[test]
+    fn test_requirements_txt_include() {
+        let tmp = TempDir::new().unwrap();
+        let sub = tmp.path().join("reqs");
+        std::fs::create_dir(&sub).unwrap();
+        write(
+            tmp.path().join("requirements.txt"),
+            "# Top.\n-r reqs/base.txt\nrequests==2.22.0 \\\n    \
+             --hash=sha256:abc  # Pinned.\n-c constraints.txt\n",
+        ).unwrap();
+        write(sub.join("base.txt"), "--requirement=common.txt\nsix\n")
+            .unwrap();
+        write(sub.join("common.txt"), "Django==2.2\n").unwrap();
+        write(tmp.path().join("constraints.txt"), "urllib3<1.25\nsix<2\n")
+            .unwrap();
+
+        let path = tmp.path().join("requirements.txt");
+        let txt = RequirementsTxt::load(&path).unwrap();
+        assert_eq!(txt.lines(), &[
+            "Django==2.2",
+            "six",
+            "requests==2.22.0     --hash=sha256:abc",
+        ]);
+        assert_eq!(txt.constraint("six"), Some("six<2"));
+        assert_eq!(txt.constraint("requests"), None);
+    }
+
+    #[test]
+    fn test_requirements_txt_nested_constraints() {
+        let tmp = TempDir::new().unwrap();
+        write(
+            tmp.path().join("requirements.txt"),
+            "-c constraints.txt\nzope.interface\n",
+        ).unwrap();
+        write(tmp.path().join("constraints.txt"), "-r pins.txt\n").unwrap();
+        write(tmp.path().join("pins.txt"), "Zope_Interface==4.6.0\n")
+            .unwrap();
+
+        let path = tmp.path().join("requirements.txt");
+        let txt = RequirementsTxt::load(&path).unwrap();
+        assert_eq!(txt.lines(), &["zope.interface"]);
+        assert_eq!(
+            txt.constraint("zope-interface"),
+            Some("Zope_Interface==4.6.0"),
+        );
+    }
+
+    #[test]
+    fn test_requirements_txt_include_cycle() {
+        let tmp = TempDir::new().unwrap();
+        write(tmp.path().join("a.txt"), "six\n-r b.txt\n").unwrap();
+        write(tmp.path().join("b.txt"), "-r ./a.txt\n").unwrap();
+
+        match RequirementsTxt::load(&tmp.path().join("a.txt")) {
+            Err(Error::IncludeCycleError(p)) => {
+                assert_eq!(p.file_name().unwrap(), "a.txt");
+            },
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join(REQUIREMENTS_IN);
+        let mut reqs = RequirementsFile::load(&path).unwrap();
+        reqs.add("six").unwrap();
+        reqs.save(&path).unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "six\n");
+    }
+}
@@ -1,14 +1,22 @@
 use std::cell::Ref;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
-use std::io::{self, Write};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::Duration;
 
+use serde_json;
 use tempfile::{NamedTempFile, TempDir};
 use unindent::unindent;
 
-use crate::lockfiles::{Dependency, Lock, Marker, PythonPackage};
+use crate::distributions::{self, Distribution};
+use crate::interrupts;
+use crate::lockfiles::{Dependency, Lock, Marker, PythonPackage, Sources};
+use crate::outputs::Verbosity;
 use crate::projects::{self, Project};
 use crate::pythons::{self, Interpreter};
 use crate::vendors;
@@ -16,41 +24,131 @@ use crate::vendors;
 #[derive(Debug)]
 pub enum Error {
     DefaultSectionNotFound,
-    ExtraSectionNotFound(String),
-    InstallCommandError(Vec<(String, Option<i32>)>),
+    DownloadCommandError(Vec<InstallFailure>),
+    ExtraSectionsNotFound(Vec<String>, Vec<String>),
+    InstallCommandError(Vec<InstallFailure>),
+    Interrupted,
     InterpreterError(pythons::Error),
     InvalidMarkerError(String, String),
+    LockWriteError(serde_json::Error),
+    PackageNotFound(String),
     PathRepresentationError(PathBuf),
+    PostSyncCheckError(Vec<String>, Vec<String>),
     ProjectError(projects::Error),
+    SitePackagesMismatchError(PathBuf, Option<PathBuf>),
     SystemError(io::Error),
 }
 
+/// A package pip failed to handle.
+#[derive(Clone, Debug)]
+pub struct InstallFailure {
+    pub package: String,
+    pub exit_code: Option<i32>,
+    /// What pip wrote to stderr, if captured.
+    pub stderr: Option<String>,
+}
+
+impl InstallFailure {
+    /// The line in stderr most likely to explain the failure.
+    ///
+    /// This is the last line pip marked as an error, or the last non-empty
+    /// line if there is none.
+    pub fn reason(&self) -> Option<&str> {
+        let lines: Vec<_> = self.stderr.as_ref()?.lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect();
+        lines.iter().rev().find(|l| l.starts_with("ERROR:"))
+            .or_else(|| lines.last())
+            .cloned()
+    }
+}
+
+/// Format failed pip runs as a header, followed by one line per package.
+fn fmt_failures(
+    f: &mut fmt::Formatter,
+    action: &str,
+    failures: &[InstallFailure],
+) -> fmt::Result {
+    write!(f, "failed to {} {} package(s):", action, failures.len())?;
+    for failure in failures {
+        match failure.exit_code {
+            Some(c) => write!(f, "\n  {} (exit {})", failure.package, c)?,
+            None => write!(f, "\n  {} (no exit code)", failure.package)?,
+        }
+        if let Some(reason) = failure.reason() {
+            write!(f, ": {}", reason)?;
+        }
+    }
+    Ok(())
+}
+
+/// Run `cmd`, passing its stderr through while also capturing it.
+fn status_capturing_stderr(
+    cmd: &mut Command,
+) -> io::Result<(ExitStatus, String)> {
+    let mut child = cmd.stderr(Stdio::piped()).spawn()?;
+    let mut captured = vec![];
+    if let Some(stderr) = child.stderr.take() {
+        let mut reader = BufReader::new(stderr);
+        let mut line = vec![];
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            io::stderr().write_all(&line)?;
+            captured.append(&mut line);
+        }
+    }
+    let status = child.wait()?;
+    Ok((status, String::from_utf8_lossy(&captured).into_owned()))
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::DefaultSectionNotFound => {
                 write!(f, "default section not found in lock file")
             },
-            Error::ExtraSectionNotFound(ref s) => {
-                write!(f, "section {} not found in lock file", s)
+            Error::DownloadCommandError(ref v) => {
+                fmt_failures(f, "download", v)
             },
-            Error::InstallCommandError(ref v) => {
-                for (k, c) in v {
-                    match c {
-                        Some(c) => {
-                            write!(f, "failed to install {:?} ({})", k, c)?;
-                        },
-                        None => { write!(f, "failed to install {:?}", k)?; },
-                    }
+            Error::ExtraSectionsNotFound(ref v, ref available) => {
+                let v = v.join(", ");
+                write!(f, "sections not found in lock file: {}", v)?;
+                if available.is_empty() {
+                    write!(f, " (no extra sections available)")
+                } else {
+                    write!(f, " (available: {})", available.join(", "))
                 }
-                Ok(())
             },
+            Error::InstallCommandError(ref v) => {
+                fmt_failures(f, "install", v)
+            },
+            Error::Interrupted => write!(f, "interrupted"),
             Error::InterpreterError(ref e) => e.fmt(f),
             Error::InvalidMarkerError(_, ref s) => write!(f, "{}", s),
+            Error::LockWriteError(ref e) => {
+                write!(f, "failed to write lock file: {}", e)
+            },
+            Error::PackageNotFound(ref k) => {
+                write!(f, "package {} not found in lock file", k)
+            },
             Error::PathRepresentationError(ref p) => {
                 write!(f, "{:?} not representable", p)
             },
+            Error::PostSyncCheckError(ref failed, ref removed) => {
+                let failed = failed.join(", ");
+                write!(f, "packages not installed as locked: {}", failed)?;
+                if !removed.is_empty() {
+                    write!(f, " (rolled back {})", removed.join(", "))?;
+                }
+                Ok(())
+            },
             Error::ProjectError(ref e) => e.fmt(f),
+            Error::SitePackagesMismatchError(ref e, Some(ref p)) => {
+                write!(f, "packages installed into {:?}, expected {:?}", p, e)
+            },
+            Error::SitePackagesMismatchError(ref e, None) => {
+                write!(f, "installed packages not found in {:?}", e)
+            },
             Error::SystemError(ref e) => e.fmt(f),
         }
     }
@@ -76,16 +174,499 @@ impl From<pythons::Error> for Error {
 
 type Result<T> = std::result::Result<T, Error>;
 
+fn installed_distributions(site_packages: &Path) -> Result<Vec<Distribution>> {
+    if !site_packages.is_dir() {
+        return Ok(vec![]);
+    }
+    Ok(distributions::find(site_packages)?)
+}
+
+fn installed_names(site_packages: &Path) -> Result<HashSet<String>> {
+    Ok(installed_distributions(site_packages)?.iter()
+        .map(Distribution::canonical_name)
+        .collect())
+}
+
+/// Packages not installed at the expected version.
+///
+/// `required` contains canonical names, and versions if the packages are
+/// named requirements. Packages with no versions only need to be present.
+fn find_unsatisfied(
+    required: &[(String, Option<String>)],
+    installed: &[Distribution],
+) -> Vec<String> {
+    let installed: HashMap<_, _> = installed.iter()
+        .map(|d| (d.canonical_name(), d.version()))
+        .collect();
+    let mut unsatisfied: Vec<_> = required.iter()
+        .filter(|(name, version)| match (installed.get(name), version) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(v), Some(ref version)) => v != version,
+        })
+        .map(|(name, _)| name.to_string())
+        .collect();
+    unsatisfied.sort();
+    unsatisfied
+}
+
+// Prints the package of each module that fails to import. Arguments are
+// "package:module" pairs. The current directory is dropped from sys.path so
+// it can't shadow what's installed.
+static IMPORT_CHECK_CODE: &str = "\
+import importlib, sys
+sys.path[:] = [p for p in sys.path if p]
+for arg in sys.argv[1:]:
+    package, _, module = arg.partition(':')
+    try:
+        importlib.import_module(module)
+    except Exception:
+        print(package)
+";
+
+/// Required packages with a top-level module that can't be imported.
+///
+/// Modules are imported with the interpreter `command` gives. Packages that
+/// don't list their top-level modules are not checked.
+fn find_unimportable<F>(
+    required: &[(String, Option<String>)],
+    installed: &[Distribution],
+    command: F,
+) -> Result<Vec<String>>
+    where F: Fn() -> std::result::Result<Command, projects::Error>
+{
+    let args: Vec<_> = installed.iter()
+        .filter(|d| {
+            let name = d.canonical_name();
+            required.iter().any(|(n, _)| *n == name)
+        })
+        .flat_map(|d| {
+            let name = d.canonical_name();
+            d.top_level().into_iter()
+                .map(move |module| format!("{}:{}", name, module))
+        })
+        .collect();
+    if args.is_empty() {
+        return Ok(vec![]);
+    }
+    let output = command()?
+        .arg("-c")
+        .arg(IMPORT_CHECK_CODE)
+        .args(&args)
+        .stderr(Stdio::null())
+        .output()?;
+    let mut names: Vec<_> = String::from_utf8_lossy(&output.stdout).lines()
+        .map(String::from)
+        .collect();
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Counts of changes made by a sync.
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    installed: usize,
+    removed: usize,
+    up_to_date: usize,
+}
+
+impl Summary {
+    /// Compare the environment before and after a sync.
+    ///
+    /// Required packages already satisfied before the sync are up to date,
+    /// and others are counted as installed. Packages present before but
+    /// gone after the sync are counted as removed.
+    fn new(
+        required: &[(String, Option<String>)],
+        before: &[Distribution],
+        after: &[Distribution],
+    ) -> Self {
+        let installed = find_unsatisfied(required, before).len();
+        let after: HashSet<_> = after.iter()
+            .map(Distribution::canonical_name)
+            .collect();
+        let removed = before.iter()
+            .filter(|d| !after.contains(&d.canonical_name()))
+            .count();
+        Self {
+            installed,
+            removed,
+            up_to_date: required.len() - installed,
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.installed == 0 && self.removed == 0 {
+            write!(f, "all {} packages up to date", self.up_to_date)
+        } else {
+            write!(
+                f, "installed {}, removed {}, {} up to date",
+                self.installed, self.removed, self.up_to_date,
+            )
+        }
+    }
+}
+
+/// Packages in `installed` but not in `before`, i.e. added by this sync.
+fn find_added(
+    before: &HashSet<String>,
+    installed: &[Distribution],
+) -> Vec<String> {
+    let mut added: Vec<_> = installed.iter()
+        .map(Distribution::canonical_name)
+        .filter(|n| !before.contains(n))
+        .collect();
+    added.sort();
+    added
+}
+
+fn find_site_packages_dirs(dir: &Path, into: &mut Vec<PathBuf>) {
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries,
+        Err(_) => { return; },
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        if entry.file_name() == "site-packages" {
+            into.push(path);
+        } else {
+            find_site_packages_dirs(&path, into);
+        }
+    }
+}
+
+/// Make sure packages are installed where we expect them to be.
+///
+/// pip decides the layout under `--prefix` by itself, which may not match
+/// the site-packages path we calculate. If some packages are missing, other
+/// site-packages directories in the environment are searched to report
+/// where they went instead.
+fn verify_layout(
+    env_root: &Path,
+    site_packages: &Path,
+    names: &[String],
+) -> Result<()> {
+    let installed = installed_names(site_packages)?;
+    let missing: Vec<_> = names.iter()
+        .filter(|n| !installed.contains(*n))
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let mut candidates = vec![];
+    find_site_packages_dirs(env_root, &mut candidates);
+    for candidate in candidates {
+        if candidate == site_packages {
+            continue;
+        }
+        let found = installed_names(&candidate)?;
+        if missing.iter().any(|n| found.contains(*n)) {
+            return Err(Error::SitePackagesMismatchError(
+                site_packages.to_path_buf(), Some(candidate),
+            ));
+        }
+    }
+    Err(Error::SitePackagesMismatchError(site_packages.to_path_buf(), None))
+}
+
+/// Packages to install with one pip invocation.
+#[derive(Debug, Default)]
+struct InstallGroup {
+    keys: Vec<String>,
+
+    // Lines in the requirements file passed to pip.
+    lines: Vec<String>,
+
+    // Additional command line options passed to pip.
+    options: Vec<String>,
+}
+
+impl InstallGroup {
+    /// Add pip options to the group, skipping ones already present.
+    fn add_options<I>(&mut self, options: I)
+        where I: IntoIterator<Item=String>
+    {
+        for option in options {
+            if !self.options.contains(&option) {
+                self.options.push(option);
+            }
+        }
+    }
+}
+
+/// Plan pip invocations to install packages.
+///
+/// By default each package is installed with its own pip invocation. If
+/// `group_by_source` is set, packages are grouped by their source instead.
+/// Index and trusted host options are passed once for each group on the
+/// command line, and only bare requirements are written into the requirement
+/// file. Packages with and without hashes are always put in different groups,
+/// since pip requires either all or none of the requirements in one run to be
+/// hashed.
+///
+/// Installs are planned in the order of `packages`. When grouping, groups
+/// are ordered by where their first package appears.
+fn plan_installs<I>(
+    packages: I,
+    sources: &Sources,
+    group_by_source: bool,
+) -> Vec<InstallGroup>
+    where I: Iterator<Item=(String, PythonPackage)>
+{
+    if !group_by_source {
+        return packages.map(|(key, package)| {
+            let (hashed, line) = package.to_requirement_line();
+            let mut group = InstallGroup {
+                keys: vec![key],
+                lines: vec![line],
+                ..Default::default()
+            };
+            group.add_options(package.pip_options(sources));
+            if hashed {
+                group.add_options(Some(String::from("--require-hashes")));
+            }
+            group
+        }).collect();
+    }
+
+    let mut groups: Vec<InstallGroup> = vec![];
+    let mut indexes = HashMap::new();
+    for (key, package) in packages {
+        let (hashed, line) = package.to_requirement_line();
+        let source = package.source().map(|s| s.key().to_string());
+        let index = *indexes.entry((source, hashed)).or_insert_with(|| {
+            groups.push(InstallGroup::default());
+            groups.len() - 1
+        });
+        let group = &mut groups[index];
+        group.add_options(package.pip_options(sources));
+        if hashed {
+            group.add_options(Some(String::from("--require-hashes")));
+        }
+        group.keys.push(key);
+        group.lines.push(line);
+    }
+    groups
+}
+
+/// Plan pip invocations to download packages, and install them afterwards.
+///
+/// Named requirements are downloaded into `wheelhouse`, each with its own pip
+/// invocation, and installed from there without accessing indexes. Other
+/// requirements are not downloaded, but installed from their specifiers.
+/// Installs are planned in the order of `packages`.
+fn plan_downloads<I>(
+    packages: I,
+    sources: &Sources,
+    wheelhouse: &str,
+) -> (Vec<InstallGroup>, Vec<InstallGroup>)
+    where I: Iterator<Item=(String, PythonPackage)>
+{
+    let mut downloads = vec![];
+    let mut installs = vec![];
+    for (key, package) in packages {
+        let (hashed, line) = package.to_requirement_line();
+        let mut install = InstallGroup {
+            keys: vec![key.clone()],
+            lines: vec![line.clone()],
+            ..Default::default()
+        };
+        if package.version().is_some() {
+            let mut download = InstallGroup {
+                keys: vec![key],
+                lines: vec![line],
+                ..Default::default()
+            };
+            download.add_options(package.pip_options(sources));
+            install.add_options(vec![
+                String::from("--no-index"),
+                format!("--find-links={}", wheelhouse),
+            ]);
+            if hashed {
+                download.add_options(Some(String::from("--require-hashes")));
+            }
+            downloads.push(download);
+        } else {
+            install.add_options(package.pip_options(sources));
+        }
+        if hashed {
+            install.add_options(Some(String::from("--require-hashes")));
+        }
+        installs.push(install);
+    }
+    (downloads, installs)
+}
+
+/// Write packages as a requirements.txt file.
+///
+/// pip options (indexes and trusted hosts) needed by the packages are written
+/// once at the top, followed by one line for each package. A requirements
+/// file can't bind a package to an index, so all sources are written in
+/// priority order if there are named requirements.
+fn write_requirements<W>(
+    packages: &[(String, PythonPackage)],
+    sources: &Sources,
+    mut writer: W,
+) -> io::Result<()>
+    where W: Write
+{
+    let mut options = InstallGroup::default();
+    for (_, package) in packages {
+        if package.version().is_some() {
+            options.add_options(sources.index_options(None));
+        } else {
+            options.add_options(package.pip_options(sources));
+        }
+    }
+    for option in options.options.iter() {
+        writeln!(writer, "{}", option)?;
+    }
+    for (_, package) in packages {
+        writeln!(writer, "{}", package.to_requirement_line().1)?;
+    }
+    Ok(())
+}
+
+/// Write packages a sync would install, one requirement line each.
+///
+/// Lines are written in installation order, with pip options needed by each
+/// package appended to its line.
+fn write_dry_run<W>(
+    packages: &[(String, PythonPackage)],
+    sources: &Sources,
+    mut writer: W,
+) -> io::Result<()>
+    where W: Write
+{
+    for (_, package) in packages {
+        writeln!(writer, "{}", package.to_requirement_txt(sources).1)?;
+    }
+    Ok(())
+}
+
 pub struct Synchronizer {
     packaging: TempDir,
     lock: Lock,
+    ignore_installed: bool,
+    only: Vec<String>,
+    dry_run: bool,
+    verbosity: Verbosity,
+    find_links: Vec<PathBuf>,
+    offline: bool,
+    cache_dir: Option<PathBuf>,
+    no_cache: bool,
+    group_by_source: bool,
+    parallel_downloads: bool,
+    rollback_on_check_failure: bool,
 }
 
 impl Synchronizer {
     pub fn new(lock: Lock) -> Result<Self> {
         let tmp_dir = TempDir::new()?;
         vendors::Packaging::populate_to(tmp_dir.path())?;
-        Ok(Self { packaging: tmp_dir, lock })
+        Ok(Self {
+            packaging: tmp_dir,
+            lock,
+            ignore_installed: false,
+            only: vec![],
+            dry_run: false,
+            verbosity: Verbosity::Normal,
+            find_links: vec![],
+            offline: false,
+            cache_dir: None,
+            no_cache: false,
+            group_by_source: false,
+            parallel_downloads: false,
+            rollback_on_check_failure: false,
+        })
+    }
+
+    /// Make pip reinstall packages even if they are already installed.
+    pub fn ignore_installed(&mut self) {
+        self.ignore_installed = true;
+    }
+
+    /// Only sync the given packages (by key) and their dependencies.
+    pub fn only(&mut self, keys: Vec<String>) {
+        self.only = keys;
+    }
+
+    /// Print packages to install instead of installing them.
+    pub fn dry_run(&mut self) {
+        self.dry_run = true;
+    }
+
+    /// Control how much pip prints.
+    pub fn verbosity(&mut self, verbosity: Verbosity) {
+        self.verbosity = verbosity;
+    }
+
+    /// Also look for packages in these directories.
+    pub fn find_links(&mut self, dirs: Vec<PathBuf>) {
+        self.find_links = dirs;
+    }
+
+    /// Do not look up packages in any index, only in `find_links()`.
+    pub fn offline(&mut self) {
+        self.offline = true;
+    }
+
+    /// Use this directory as pip's cache, instead of pip's default.
+    pub fn cache_dir(&mut self, dir: Option<PathBuf>) {
+        self.cache_dir = dir;
+    }
+
+    /// Disable pip's cache. This wins over `cache_dir()`.
+    pub fn no_cache(&mut self) {
+        self.no_cache = true;
+    }
+
+    /// Install packages from each source in one pip run.
+    pub fn group_by_source(&mut self) {
+        self.group_by_source = true;
+    }
+
+    /// Download packages in parallel before installing them. This wins over
+    /// `group_by_source()`.
+    pub fn parallel_downloads(&mut self) {
+        self.parallel_downloads = true;
+    }
+
+    /// Remove added packages if any required package is missing, installed
+    /// at another version, or fails to import after installing.
+    pub fn rollback_on_check_failure(&mut self) {
+        self.rollback_on_check_failure = true;
+    }
+
+    fn configure_links(&self, cmd: &mut Command) {
+        if self.offline {
+            cmd.arg("--no-index");
+        }
+        for dir in self.find_links.iter() {
+            cmd.arg("--find-links").arg(dir);
+        }
+    }
+
+    fn configure_cache(&self, cmd: &mut Command) {
+        if self.no_cache {
+            cmd.arg("--no-cache-dir");
+        } else if let Some(ref dir) = self.cache_dir {
+            cmd.arg("--cache-dir").arg(dir);
+        }
+    }
+
+    /// Print a `[i/N] action keys` progress line, unless running quietly.
+    fn progress(&self, i: usize, total: usize, action: &str, keys: &[String]) {
+        if self.verbosity != Verbosity::Quiet {
+            eprintln!("[{}/{}] {} {}", i + 1, total, action, keys.join(", "));
+        }
     }
 
     fn evaluate_marker(&self, m: &Marker, int: &Interpreter) -> Result<bool> {
@@ -122,124 +703,320 @@ impl Synchronizer {
 
         // TODO: Show error if out.status() is not OK.
 
-        let s = String::from_utf8(output.stdout).unwrap();
+        let s = String::from_utf8(output.stdout)
+            .map_err(pythons::Error::from)?;
         if s == "True" {
             Ok(true)
         } else if s == "False" {
             Ok(false)
         } else {
-            let e = String::from_utf8(output.stderr).unwrap();
+            let e = String::from_utf8_lossy(&output.stderr).into_owned();
             Err(Error::InvalidMarkerError(s, e))
         }
     }
 
+    /// Collect packages required by `current` in installation order.
+    ///
+    /// Dependencies are walked depth-first, so they come before dependants.
+    /// Siblings are walked by canonical name (then key), so the order is
+    /// always the same for a given lock file.
     fn collect_required<'a>(
         &self,
         current: Ref<'a, Dependency>,
-        into: &mut HashMap<String, PythonPackage>,
+        seen: &mut HashSet<String>,
+        into: &mut Vec<(String, PythonPackage)>,
         interpreter: &Interpreter,
     ) -> Result<()> {
-        if into.contains_key(current.key()) {
+        if !seen.insert(current.key().to_string()) {
             return Ok(());
         }
-        if let Some(python) = current.python() {
-            into.insert(current.key().to_string(), python.clone());
+        if let Some(m) = current.markers() {
+            if !self.evaluate_marker(m, interpreter)? {
+                return Ok(());
+            }
         }
-        for (child, marker) in current.dependencies() {
+        let mut children: Vec<_> = current.dependencies().collect();
+        children.sort_by_cached_key(|(child, _)| {
+            let key = child.key().to_string();
+            let name = child.python().map(PythonPackage::canonical_name);
+            (name.unwrap_or_else(|| key.clone()), key)
+        });
+        for (child, marker) in children {
             if let Some(m) = marker {
                 if !self.evaluate_marker(m, interpreter)? {
                     continue;
                 }
             }
-            self.collect_required(Ref::clone(&child), into, interpreter)?;
+            let child = Ref::clone(&child);
+            self.collect_required(child, seen, into, interpreter)?;
+        }
+        if let Some(python) = current.python() {
+            into.push((current.key().to_string(), python.clone()));
         }
         Ok(())
     }
 
-    // TODO: The current installation plan implementation simply installs
-    // things in an undefined (implementation-defined) order. For best
-    // compatibility, packages should be installed from leaf to root, so
-    // that dependencies can be installed before their dependants.
+    /// Packages required by the given sections.
+    ///
+    /// Extra names are matched case-sensitively against section keys, i.e.
+    /// `dev` selects the `[dev]` section. All sections are checked before
+    /// anything is collected, and missing extras are reported together.
+    ///
+    /// If `only` is not empty, packages are collected from the named keys
+    /// instead, and the sections are ignored.
+    ///
+    /// Packages are returned from leaf to root, so dependencies come before
+    /// their dependants. The order between packages in a cycle is undefined.
     fn required_packages<'a, I>(
         &self,
         interpreter: &Interpreter,
         default: bool,
         extras: I,
-    ) -> Result<HashMap<String, PythonPackage>>
+        only: &[String],
+    ) -> Result<Vec<(String, PythonPackage)>>
         where I: Iterator<Item=&'a str>
     {
         let dependencies = self.lock.dependencies();
-        let mut deps = HashMap::new();
+        let mut seen = HashSet::new();
+        let mut deps = vec![];
+        if !only.is_empty() {
+            for key in only {
+                let dep = dependencies.package(key).ok_or_else(|| {
+                    Error::PackageNotFound(key.to_string())
+                })?;
+                self.collect_required(dep, &mut seen, &mut deps, interpreter)?;
+            }
+            return Ok(deps);
+        }
+        let mut sections = vec![];
         if default {
-            if let Some(s) = dependencies.default() {
-                self.collect_required(s, &mut deps, interpreter)?;
-            } else {
-                return Err(Error::DefaultSectionNotFound);
+            match dependencies.default() {
+                Some(s) => { sections.push(s); },
+                None => { return Err(Error::DefaultSectionNotFound); },
             }
         }
+        let mut missing = vec![];
         for extra in extras {
-            if let Some(s) = dependencies.extra(&extra) {
-                self.collect_required(s, &mut deps, interpreter)?;
-            } else {
-                return Err(Error::ExtraSectionNotFound(extra.to_string()));
+            match dependencies.extra(extra) {
+                Some(s) => { sections.push(s); },
+                None => { missing.push(extra.to_string()); },
             }
         }
+        if !missing.is_empty() {
+            let available = dependencies.extras().map(String::from).collect();
+            return Err(Error::ExtraSectionsNotFound(missing, available));
+        }
+        for s in sections {
+            self.collect_required(s, &mut seen, &mut deps, interpreter)?;
+        }
         Ok(deps)
     }
 
-    fn install_into<I, F>(
+    /// Start a pip process to download a group of packages into `dest`.
+    ///
+    /// The requirement file is returned with the process, and should be kept
+    /// around until pip finishes.
+    fn spawn_download<F>(
         &self,
-        prefix: &Path,
-        packages: I,
+        dest: &str,
+        group: InstallGroup,
+        command: F,
+    ) -> Result<(Vec<String>, NamedTempFile, Child)>
+        where F: Fn() -> std::result::Result<Command, projects::Error>
+    {
+        let mut f = NamedTempFile::new()?;
+        for line in group.lines.iter() {
+            writeln!(f, "{}", line)?;
+        }
+        let requirement = f.path().to_str().ok_or_else(|| {
+            Error::PathRepresentationError(f.path().to_path_buf())
+        })?;
+
+        let mut cmd = command()?;
+        cmd.args([
+            "-m", "pip", "download",
+            "--requirement", requirement,
+            "--dest", dest,
+            "--no-deps",
+        ]);
+        cmd.args(&group.options);
+        self.configure_links(&mut cmd);
+        self.configure_cache(&mut cmd);
+        cmd.env("PIP_DISABLE_PIP_VERSION_CHECK", "1");
+        cmd.env("PIP_REQUIRE_VIRTUALENV", "0");
+        self.verbosity.configure_pip(&mut cmd);
+        let child = cmd.spawn()?;
+        Ok((group.keys, f, child))
+    }
+
+    /// Download packages concurrently into `dest`.
+    ///
+    /// At most `limit` pip processes run at the same time. Progress is
+    /// reported as each process finishes.
+    fn download_into<F>(
+        &self,
+        dest: &Path,
+        groups: Vec<InstallGroup>,
+        limit: usize,
         command: F,
     ) -> Result<()>
-        where
-            I: Iterator<Item=(String, PythonPackage)>,
-            F: Fn() -> std::result::Result<Command, projects::Error>
+        where F: Fn() -> std::result::Result<Command, projects::Error>
     {
-        let env = prefix.to_str().ok_or_else(|| {
-            Error::PathRepresentationError(prefix.to_path_buf())
+        let dest = dest.to_str().ok_or_else(|| {
+            Error::PathRepresentationError(dest.to_path_buf())
         })?;
 
-        let mut requirements = HashMap::new();
-        for (key, package) in packages {
-            let (hashed, requirement_txt) = package.to_requirement_txt();
-            let mut f = NamedTempFile::new()?;
-            writeln!(f, "{}", requirement_txt)?;
+        let total = groups.len();
+        let mut pending = groups.into_iter();
+        let mut running = vec![];
+        let mut done = 0;
+        let mut error_context = vec![];
+        loop {
+            while running.len() < limit.max(1) && !interrupts::interrupted() {
+                match pending.next() {
+                    Some(group) => {
+                        running.push(self.spawn_download(
+                            dest, group, &command,
+                        )?);
+                    },
+                    None => { break; },
+                }
+            }
+            if running.is_empty() {
+                break;
+            }
+            if interrupts::interrupted() {
+                // The children may have been spawned after the signal.
+                for (_, _, child) in running.iter_mut() {
+                    child.kill().ok();
+                }
+            }
 
-            let name = f.path().to_str().ok_or_else(|| {
-                Error::PathRepresentationError(f.path().to_path_buf())
-            })?.to_string();
+            let mut i = 0;
+            let mut reaped = false;
+            while i < running.len() {
+                let status = match running[i].2.try_wait()? {
+                    Some(status) => status,
+                    None => { i += 1; continue; },
+                };
+                let (keys, _, _) = running.swap_remove(i);
+                reaped = true;
+                self.progress(done, total, "downloaded", &keys);
+                done += 1;
+                if !status.success() {
+                    for key in keys {
+                        error_context.push(InstallFailure {
+                            package: key,
+                            exit_code: status.code(),
+                            stderr: None,
+                        });
+                    }
+                }
+            }
+            if !reaped {
+                thread::sleep(Duration::from_millis(20));
+            }
+        }
 
-            // 3-tuple:
-            //  * The temporary file, for later cleanup.
-            //  * Whether hashes present.
-            //  * Path to the temporary file as string, to pass to pip.
-            // TempFile objects need to be kept around so they are not deleted.
-            requirements.insert(key, (f, hashed, name));
+        if interrupts::interrupted() {
+            Err(Error::Interrupted)
+        } else if error_context.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::DownloadCommandError(error_context))
         }
+    }
+
+    /// Download packages concurrently, and install them afterwards in the
+    /// order given.
+    fn download_and_install_into<F>(
+        &self,
+        prefix: &Path,
+        packages: Vec<(String, PythonPackage)>,
+        command: F,
+    ) -> Result<()>
+        where F: Fn() -> std::result::Result<Command, projects::Error>
+    {
+        let wheelhouse = TempDir::new()?;
+        let path = wheelhouse.path().to_str().ok_or_else(|| {
+            Error::PathRepresentationError(wheelhouse.path().to_path_buf())
+        })?;
+        let (downloads, installs) = plan_downloads(
+            packages.into_iter(),
+            self.lock.sources(),
+            path,
+        );
+        let limit = thread::available_parallelism()
+            .map(NonZeroUsize::get)
+            .unwrap_or(1);
+        self.download_into(wheelhouse.path(), downloads, limit, &command)?;
+        self.install_into(prefix, installs, &command)
+    }
+
+    fn install_into<F>(
+        &self,
+        prefix: &Path,
+        groups: Vec<InstallGroup>,
+        command: F,
+    ) -> Result<()>
+        where F: Fn() -> std::result::Result<Command, projects::Error>
+    {
+        let env = prefix.to_str().ok_or_else(|| {
+            Error::PathRepresentationError(prefix.to_path_buf())
+        })?;
 
         let mut error_context = vec![];
+        let total = groups.len();
+
+        // TODO: This is very noisy unless --quiet is given. Can we pipe pip's
+        // output and make is less so? (e.g. discard some lines matching
+        // certain patterns).
+        for (i, group) in groups.into_iter().enumerate() {
+            // Stop between pip runs, so no package is left half-installed.
+            if interrupts::interrupted() {
+                return Err(Error::Interrupted);
+            }
+            self.progress(i, total, "installing", &group.keys);
+
+            // The temporary file needs to be kept around until pip finishes,
+            // so it is not deleted.
+            let mut f = NamedTempFile::new()?;
+            for line in group.lines.iter() {
+                writeln!(f, "{}", line)?;
+            }
+            let requirement = f.path().to_str().ok_or_else(|| {
+                Error::PathRepresentationError(f.path().to_path_buf())
+            })?;
 
-        // TODO: This is very noisy. Can we pipe pip's output and make is
-        // less so? (e.g. discard some lines matching certain patterns).
-        for (key, (_, hashed, requirement)) in requirements.into_iter() {
             let mut cmd = command()?;
             cmd.args(&[
                 "-m", "pip", "install",
-                "--requirement", &requirement,
+                "--requirement", requirement,
                 "--prefix", env,
                 "--no-deps",
             ]);
+            if self.ignore_installed {
+                cmd.arg("--ignore-installed");
+            }
+            cmd.args(&group.options);
+            self.configure_links(&mut cmd);
+            self.configure_cache(&mut cmd);
             cmd.env("PIP_DISABLE_PIP_VERSION_CHECK", "1");
             cmd.env("PIP_NO_WARN_SCRIPT_LOCATION", "0");
             cmd.env("PIP_REQUIRE_VIRTUALENV", "0");
-            if hashed {
-                cmd.arg("--require-hashes");
+            self.verbosity.configure_pip(&mut cmd);
+            let (status, stderr) = status_capturing_stderr(&mut cmd)?;
+            if interrupts::interrupted() {
+                return Err(Error::Interrupted);
             }
-            let status = cmd.status()?;
             if !status.success() {
-                error_context.push((key.to_string(), status.code()))
+                for key in group.keys {
+                    error_context.push(InstallFailure {
+                        package: key,
+                        exit_code: status.code(),
+                        stderr: Some(stderr.clone()),
+                    });
+                }
             }
         }
 
@@ -255,17 +1032,1008 @@ impl Synchronizer {
         project: &Project,
         default: bool,
         extras: I,
-    ) -> Result<()>
+    ) -> Result<Summary>
         where I: Iterator<Item=&'a str>
     {
         let interpreter = project.base_interpreter();
-        let packages = self.required_packages(interpreter, default, extras)?;
-        self.install_into(
-            &project.env_root()?,
-            packages.into_iter(),
-            || project.command(None),
-        )?;
+        let packages =
+            self.required_packages(interpreter, default, extras, &self.only)?;
+        let required: Vec<_> = packages.iter()
+            .map(|(_, p)| (p.canonical_name(), p.version().map(String::from)))
+            .collect();
+        let site_packages = project.presumed_site_packages()?;
+
+        // Remember what's already there, so we know what to roll back.
+        let before_dists = installed_distributions(&site_packages)?;
+        if self.dry_run {
+            let stdout = io::stdout();
+            write_dry_run(&packages, self.lock.sources(), stdout.lock())?;
+            return Ok(Summary::new(&required, &before_dists, &before_dists));
+        }
+        let before: HashSet<_> = before_dists.iter()
+            .map(Distribution::canonical_name)
+            .collect();
+        let names: Vec<_> = required.iter().map(|(n, _)| n.clone()).collect();
+        let env_root = project.env_root()?;
+
+        // Ctrl-C also reaches pip, which cleans up after itself. Catch it so
+        // we can stop starting new pip runs, and return normally to clean up
+        // our temporary files. Packages already installed are kept, and
+        // running sync again finishes the job.
+        let _interrupt_guard = interrupts::catch();
+
+        let command = || project.command(None);
+        if self.parallel_downloads {
+            self.download_and_install_into(&env_root, packages, command)?;
+        } else {
+            let groups = plan_installs(
+                packages.into_iter(),
+                self.lock.sources(),
+                self.group_by_source,
+            );
+            self.install_into(&env_root, groups, command)?;
+        }
+        verify_layout(&env_root, &site_packages, &names)?;
+
+        let installed = distributions::find(&site_packages)?;
+        if self.rollback_on_check_failure {
+            self.check_or_roll_back(&required, &before, &installed, command)?;
+        }
+
         // TODO: Remove packages not listed in lock.
+        Ok(Summary::new(&required, &before_dists, &installed))
+    }
+
+    /// Check required packages are installed as locked, or roll back.
+    ///
+    /// A package fails the check if it is missing, installed at another
+    /// version, or any of its top-level modules can't be imported. Packages
+    /// upgraded or downgraded by this sync can't be restored since we don't
+    /// have their previous artifacts, so only packages newly added (i.e. not
+    /// in `before`) are removed.
+    fn check_or_roll_back<F>(
+        &self,
+        required: &[(String, Option<String>)],
+        before: &HashSet<String>,
+        installed: &[Distribution],
+        command: F,
+    ) -> Result<()>
+        where F: Fn() -> std::result::Result<Command, projects::Error>
+    {
+        let mut unsatisfied = find_unsatisfied(required, installed);
+        unsatisfied.extend(find_unimportable(required, installed, &command)?);
+        unsatisfied.sort();
+        unsatisfied.dedup();
+        if unsatisfied.is_empty() {
+            return Ok(());
+        }
+        let added = find_added(before, installed);
+        self.uninstall(&added, command)?;
+        Err(Error::PostSyncCheckError(unsatisfied, added))
+    }
+
+    fn uninstall<F>(&self, names: &[String], command: F) -> Result<()>
+        where F: Fn() -> std::result::Result<Command, projects::Error>
+    {
+        if names.is_empty() {
+            return Ok(());
+        }
+        let mut cmd = command()?;
+        cmd.args(["-m", "pip", "uninstall", "--yes"]);
+        cmd.args(names);
+        cmd.env("PIP_DISABLE_PIP_VERSION_CHECK", "1");
+        cmd.env("PIP_REQUIRE_VIRTUALENV", "0");
+        self.verbosity.configure_pip(&mut cmd);
+        let status = cmd.status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            let context = names.iter()
+                .map(|n| InstallFailure {
+                    package: n.to_string(),
+                    exit_code: status.code(),
+                    stderr: None,
+                })
+                .collect();
+            Err(Error::InstallCommandError(context))
+        }
+    }
+
+    /// Write packages `sync()` would install as a requirements.txt file.
+    ///
+    /// Markers are evaluated against `interpreter`. Packages are sorted by
+    /// their keys so the output is stable.
+    pub fn export<'a, I, W>(
+        &self,
+        interpreter: &Interpreter,
+        default: bool,
+        extras: I,
+        writer: W,
+    ) -> Result<()>
+        where I: Iterator<Item=&'a str>, W: Write
+    {
+        let mut packages =
+            self.required_packages(interpreter, default, extras, &[])?;
+        packages.sort_by(|a, b| a.0.cmp(&b.0));
+        write_requirements(&packages, self.lock.sources(), writer)?;
         Ok(())
     }
+
+    /// Write the lock file, pinned to versions installed in the project.
+    ///
+    /// This should be called after `sync()`, so the versions reflect what
+    /// was actually resolved. Packages not found in the environment are
+    /// written as-is.
+    pub fn freeze_into(&self, project: &Project, path: &Path) -> Result<()> {
+        self.freeze_with(&project.installed_distributions()?, path)
+    }
+
+    fn freeze_with(
+        &self,
+        installed: &[Distribution],
+        path: &Path,
+    ) -> Result<()> {
+        let versions = installed.iter()
+            .map(|d| (d.canonical_name(), d.version().to_string()))
+            .collect();
+        self.lock.dependencies().pin_versions(&versions);
+
+        let writer = BufWriter::new(File::create(path)?);
+        self.lock.write(writer).map_err(Error::LockWriteError)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, write};
+
+    fn install(site_packages: &Path, name: &str) {
+        let dist_info = site_packages.join(format!("{}-1.0.dist-info", name));
+        create_dir_all(&dist_info).unwrap();
+        write(
+            dist_info.join("METADATA"),
+            format!("Metadata-Version: 2.1\nName: {}\nVersion: 1.0\n", name),
+        ).unwrap();
+    }
+
+    fn plan_fixture(group_by_source: bool) -> Vec<InstallGroup> {
+        // Sources are told apart by key, even if their names are the same.
+        let lock: Lock = serde_json::from_str(r#"{
+            "sources": {
+                "pypi": {"url": "https://pypi.org/simple"},
+                "private": {
+                    "url": "https://example.com/simple",
+                    "name": "pypi"
+                }
+            },
+            "dependencies": {
+                "foo": {"python": {
+                    "name": "foo", "version": "1.0", "source": "pypi"
+                }},
+                "bar": {"python": {
+                    "name": "bar", "version": "2.0", "source": "private"
+                }},
+                "baz": {"python": {
+                    "name": "baz", "version": "3.0", "source": "pypi"
+                }}
+            }
+        }"#).unwrap();
+        let mut packages = lock.dependencies().iter()
+            .filter_map(|(k, d)| Some((k.to_string(), d.python()?.clone())))
+            .collect::<Vec<_>>();
+        packages.sort_by(|a, b| a.0.cmp(&b.0));
+        plan_installs(packages.into_iter(), lock.sources(), group_by_source)
+    }
+
+    /// Fake interpreter that logs its arguments into `log`.
+    #[cfg(unix)]
+    fn logging_command(
+        log: &Path,
+    ) -> impl Fn() -> std::result::Result<Command, projects::Error> {
+        let script = format!(r#"echo "$@" >> {:?}"#, log);
+        move || {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(&script).arg("sh");
+            Ok(cmd)
+        }
+    }
+
+    /// A single pip run installing foo.
+    #[cfg(unix)]
+    fn single_group() -> Vec<InstallGroup> {
+        vec![InstallGroup {
+            keys: vec![String::from("foo")],
+            lines: vec![String::from("foo == 1.0")],
+            ..Default::default()
+        }]
+    }
+
+    #[test]
+    fn test_plan_installs() {
+        let groups = plan_fixture(false);
+        let keys: Vec<_> = groups.iter().map(|g| g.keys.clone()).collect();
+        assert_eq!(keys, vec![vec!["bar"], vec!["baz"], vec!["foo"]]);
+        assert_eq!(groups[0].lines, vec!["bar == 2.0"]);
+        assert_eq!(
+            groups[0].options,
+            vec!["--index-url=https://example.com/simple"],
+        );
+    }
+
+    #[test]
+    fn test_plan_installs_trusted_host_once() {
+        let lock: Lock = serde_json::from_str(r#"{
+            "sources": {
+                "a": {
+                    "url": "https://example.com/a/simple",
+                    "no_verify_ssl": true,
+                    "priority": 0
+                },
+                "b": {
+                    "url": "https://example.com/b/simple",
+                    "no_verify_ssl": true,
+                    "priority": 1
+                }
+            },
+            "dependencies": {
+                "foo": {"python": {"name": "foo", "version": "1.0"}},
+                "bar": {"python": {
+                    "name": "bar",
+                    "url": "https://example.com/bar.zip",
+                    "no_verify_ssl": true
+                }}
+            }
+        }"#).unwrap();
+        let packages = lock.dependencies().iter()
+            .filter_map(|(k, d)| Some((k.to_string(), d.python()?.clone())))
+            .collect::<Vec<_>>();
+
+        for group_by_source in [false, true].iter() {
+            let groups = plan_installs(
+                packages.clone().into_iter(),
+                lock.sources(),
+                *group_by_source,
+            );
+            for group in groups.iter() {
+                assert!(group.lines.iter().all(|l| !l.contains("--")));
+                let trusted = group.options.iter()
+                    .filter(|o| o.starts_with("--trusted-host="))
+                    .count();
+                assert_eq!(trusted, 1, "{:?}", group.options);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_install_from_file_index() {
+        let tmp = TempDir::new().unwrap();
+        let log = tmp.path().join("log");
+
+        let command = logging_command(&log);
+
+        let lock: Lock = serde_json::from_str(r#"{
+            "sources": {
+                "local": {
+                    "url": "file:///srv/wheelhouse/simple",
+                    "no_verify_ssl": true
+                }
+            },
+            "dependencies": {
+                "": {"dependencies": {"foo": null}},
+                "foo": {"python": {"name": "foo", "version": "1.0"}}
+            }
+        }"#).unwrap();
+        let packages = lock.dependencies().iter()
+            .filter_map(|(k, d)| Some((k.to_string(), d.python()?.clone())))
+            .collect::<Vec<_>>();
+        let groups = plan_installs(packages.into_iter(), lock.sources(), true);
+
+        let sync = synchronizer(lock);
+        sync.install_into(tmp.path(), groups, command).unwrap();
+
+        let log = std::fs::read_to_string(&log).unwrap();
+        assert!(log.contains("--index-url=file:///srv/wheelhouse/simple"));
+        assert!(!log.contains("--trusted-host"), "{}", log);
+    }
+
+    #[test]
+    fn test_plan_installs_group_by_source() {
+        let groups = plan_fixture(true);
+        assert_eq!(groups.len(), 2);
+
+        assert_eq!(groups[0].keys, vec!["bar"]);
+        assert_eq!(groups[0].lines, vec!["bar == 2.0"]);
+        assert_eq!(
+            groups[0].options,
+            vec!["--index-url=https://example.com/simple"],
+        );
+
+        assert_eq!(groups[1].keys, vec!["baz", "foo"]);
+        assert_eq!(groups[1].lines, vec!["baz == 3.0", "foo == 1.0"]);
+        assert_eq!(
+            groups[1].options,
+            vec!["--index-url=https://pypi.org/simple"],
+        );
+    }
+
+    fn synchronizer(lock: Lock) -> Synchronizer {
+        Synchronizer {
+            packaging: TempDir::new().unwrap(),
+            lock,
+            ignore_installed: false,
+            only: vec![],
+            dry_run: false,
+            verbosity: Verbosity::Normal,
+            find_links: vec![],
+            offline: false,
+            cache_dir: None,
+            no_cache: false,
+            group_by_source: false,
+            parallel_downloads: false,
+            rollback_on_check_failure: false,
+        }
+    }
+
+    fn chain_lock() -> Lock {
+        serde_json::from_str(r#"{
+            "sources": {"pypi": {"url": "https://pypi.org/simple"}},
+            "dependencies": {
+                "": {"dependencies": {"a": null, "c": null}},
+                "a": {
+                    "python": {"name": "a", "version": "1.0"},
+                    "dependencies": {"b": null}
+                },
+                "b": {
+                    "python": {"name": "b", "version": "1.0"},
+                    "dependencies": {"c": null}
+                },
+                "c": {"python": {"name": "c", "version": "1.0"}}
+            }
+        }"#).unwrap()
+    }
+
+    #[test]
+    fn test_freeze() {
+        let tmp = TempDir::new().unwrap();
+        let site_packages = tmp.path().join("site-packages");
+        install(&site_packages, "a");
+        install(&site_packages, "c");
+        let installed = distributions::find(&site_packages).unwrap();
+
+        let lock: Lock = serde_json::from_str(r#"{
+            "sources": {"pypi": {"url": "https://pypi.org/simple"}},
+            "dependencies": {
+                "": {"dependencies": {"a": null, "b": null}},
+                "a": {"python": {"name": "a", "version": "0.9"}},
+                "b": {"python": {"name": "b", "version": "2.0"}}
+            },
+            "hashes": {"a": ["sha256:abcd"]}
+        }"#).unwrap();
+        assert!(lock.dependencies().package("a").unwrap()
+            .python().unwrap().hashes().is_some());
+        let path = tmp.path().join("frozen.json");
+        synchronizer(lock).freeze_with(&installed, &path).unwrap();
+
+        let frozen = projects::read_lock_file(&path).unwrap();
+        let deps = frozen.dependencies();
+        let a = deps.package("a").unwrap();
+        let a = a.python().unwrap();
+        assert_eq!(a.version(), Some("1.0"));
+        assert!(a.hashes().is_none(), "stale hashes should be dropped");
+        let b = deps.package("b").unwrap();
+        assert_eq!(b.python().unwrap().version(), Some("2.0"));
+        assert!(deps.package("c").is_none(), "only locked packages are kept");
+    }
+
+    #[test]
+    fn test_required_packages_order() {
+        let sync = synchronizer(chain_lock());
+        let interpreter = Interpreter::new("python", PathBuf::from("python"));
+        let packages = sync.required_packages(
+            &interpreter,
+            true,
+            std::iter::empty(),
+            &[],
+        ).unwrap();
+        let keys: Vec<_> = packages.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, vec!["c", "b", "a"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_install_order() {
+        let tmp = TempDir::new().unwrap();
+        let log = tmp.path().join("log");
+
+        // Fake interpreter that logs requirements passed to each pip run.
+        let script = format!(r#"echo $(cat "$5") >> {:?}"#, log);
+        let command = || {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(&script).arg("sh");
+            Ok(cmd)
+        };
+
+        let sync = synchronizer(chain_lock());
+        let interpreter = Interpreter::new("python", PathBuf::from("python"));
+        for group_by_source in [false, true].iter() {
+            let packages = sync.required_packages(
+                &interpreter,
+                true,
+                std::iter::empty(),
+                &[],
+            ).unwrap();
+            let groups = plan_installs(
+                packages.into_iter(),
+                sync.lock.sources(),
+                *group_by_source,
+            );
+            sync.install_into(tmp.path(), groups, command).unwrap();
+        }
+
+        let log = std::fs::read_to_string(&log).unwrap();
+        assert_eq!(log.lines().collect::<Vec<_>>(), vec![
+            "c == 1.0",
+            "b == 1.0",
+            "a == 1.0",
+            "c == 1.0 b == 1.0 a == 1.0",
+        ]);
+    }
+
+    #[test]
+    fn test_required_packages_missing_extras() {
+        let sync = synchronizer(chain_lock());
+        let interpreter = Interpreter::new("python", PathBuf::from("python"));
+        let result = sync.required_packages(
+            &interpreter,
+            true,
+            vec!["dev", "Test", "docs"].into_iter(),
+            &[],
+        );
+        match result {
+            Err(ref e @ Error::ExtraSectionsNotFound(..)) => {
+                assert_eq!(
+                    e.to_string(),
+                    "sections not found in lock file: dev, Test, docs \
+                     (no extra sections available)",
+                );
+            },
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_required_packages_tie_break() {
+        static JSON: &str = r#"{
+            "dependencies": {
+                "": {"dependencies": {"z": null, "b": null, "a": null}},
+                "z": {
+                    "python": {"name": "Alpha", "version": "1.0"},
+                    "dependencies": {"y": null, "x": null}
+                },
+                "b": {"python": {"name": "b", "version": "1.0"}},
+                "a": {"python": {"name": "c", "version": "1.0"}},
+                "y": {"python": {"name": "y", "version": "1.0"}},
+                "x": {"python": {"name": "x", "version": "1.0"}}
+            }
+        }"#;
+        let interpreter = Interpreter::new("python", PathBuf::from("python"));
+        let keys = || {
+            let sync = synchronizer(serde_json::from_str(JSON).unwrap());
+            sync.required_packages(&interpreter, true, std::iter::empty(), &[])
+                .unwrap()
+                .into_iter()
+                .map(|(k, _)| k)
+                .collect::<Vec<_>>()
+        };
+        let expected = vec!["x", "y", "z", "b", "a"];
+        for _ in 0..10 {
+            assert_eq!(keys(), expected);
+        }
+    }
+
+    #[test]
+    fn test_required_packages_only() {
+        let sync = synchronizer(chain_lock());
+        let interpreter = Interpreter::new("python", PathBuf::from("python"));
+        let required = |only: &[&str]| {
+            let only: Vec<_> = only.iter().map(|k| k.to_string()).collect();
+            sync.required_packages(
+                &interpreter,
+                true,
+                std::iter::empty(),
+                &only,
+            )
+        };
+
+        let packages = required(&["b"]).unwrap();
+        let keys: Vec<_> = packages.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, vec!["c", "b"]);
+
+        match required(&["c", "missing"]) {
+            Err(Error::PackageNotFound(ref k)) => assert_eq!(k, "missing"),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_download_and_install_order() {
+        let tmp = TempDir::new().unwrap();
+        let log = tmp.path().join("log");
+
+        // Fake interpreter that logs the pip subcommand and requirements.
+        // Downloads are slowed down so an install starting before them
+        // would show up in the log.
+        let script = format!(
+            r#"if [ "$3" = download ]; then sleep 0.2; fi
+            echo "$3 $(cat "$5")" >> {:?}"#,
+            log,
+        );
+        let command = || {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(&script).arg("sh");
+            Ok(cmd)
+        };
+
+        let sync = synchronizer(chain_lock());
+        let packages = ["c", "b", "a"].iter().map(|k| {
+            let dep = sync.lock.dependencies().iter()
+                .find(|(key, _)| key == k)
+                .unwrap().1;
+            (k.to_string(), dep.python().unwrap().clone())
+        }).collect();
+        sync.download_and_install_into(tmp.path(), packages, command)
+            .unwrap();
+
+        let log = std::fs::read_to_string(&log).unwrap();
+        let lines: Vec<_> = log.lines().collect();
+        assert_eq!(lines.len(), 6);
+
+        let mut downloads = lines[..3].to_vec();
+        downloads.sort();
+        assert_eq!(downloads, vec![
+            "download a == 1.0",
+            "download b == 1.0",
+            "download c == 1.0",
+        ]);
+        assert_eq!(&lines[3..], &[
+            "install c == 1.0",
+            "install b == 1.0",
+            "install a == 1.0",
+        ]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_download_limit() {
+        let tmp = TempDir::new().unwrap();
+        let log = tmp.path().join("log");
+
+        // Fake interpreter that logs when each download starts and ends.
+        let script = format!(
+            r#"echo start >> {:?}; sleep 0.1; echo end >> {:?}"#,
+            log, log,
+        );
+        let command = || {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(&script).arg("sh");
+            Ok(cmd)
+        };
+
+        let sync = synchronizer(chain_lock());
+        let packages = ["c", "b", "a"].iter().map(|k| {
+            let dep = sync.lock.dependencies().iter()
+                .find(|(key, _)| key == k)
+                .unwrap().1;
+            (k.to_string(), dep.python().unwrap().clone())
+        });
+        let dest = tmp.path().to_str().unwrap();
+        let (downloads, _) = plan_downloads(
+            packages, sync.lock.sources(), dest,
+        );
+        sync.download_into(tmp.path(), downloads, 2, command).unwrap();
+
+        let log = std::fs::read_to_string(&log).unwrap();
+        let (mut count, mut most) = (0, 0);
+        for line in log.lines() {
+            if line == "start" {
+                count += 1;
+                most = most.max(count);
+            } else {
+                count -= 1;
+            }
+        }
+        assert_eq!(log.lines().count(), 6);
+        assert_eq!(most, 2);
+    }
+
+    #[test]
+    fn test_write_requirements() {
+        let lock: Lock = serde_json::from_str(r#"{
+            "sources": {
+                "pypi": {"url": "https://pypi.org/simple", "priority": 0},
+                "private": {
+                    "url": "https://example.com/simple",
+                    "no_verify_ssl": true,
+                    "priority": 1
+                }
+            },
+            "dependencies": {
+                "": {"dependencies": {"foo": null, "bar": null}},
+                "foo": {"python": {"name": "foo", "version": "1.0"}},
+                "bar": {"python": {
+                    "name": "bar", "version": "2.0", "source": "private"
+                }}
+            },
+            "hashes": {"foo": ["sha256:abc"]}
+        }"#).unwrap();
+        let sync = synchronizer(lock);
+        let interpreter = Interpreter::new("python", PathBuf::from("python"));
+
+        let mut buf = vec![];
+        sync.export(&interpreter, true, std::iter::empty(), &mut buf)
+            .unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), unindent("
+            --index-url=https://pypi.org/simple
+            --extra-index-url=https://example.com/simple
+            --trusted-host=example.com
+            bar == 2.0
+            foo == 1.0 --hash sha256:abc
+        "));
+    }
+
+    #[test]
+    fn test_write_requirements_extra_sources() {
+        let lock: Lock = serde_json::from_str(r#"{
+            "sources": {
+                "private": {
+                    "url": "https://example.com/simple",
+                    "priority": 0,
+                    "extra": true
+                }
+            },
+            "dependencies": {
+                "": {"dependencies": {"foo": null, "bar": null}},
+                "foo": {"python": {"name": "foo", "version": "1.0"}},
+                "bar": {"python": {
+                    "name": "bar", "version": "2.0", "source": "private"
+                }}
+            }
+        }"#).unwrap();
+        let sync = synchronizer(lock);
+        let interpreter = Interpreter::new("python", PathBuf::from("python"));
+
+        // foo is not bound to a source, and is still found on PyPI.
+        let mut buf = vec![];
+        sync.export(&interpreter, true, std::iter::empty(), &mut buf)
+            .unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), unindent("
+            --extra-index-url=https://example.com/simple
+            bar == 2.0
+            foo == 1.0
+        "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_required_packages_self_marker() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let lock: Lock = serde_json::from_str(r#"{
+            "dependencies": {
+                "": {"dependencies": {"a": null, "b": null}},
+                "a": {
+                    "python": {"name": "a", "version": "1.0"},
+                    "dependencies": {"c": null}
+                },
+                "b": {
+                    "markers": ["python_version < '1'"],
+                    "python": {"name": "b", "version": "1.0"},
+                    "dependencies": {"c": null, "d": null}
+                },
+                "c": {"python": {"name": "c", "version": "1.0"}},
+                "d": {"python": {"name": "d", "version": "1.0"}}
+            }
+        }"#).unwrap();
+        let sync = synchronizer(lock);
+
+        // Fake interpreter that evaluates only b's marker to false.
+        let tmp = TempDir::new().unwrap();
+        let python = tmp.path().join("python");
+        write(&python, unindent(r#"
+            #!/bin/sh
+            case "$2" in
+            *"python_version < '1'"*) printf False ;;
+            *) printf True ;;
+            esac
+        "#)).unwrap();
+        let permissions = std::fs::Permissions::from_mode(0o755);
+        std::fs::set_permissions(&python, permissions).unwrap();
+        let interpreter = Interpreter::new("python", python);
+
+        let packages = sync.required_packages(
+            &interpreter,
+            true,
+            std::iter::empty(),
+            &[],
+        ).unwrap();
+        let keys: Vec<_> = packages.into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["c", "a"]);
+    }
+
+    #[test]
+    fn test_write_dry_run() {
+        let sync = synchronizer(chain_lock());
+        let interpreter = Interpreter::new("python", PathBuf::from("python"));
+        let packages = sync.required_packages(
+            &interpreter,
+            true,
+            std::iter::empty(),
+            &[],
+        ).unwrap();
+
+        let mut buf = vec![];
+        write_dry_run(&packages, sync.lock.sources(), &mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), unindent("
+            c == 1.0 --index-url=https://pypi.org/simple
+            b == 1.0 --index-url=https://pypi.org/simple
+            a == 1.0 --index-url=https://pypi.org/simple
+        "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_ignore_installed() {
+        let tmp = TempDir::new().unwrap();
+        let log = tmp.path().join("log");
+
+        let command = logging_command(&log);
+
+        let mut sync = synchronizer(chain_lock());
+        sync.install_into(tmp.path(), single_group(), &command).unwrap();
+        sync.ignore_installed();
+        sync.install_into(tmp.path(), single_group(), &command).unwrap();
+
+        let log = std::fs::read_to_string(&log).unwrap();
+        let lines: Vec<_> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(!lines[0].contains("--ignore-installed"));
+        assert!(lines[1].contains("--ignore-installed"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_offline() {
+        let tmp = TempDir::new().unwrap();
+        let log = tmp.path().join("log");
+
+        let command = logging_command(&log);
+
+        let mut sync = synchronizer(chain_lock());
+        sync.find_links(vec![PathBuf::from("/srv/wheels")]);
+        sync.install_into(tmp.path(), single_group(), &command).unwrap();
+        sync.offline();
+        sync.install_into(tmp.path(), single_group(), &command).unwrap();
+
+        let log = std::fs::read_to_string(&log).unwrap();
+        let lines: Vec<_> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(!lines[0].contains("--no-index"));
+        assert!(lines[0].ends_with("--find-links /srv/wheels"));
+        assert!(lines[1].ends_with("--no-index --find-links /srv/wheels"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cache_dir() {
+        let tmp = TempDir::new().unwrap();
+        let log = tmp.path().join("log");
+
+        let command = logging_command(&log);
+
+        let mut sync = synchronizer(chain_lock());
+        sync.install_into(tmp.path(), single_group(), &command).unwrap();
+        sync.cache_dir(Some(PathBuf::from("/tmp/cache")));
+        sync.install_into(tmp.path(), single_group(), &command).unwrap();
+        sync.no_cache();
+        sync.install_into(tmp.path(), single_group(), &command).unwrap();
+
+        let log = std::fs::read_to_string(&log).unwrap();
+        let lines: Vec<_> = log.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(!lines[0].contains("cache-dir"));
+        assert!(lines[1].ends_with("--cache-dir /tmp/cache"));
+        assert!(lines[2].ends_with("--no-cache-dir"));
+    }
+
+    #[test]
+    fn test_post_sync_check_rollback() {
+        let env = TempDir::new().unwrap();
+        let site_packages = env.path().join("site-packages");
+        install(&site_packages, "existing");
+        let before = installed_names(&site_packages).unwrap();
+
+        // Simulate a sync installing "foo" and "bar" at the wrong version.
+        install(&site_packages, "foo");
+        install(&site_packages, "Bar");
+        let installed = distributions::find(&site_packages).unwrap();
+
+        let required = vec![
+            (String::from("bar"), Some(String::from("2.0"))),
+            (String::from("existing"), Some(String::from("1.0"))),
+            (String::from("foo"), None),
+        ];
+        assert_eq!(find_unsatisfied(&required, &installed), vec!["bar"]);
+        assert_eq!(find_added(&before, &installed), vec!["bar", "foo"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_post_sync_check_uninstall() {
+        let tmp = TempDir::new().unwrap();
+        let log = tmp.path().join("log");
+        let command = logging_command(&log);
+
+        let site_packages = tmp.path().join("site-packages");
+        install(&site_packages, "existing");
+        let before = installed_names(&site_packages).unwrap();
+        install(&site_packages, "foo");
+        install(&site_packages, "bar");
+        let installed = distributions::find(&site_packages).unwrap();
+
+        let required = vec![
+            (String::from("bar"), Some(String::from("2.0"))),
+            (String::from("foo"), None),
+        ];
+        let sync = synchronizer(chain_lock());
+        let result = sync.check_or_roll_back(
+            &required, &before, &installed, command,
+        );
+        match result {
+            Err(Error::PostSyncCheckError(failed, removed)) => {
+                assert_eq!(failed, vec!["bar"]);
+                assert_eq!(removed, vec!["bar", "foo"]);
+            },
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        let log = std::fs::read_to_string(&log).unwrap();
+        assert_eq!(log, "-m pip uninstall --yes bar foo\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_post_sync_check_unimportable() {
+        let tmp = TempDir::new().unwrap();
+        let log = tmp.path().join("log");
+
+        // Fake interpreter that fails to import foo's module, and logs
+        // everything else it's asked to do.
+        let script = format!(
+            r#"if [ "$1" = -c ]; then shift 2; echo "$@" >> {0:?}; \
+               echo foo; else echo "$@" >> {0:?}; fi"#,
+            log,
+        );
+        let command = || {
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(&script).arg("sh");
+            Ok(cmd)
+        };
+
+        let site_packages = tmp.path().join("site-packages");
+        install(&site_packages, "foo");
+        install(&site_packages, "bar");
+        install(&site_packages, "extra");
+        for (name, modules) in &[("foo", "foo\n_foo\n"), ("extra", "extra")] {
+            let dist_info = format!("{}-1.0.dist-info", name);
+            let path = site_packages.join(dist_info).join("top_level.txt");
+            write(path, modules).unwrap();
+        }
+        let installed = distributions::find(&site_packages).unwrap();
+
+        let required = vec![
+            (String::from("bar"), None),
+            (String::from("foo"), Some(String::from("1.0"))),
+        ];
+        let sync = synchronizer(chain_lock());
+        let before = HashSet::new();
+        let result = sync.check_or_roll_back(
+            &required, &before, &installed, command,
+        );
+        match result {
+            Err(Error::PostSyncCheckError(failed, _)) => {
+                assert_eq!(failed, vec!["foo"]);
+            },
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        // Only required packages listing top-level modules are checked.
+        let log = std::fs::read_to_string(&log).unwrap();
+        let lines: Vec<_> = log.lines().collect();
+        assert_eq!(lines[0], "foo:foo foo:_foo");
+        assert_eq!(lines[1], "-m pip uninstall --yes bar extra foo");
+    }
+
+    #[test]
+    fn test_install_command_error_display() {
+        let failure = |package: &str, exit_code, stderr: Option<&str>| {
+            InstallFailure {
+                package: package.to_string(),
+                exit_code,
+                stderr: stderr.map(String::from),
+            }
+        };
+        let e = Error::InstallCommandError(vec![
+            failure("requests", Some(1), Some(
+                "ERROR: No matching distribution found for requests==99\n\n",
+            )),
+            failure("urllib3", None, None),
+            failure("idna", Some(2), Some("Traceback\n  oops\n")),
+        ]);
+        assert_eq!(e.to_string(), unindent("
+            failed to install 3 package(s):
+              requests (exit 1): ERROR: No matching distribution found \
+            for requests==99
+              urllib3 (no exit code)
+              idna (exit 2): oops").trim_end());
+    }
+
+    #[test]
+    fn test_summary() {
+        let env = TempDir::new().unwrap();
+        let site_packages = env.path().join("site-packages");
+        install(&site_packages, "foo");
+        install(&site_packages, "old");
+        let before = distributions::find(&site_packages).unwrap();
+
+        // Simulate a sync installing "bar" and removing "old".
+        install(&site_packages, "bar");
+        std::fs::remove_dir_all(site_packages.join("old-1.0.dist-info"))
+            .unwrap();
+        let after = distributions::find(&site_packages).unwrap();
+
+        let required = vec![
+            (String::from("bar"), Some(String::from("1.0"))),
+            (String::from("foo"), Some(String::from("1.0"))),
+        ];
+        let summary = Summary::new(&required, &before, &after);
+        assert_eq!(
+            summary.to_string(),
+            "installed 1, removed 1, 1 up to date",
+        );
+
+        let summary = Summary::new(&required, &after, &after);
+        assert_eq!(summary.to_string(), "all 2 packages up to date");
+    }
+
+    #[test]
+    fn test_verify_layout() {
+        let env = TempDir::new().unwrap();
+        let expected = env.path().join("lib/python3.7/site-packages");
+        install(&expected, "Foo_Bar");
+
+        let names = vec![String::from("foo-bar")];
+        assert!(verify_layout(env.path(), &expected, &names).is_ok());
+    }
+
+    #[test]
+    fn test_verify_layout_mismatch() {
+        let env = TempDir::new().unwrap();
+        let expected = env.path().join("lib/python3.7/site-packages");
+        let actual = env.path().join("lib64/python3.7/site-packages");
+        install(&actual, "foo");
+
+        let names = vec![String::from("foo")];
+        match verify_layout(env.path(), &expected, &names) {
+            Err(Error::SitePackagesMismatchError(ref e, Some(ref p))) => {
+                assert_eq!(e, &expected);
+                assert_eq!(p, &actual);
+            },
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
 }
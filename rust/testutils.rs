@@ -0,0 +1,28 @@
+use std::path::Path;
+
+use crate::pythons::Interpreter;
+
+pub struct Interpreters(Option<std::fs::ReadDir>);
+
+impl Iterator for Interpreters {
+    type Item = Interpreter;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let env = self.0.as_mut()?.next()?.ok()?.path();
+            let exe = if cfg!(windows) {
+                env.join("Scripts").join("python.exe")
+            } else {
+                env.join("bin").join("python")
+            };
+            if exe.is_file() {
+                let name = env.file_name().unwrap().to_string_lossy();
+                return Some(Interpreter::new(name, exe));
+            }
+        }
+    }
+}
+
+pub fn find_interpreters() -> Interpreters {
+    let tox_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(".tox");
+    Interpreters(tox_dir.read_dir().ok())
+}